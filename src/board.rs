@@ -1,24 +1,113 @@
+use crate::config::BoardIdentity;
 use crate::error::{CpdError, Result};
+use crate::mdns::{DiscoveredBoard, Discovery};
+use crate::storage::BoardStorage;
+use crate::web;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::net::IpAddr;
 use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
 use sysinfo::Disks;
 
-#[derive(Debug, Clone)]
+/// Start of the marker files `cpd alias add` writes to boards without a
+/// serial number, followed by the alias
+pub const MARKER_FILE_PREFIX: &str = ".cpd_board_";
+
+#[derive(Debug, Clone, Serialize)]
 pub struct CircuitPythonBoard {
     pub path: PathBuf,
     pub name: String,
     pub volume_label: Option<String>,
     pub total_space: u64,
     pub available_space: u64,
+    /// `Board ID` from `boot_out.txt`, e.g. `adafruit_feather_esp32s3_nopsram`
+    pub board_id: Option<String>,
+    /// USB serial number, from the `UID` line of `boot_out.txt` or the USB device
+    pub serial_number: Option<String>,
+    /// The drive is mounted read-only, usually because `boot.py` gave
+    /// CircuitPython write access with `storage.remount()`
+    pub read_only: bool,
+    /// Set for boards found over mDNS, which are deployed to through the Web Workflow
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub network: Option<NetworkInfo>,
+    /// Name the board is registered under with `cpd alias add`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias: Option<String>,
+}
+
+/// Where a board found on the network can be reached
+#[derive(Debug, Clone, Serialize)]
+pub struct NetworkInfo {
+    pub hostname: String,
+    pub ip: IpAddr,
+    pub port: u16,
+    /// CircuitPython version from `/cp/version.json`
+    pub version: Option<String>,
 }
 
 impl CircuitPythonBoard {
     pub fn new(path: PathBuf, name: String, volume_label: Option<String>, total_space: u64, available_space: u64) -> Self {
         Self {
+            board_id: read_board_id(&path),
+            serial_number: read_serial_number(&path),
+            read_only: is_read_only_mount(&path),
             path,
             name,
             volume_label,
             total_space,
             available_space,
+            network: None,
+            alias: None,
+        }
+    }
+    
+    /// Describe a board reached through `storage` rather than found by detection
+    pub fn from_storage(storage: &dyn BoardStorage, name: String) -> Self {
+        let boot_out = storage
+            .read(Path::new("boot_out.txt"))
+            .map(|content| String::from_utf8_lossy(&content).into_owned())
+            .unwrap_or_default();
+
+        Self {
+            path: storage.root(),
+            name,
+            volume_label: None,
+            total_space: 0,
+            available_space: storage.free_space().unwrap_or_default(),
+            board_id: parse_board_id(&boot_out),
+            serial_number: parse_boot_out_field(&boot_out, "UID:"),
+            read_only: false,
+            network: None,
+            alias: None,
+        }
+    }
+    
+    /// Describe a board that answered mDNS discovery, with what it reports
+    /// at `/cp/version.json` if that could be fetched
+    pub fn from_network(found: DiscoveredBoard, info: Option<web::DeviceInfo>) -> Self {
+        let info = info.unwrap_or(web::DeviceInfo {
+            board_id: None,
+            version: None,
+        });
+
+        Self {
+            path: PathBuf::from(found.url()),
+            name: found.instance,
+            volume_label: None,
+            total_space: 0,
+            available_space: 0,
+            board_id: info.board_id,
+            serial_number: None,
+            read_only: false,
+            network: Some(NetworkInfo {
+                hostname: found.hostname,
+                ip: found.ip,
+                port: found.port,
+                version: info.version,
+            }),
+            alias: None,
         }
     }
     
@@ -36,19 +125,133 @@ impl CircuitPythonBoard {
             format_bytes(self.total_space)
         )
     }
+    
+    /// Whether this is the board `identity` describes
+    pub fn matches(&self, identity: &BoardIdentity) -> bool {
+        match identity {
+            BoardIdentity::Serial { serial } => self
+                .serial_number
+                .as_ref()
+                .is_some_and(|serial_number| serial_number.eq_ignore_ascii_case(serial)),
+            BoardIdentity::Marker { board_id, marker } => {
+                self.board_id.as_ref() == Some(board_id) && self.network.is_none() && self.path.join(marker).is_file()
+            }
+        }
+    }
+    
+    /// An identity to register this board under `name`: its serial number
+    /// if known, or else its Board ID and a marker file written to the drive
+    pub fn register_identity(&self, name: &str) -> Result<BoardIdentity> {
+        if let Some(serial) = &self.serial_number {
+            return Ok(BoardIdentity::Serial { serial: serial.clone() });
+        }
+        
+        let Some(board_id) = &self.board_id else {
+            return Err(CpdError::Configuration {
+                message: format!(
+                    "{} has neither a USB serial number nor a Board ID in boot_out.txt; pass --serial or --board-id and --marker",
+                    self.path.display()
+                ),
+            });
+        };
+        if self.network.is_some() {
+            return Err(CpdError::Configuration {
+                message: "Aliases can only be registered for boards with a drive".to_string(),
+            });
+        }
+        self.ensure_writable()?;
+        
+        let marker = PathBuf::from(format!("{}{}", MARKER_FILE_PREFIX, name));
+        fs::write(self.path.join(&marker), format!("{}\n", name))?;
+        Ok(BoardIdentity::Marker {
+            board_id: board_id.clone(),
+            marker,
+        })
+    }
+    
+    /// Fail with an explanation of the `boot.py` cause if the board can't be written to
+    pub fn ensure_writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(CpdError::ReadOnlyBoard {
+                path: self.path.display().to_string(),
+            });
+        }
+        Ok(())
+    }
 }
 
 pub struct BoardDetector {
     verbose: bool,
+    discovery: Option<Discovery>,
+    aliases: BTreeMap<String, BoardIdentity>,
 }
 
 impl BoardDetector {
     pub fn new(verbose: bool) -> Self {
-        Self { verbose }
+        Self {
+            verbose,
+            discovery: None,
+            aliases: BTreeMap::new(),
+        }
+    }
+    
+    /// Also look for Web Workflow boards with `discovery`; only drives are detected when `None` (the default)
+    pub fn with_discovery(mut self, discovery: Option<Discovery>) -> Self {
+        self.discovery = discovery;
+        self
     }
     
-    /// Detect all available CircuitPython boards
+    /// Recognize boards by these aliases, usually `UserConfig::aliases`
+    pub fn with_aliases(mut self, aliases: BTreeMap<String, BoardIdentity>) -> Self {
+        self.aliases = aliases;
+        self
+    }
+    
+    /// Detect all available CircuitPython boards: drives, then boards on the network
     pub fn detect_boards(&self) -> Result<Vec<CircuitPythonBoard>> {
+        let mut boards = self.detect_drives();
+        boards.extend(self.detect_network_boards());
+        
+        for board in &mut boards {
+            board.alias = self
+                .aliases
+                .iter()
+                .find(|(_, identity)| board.matches(identity))
+                .map(|(name, _)| name.clone());
+        }
+        Ok(boards)
+    }
+    
+    /// The connected board registered as `name`
+    pub fn find_alias(&self, name: &str) -> Result<CircuitPythonBoard> {
+        self.resolve_alias(name, self.detect_drives())
+    }
+    
+    fn resolve_alias(&self, name: &str, boards: Vec<CircuitPythonBoard>) -> Result<CircuitPythonBoard> {
+        let identity = self.aliases.get(name).ok_or_else(|| CpdError::UnknownBoardAlias {
+            name: name.to_string(),
+        })?;
+        
+        let mut matching: Vec<CircuitPythonBoard> = boards.into_iter().filter(|board| board.matches(identity)).collect();
+        match matching.len() {
+            0 => Err(CpdError::AliasedBoardNotFound {
+                name: name.to_string(),
+                identity: identity.to_string(),
+            }),
+            1 => {
+                let mut board = matching.remove(0);
+                if self.verbose {
+                    println!("Board '{}' is at {}", name, board.path.display());
+                }
+                board.alias = Some(name.to_string());
+                Ok(board)
+            }
+            _ => Err(CpdError::MultipleBoardsFound),
+        }
+    }
+    
+    /// Boards that show up as a drive
+    fn detect_drives(&self) -> Vec<CircuitPythonBoard> {
         let mut boards = Vec::new();
         let disks = Disks::new_with_refreshed_list();
         
@@ -77,7 +280,41 @@ impl BoardDetector {
             }
         }
         
-        Ok(boards)
+        boards
+    }
+    
+    /// Boards advertising the Web Workflow over mDNS; a network that can't
+    /// be searched just means there are none
+    fn detect_network_boards(&self) -> Vec<CircuitPythonBoard> {
+        let Some(discovery) = &self.discovery else {
+            return Vec::new();
+        };
+        
+        let found = match discovery.discover() {
+            Ok(found) => found,
+            Err(e) => {
+                if self.verbose {
+                    println!("Could not search the network for boards: {}", e);
+                }
+                return Vec::new();
+            }
+        };
+        
+        found
+            .into_iter()
+            .map(|found| {
+                let info = web::device_info(&found.url());
+                if let Err(e) = &info && self.verbose {
+                    println!("Could not read /cp/version.json from {}: {}", found.hostname, e);
+                }
+                let board = CircuitPythonBoard::from_network(found, info.ok());
+                
+                if self.verbose {
+                    println!("Found CircuitPython board on the network: {}", board.display_name());
+                }
+                board
+            })
+            .collect()
     }
     
     /// Check if a path represents a CircuitPython board
@@ -278,6 +515,28 @@ impl BoardDetector {
         None
     }
     
+    /// Wait for a board to (re)appear at `path`, polling until `timeout` elapses.
+    ///
+    /// Returns true once the path is a CircuitPython board again.
+    pub fn wait_for_board(&self, path: &Path, timeout: Duration) -> bool {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if self.is_circuitpython_board(path) {
+                return true;
+            }
+
+            if Instant::now() >= deadline {
+                if self.verbose {
+                    println!("Board did not reappear at {} within {:?}", path.display(), timeout);
+                }
+                return false;
+            }
+
+            std::thread::sleep(Duration::from_millis(250));
+        }
+    }
+    
     /// Interactive board selection
     pub fn select_board<'a>(&self, boards: &'a [CircuitPythonBoard]) -> Result<&'a CircuitPythonBoard> {
         if boards.is_empty() {
@@ -290,11 +549,15 @@ impl BoardDetector {
         
         println!("Multiple CircuitPython boards detected:");
         for (i, board) in boards.iter().enumerate() {
+            let details = match &board.network {
+                Some(network) => format!("WiFi, {}", network.hostname),
+                None => board.format_space(),
+            };
             println!("  {}: {} at {} ({})", 
                 i + 1, 
                 board.display_name(), 
                 board.path.display(),
-                board.format_space()
+                details
             );
         }
         
@@ -323,22 +586,62 @@ impl BoardDetector {
     pub fn list_boards(&self) -> Result<()> {
         let boards = self.detect_boards()?;
         
+        let disconnected: Vec<String> = self
+            .aliases
+            .iter()
+            .filter(|(name, _)| !boards.iter().any(|board| board.alias.as_ref() == Some(*name)))
+            .map(|(name, identity)| format!("{} ({})", name, identity))
+            .collect();
+        let print_disconnected = || {
+            if !disconnected.is_empty() {
+                println!("Aliases without a connected board:");
+                for alias in &disconnected {
+                    println!("  • {}", alias);
+                }
+            }
+        };
+        
         if boards.is_empty() {
             println!("No CircuitPython boards detected.");
             println!("\nTroubleshooting:");
             println!("  - Ensure your CircuitPython board is connected via USB");
             println!("  - Check that the board appears as a removable drive");
             println!("  - Try pressing the RESET button on your board");
+            println!("  - For boards on WiFi, set CIRCUITPY_WIFI_SSID and CIRCUITPY_WEB_API_PASSWORD in the board's settings.toml");
+            if !disconnected.is_empty() {
+                println!();
+                print_disconnected();
+            }
             return Ok(());
         }
         
         println!("Detected CircuitPython boards:");
         for board in &boards {
             println!("  • {} at {}", board.display_name(), board.path.display());
-            println!("    Space: {}", board.format_space());
+            if let Some(alias) = &board.alias {
+                println!("    Alias: {}", alias);
+            }
+            if let Some(network) = &board.network {
+                println!("    Hostname: {}", network.hostname);
+                println!("    IP: {}", network.ip);
+            } else {
+                println!("    Space: {}", board.format_space());
+            }
+            if let Some(board_id) = &board.board_id {
+                println!("    Board ID: {}", board_id);
+            }
+            if let Some(serial_number) = &board.serial_number {
+                println!("    Serial: {}", serial_number);
+            }
+            if let Some(version) = board.network.as_ref().and_then(|network| network.version.as_ref()) {
+                println!("    Version: CircuitPython {}", version);
+            }
+            if board.read_only {
+                println!("    ⚠️  Read-only: boot.py has probably remounted the drive for CircuitPython");
+            }
             
             // Show some board details
-            if let Ok(entries) = std::fs::read_dir(&board.path) {
+            if board.network.is_none() && let Ok(entries) = std::fs::read_dir(&board.path) {
                 let file_count = entries.count();
                 println!("    Files: {} items", file_count);
             }
@@ -346,10 +649,100 @@ impl BoardDetector {
             println!();
         }
         
+        print_disconnected();
         Ok(())
     }
 }
 
+/// Whether the filesystem holding `path` is mounted read-only.
+///
+/// This checks the mount flags rather than writing a probe file, since any
+/// write to CIRCUITPY makes CircuitPython restart `code.py`.
+pub fn is_read_only_mount(path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+        
+        let Ok(c_path) = CString::new(path.as_os_str().as_bytes()) else {
+            return false;
+        };
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        // SAFETY: c_path is NUL-terminated and stat is a valid, writable statvfs
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+            return false;
+        }
+        (stat.f_flag & libc::ST_RDONLY) != 0
+    }
+    
+    #[cfg(not(unix))]
+    {
+        // The disk with the longest mount point containing `path` holds it
+        let disks = Disks::new_with_refreshed_list();
+        disks
+            .iter()
+            .filter(|disk| path.starts_with(disk.mount_point()))
+            .max_by_key(|disk| disk.mount_point().as_os_str().len())
+            .is_some_and(|disk| disk.is_read_only())
+    }
+}
+
+/// Read the `Board ID` line that CircuitPython writes to `boot_out.txt`
+pub fn read_board_id(board_path: &Path) -> Option<String> {
+    let content = std::fs::read_to_string(board_path.join("boot_out.txt")).ok()?;
+    parse_board_id(&content)
+}
+
+/// The board's USB serial number. CircuitPython 8 and later write it to
+/// `boot_out.txt` as `UID`; for older versions it's looked up from the USB
+/// device where the platform allows.
+pub fn read_serial_number(board_path: &Path) -> Option<String> {
+    std::fs::read_to_string(board_path.join("boot_out.txt"))
+        .ok()
+        .and_then(|content| parse_boot_out_field(&content, "UID:"))
+        .or_else(|| usb_serial_number(board_path))
+}
+
+fn parse_board_id(boot_out: &str) -> Option<String> {
+    parse_boot_out_field(boot_out, "Board ID:")
+}
+
+fn parse_boot_out_field(boot_out: &str, prefix: &str) -> Option<String> {
+    boot_out
+        .lines()
+        .find_map(|line| line.strip_prefix(prefix))
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+/// Find the USB device behind the drive mounted at `mount_point` in sysfs and
+/// read its serial number
+#[cfg(target_os = "linux")]
+fn usb_serial_number(mount_point: &Path) -> Option<String> {
+    let mounts = fs::read_to_string("/proc/mounts").ok()?;
+    let device = mounts.lines().find_map(|line| {
+        let mut parts = line.split_whitespace();
+        let device = parts.next()?;
+        // Spaces in mount points are escaped as \040
+        let mount = parts.next()?.replace("\\040", " ");
+        (Path::new(&mount) == mount_point).then_some(device)
+    })?;
+
+    let mut dir = fs::canonicalize(Path::new("/sys/class/block").join(Path::new(device).file_name()?)).ok()?;
+    while dir.pop() {
+        if dir.join("idVendor").is_file() {
+            let serial = fs::read_to_string(dir.join("serial")).ok()?;
+            return Some(serial.trim().to_string()).filter(|serial| !serial.is_empty());
+        }
+    }
+    None
+}
+
+#[cfg(not(target_os = "linux"))]
+fn usb_serial_number(_mount_point: &Path) -> Option<String> {
+    None
+}
+
 fn format_bytes(bytes: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
     let mut size = bytes as f64;
@@ -388,6 +781,77 @@ mod tests {
         assert!(detector.is_circuitpython_board(temp_dir.path()));
     }
     
+    #[test]
+    fn test_parse_board_id() {
+        let boot_out = "Adafruit CircuitPython 9.0.0 on 2024-03-19; Adafruit Feather ESP32S3 No PSRAM with ESP32S3\n\
+                        Board ID:adafruit_feather_esp32s3_nopsram\n\
+                        UID:C7FD1A2B3C4D\n";
+        
+        assert_eq!(parse_board_id(boot_out).as_deref(), Some("adafruit_feather_esp32s3_nopsram"));
+        assert_eq!(parse_board_id("CircuitPython test"), None);
+        assert_eq!(parse_boot_out_field(boot_out, "UID:").as_deref(), Some("C7FD1A2B3C4D"));
+    }
+    
+    #[test]
+    fn test_resolve_alias() {
+        let serial_dir = TempDir::new().unwrap();
+        fs::write(
+            serial_dir.path().join("boot_out.txt"),
+            "Adafruit CircuitPython 9.0.0\nBoard ID:adafruit_qtpy_rp2040\nUID:C7FD1A2B3C4D\n",
+        )
+        .unwrap();
+        let marker_dir = TempDir::new().unwrap();
+        fs::write(marker_dir.path().join("boot_out.txt"), "Adafruit CircuitPython 7.3.3\nBoard ID:adafruit_qtpy_rp2040\n").unwrap();
+        let boards = || {
+            vec![
+                CircuitPythonBoard::new(serial_dir.path().to_path_buf(), "sdb1".to_string(), None, 0, 0),
+                CircuitPythonBoard::new(marker_dir.path().to_path_buf(), "sdc1".to_string(), None, 0, 0),
+            ]
+        };
+        
+        let serial_identity = boards()[0].register_identity("kitchen-sensor").unwrap();
+        assert_eq!(serial_identity, BoardIdentity::Serial { serial: "C7FD1A2B3C4D".to_string() });
+        let marker_identity = boards()[1].register_identity("lab-3").unwrap();
+        assert!(marker_dir.path().join(".cpd_board_lab-3").is_file());
+        
+        let detector = BoardDetector::new(false).with_aliases(BTreeMap::from([
+            ("kitchen-sensor".to_string(), serial_identity),
+            ("lab-3".to_string(), marker_identity.clone()),
+            (
+                "greenhouse".to_string(),
+                BoardIdentity::Serial { serial: "0000".to_string() },
+            ),
+        ]));
+        
+        let board = detector.resolve_alias("kitchen-sensor", boards()).unwrap();
+        assert_eq!(board.path, serial_dir.path());
+        assert_eq!(board.alias.as_deref(), Some("kitchen-sensor"));
+        assert_eq!(detector.resolve_alias("lab-3", boards()).unwrap().path, marker_dir.path());
+        
+        let error = detector.resolve_alias("greenhouse", boards()).unwrap_err();
+        assert!(matches!(error, CpdError::AliasedBoardNotFound { .. }));
+        let error = detector.resolve_alias("attic", boards()).unwrap_err();
+        assert!(matches!(error, CpdError::UnknownBoardAlias { .. }));
+        
+        // The marker tells apart boards with the same Board ID
+        fs::remove_file(marker_dir.path().join(".cpd_board_lab-3")).unwrap();
+        assert!(!boards()[1].matches(&marker_identity));
+    }
+    
+    #[test]
+    fn test_read_only_board() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut board = CircuitPythonBoard::new(temp_dir.path().to_path_buf(), "Test".to_string(), None, 0, 0);
+        
+        assert!(!board.read_only);
+        assert!(board.ensure_writable().is_ok());
+        
+        board.read_only = true;
+        let error = board.ensure_writable().unwrap_err();
+        assert!(matches!(error, CpdError::ReadOnlyBoard { .. }));
+        assert!(error.to_string().contains("boot.py"));
+    }
+    
     #[test]
     fn test_format_bytes() {
         assert_eq!(format_bytes(0), "0 B");
@@ -1,4 +1,7 @@
-use clap::Parser;
+use crate::deploy::BoardSelector;
+use crate::output::OutputFormat;
+use crate::version_file;
+use clap::{Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
@@ -19,39 +22,156 @@ Examples:
   cpd --list-boards            Show all detected CircuitPython boards
   cpd --dry-run                Preview deployment without copying files
   cpd --backup ./backup        Create backup before deployment
-  cpd --board /media/CIRCUITPY  Deploy to specific board path")]
+  cpd --board /media/CIRCUITPY  Deploy to specific board path
+  cpd --board serial:/dev/ttyUSB0  Deploy over the serial raw REPL
+  cpd --board http://cpy-1a2b3c.local  Deploy over WiFi with the Web Workflow
+  cpd alias add kitchen-sensor  Name the connected board, then deploy with --board kitchen-sensor
+  cpd deploy --resume          Finish an interrupted deployment
+  cpd ignore explain lib/x.py  Show which rule includes or excludes a path")]
 pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Path to the project directory to deploy (defaults to current directory)
     #[arg(value_name = "PROJECT_DIR")]
     pub project_dir: Option<PathBuf>,
 
-    /// Specify the board drive/mount point manually (e.g., E:\, /media/CIRCUITPY)
-    #[arg(short = 'b', long = "board", value_name = "BOARD_PATH")]
+    /// Specify the board drive/mount point manually (e.g., E:\, /media/CIRCUITPY), serial:PORT for boards without a drive, http://HOST for the Web Workflow, or an alias from `cpd alias add`
+    #[arg(short = 'b', long = "board", global = true, value_name = "BOARD_PATH")]
     pub board_path: Option<PathBuf>,
 
     /// Backup existing board files before deployment
-    #[arg(short = 'B', long = "backup", value_name = "BACKUP_DIR")]
+    #[arg(short = 'B', long = "backup", global = true, value_name = "BACKUP_DIR")]
     pub backup_dir: Option<PathBuf>,
 
     /// Preview deployment without copying files (safe mode)
-    #[arg(short = 'n', long = "dry-run")]
+    #[arg(short = 'n', long = "dry-run", global = true)]
     pub dry_run: bool,
 
     /// Show detailed information during deployment
-    #[arg(short = 'v', long = "verbose")]
+    #[arg(short = 'v', long = "verbose", global = true)]
     pub verbose: bool,
 
-    /// Force deployment even if board validation fails
-    #[arg(short = 'f', long = "force")]
+    /// Force deployment even if board validation fails, and overwrite protected board files
+    #[arg(short = 'f', long = "force", global = true)]
     pub force: bool,
 
+    /// Overwrite and delete protected board files, but still validate the board
+    #[arg(long = "overwrite-protected", global = true)]
+    pub overwrite_protected: bool,
+
     /// Skip interactive confirmation prompts
-    #[arg(short = 'y', long = "yes")]
+    #[arg(short = 'y', long = "yes", global = true)]
     pub assume_yes: bool,
 
-    /// List all detected CircuitPython boards and exit
-    #[arg(short = 'l', long = "list-boards")]
+    /// List CircuitPython boards on USB and on the local network, and exit
+    #[arg(short = 'l', long = "list-boards", global = true)]
     pub list_boards: bool,
+
+    /// Also look for Web Workflow boards on the local network when no --board is given
+    #[arg(long = "network", global = true)]
+    pub network: bool,
+
+    /// Read every written file back from the board and compare hashes with the source
+    #[arg(long = "verify", global = true)]
+    pub verify: bool,
+
+    /// Number of times to rewrite a file that fails verification
+    #[arg(long = "verify-retries", global = true, value_name = "N", default_value_t = 2, requires = "verify")]
+    pub verify_retries: u32,
+
+    /// Number of times to retry a write that fails with a transient error (0 disables)
+    #[arg(long = "retries", global = true, value_name = "N", default_value_t = 3)]
+    pub retries: u32,
+
+    /// Don't apply patterns from .gitignore files
+    #[arg(long = "no-gitignore", global = true)]
+    pub no_gitignore: bool,
+
+    /// Don't apply the built-in default ignore patterns
+    #[arg(long = "no-default-ignores", global = true)]
+    pub no_default_ignores: bool,
+
+    /// Deploy profile from cpd.toml to use (default: chosen by the board's ID)
+    #[arg(long = "profile", global = true, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Output format; `json` prints one JSON event per line and never prompts
+    #[arg(long = "output", global = true, value_name = "FORMAT", value_enum, default_value_t = OutputFormat::Human)]
+    pub output: OutputFormat,
+
+    /// Write git version info to this board path (.py or .json), e.g. lib/_cpd_version.py
+    #[arg(long = "version-file", global = true, value_name = "PATH")]
+    pub version_file: Option<PathBuf>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// Deploy the project to a board (the default when no command is given)
+    Deploy {
+        /// Path to the project directory to deploy (defaults to current directory)
+        #[arg(value_name = "PROJECT_DIR")]
+        project_dir: Option<PathBuf>,
+
+        /// Finish an interrupted deployment, copying only the files that are still missing
+        #[arg(long = "resume")]
+        resume: bool,
+    },
+
+    /// Inspect the ignore rules applied to the project
+    Ignore {
+        #[command(subcommand)]
+        action: IgnoreCommand,
+    },
+
+    /// Name boards so --board NAME finds them wherever they are mounted
+    Alias {
+        #[command(subcommand)]
+        action: AliasCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum IgnoreCommand {
+    /// Print the effective ignore rules and where each one comes from
+    List,
+
+    /// Show which file, line and pattern includes or excludes a path
+    Explain {
+        /// Path to check, relative to the project directory
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum AliasCommand {
+    /// Register NAME for the board given with --board, or for a connected board
+    Add {
+        #[arg(value_name = "NAME")]
+        name: String,
+
+        /// Identify the board by this USB serial number instead of reading it from the board
+        #[arg(long = "serial", conflicts_with_all = ["board_id", "marker"])]
+        serial: Option<String>,
+
+        /// Identify the board by this Board ID together with --marker
+        #[arg(long = "board-id", requires = "marker")]
+        board_id: Option<String>,
+
+        /// File on the board's drive that marks this particular board, used with --board-id
+        #[arg(long = "marker", requires = "board_id")]
+        marker: Option<PathBuf>,
+    },
+
+    /// Forget a registered alias
+    Remove {
+        #[arg(value_name = "NAME")]
+        name: String,
+    },
+
+    /// Print the registered aliases and the boards they stand for
+    List,
 }
 
 impl Cli {
@@ -60,11 +180,27 @@ impl Cli {
     }
 
     pub fn project_dir(&self) -> PathBuf {
-        self.project_dir
-            .clone()
+        let subcommand_dir = match &self.command {
+            Some(Command::Deploy { project_dir, .. }) => project_dir.as_ref(),
+            Some(Command::Ignore { .. }) | Some(Command::Alias { .. }) | None => None,
+        };
+
+        subcommand_dir
+            .or(self.project_dir.as_ref())
+            .cloned()
             .unwrap_or_else(|| std::env::current_dir().unwrap_or_else(|_| PathBuf::from(".")))
     }
 
+    /// Whether to resume an interrupted deployment
+    pub fn resume(&self) -> bool {
+        matches!(self.command, Some(Command::Deploy { resume: true, .. }))
+    }
+
+    /// Whether output is meant for a person rather than a script
+    pub fn is_human(&self) -> bool {
+        self.output == OutputFormat::Human
+    }
+
     pub fn validate(&self) -> crate::error::Result<()> {
         use crate::error::CpdError;
 
@@ -82,8 +218,11 @@ impl Cli {
             });
         }
 
-        // Validate board path if specified
-        if let Some(board_path) = &self.board_path {
+        // Validate board path if specified; serial ports, network boards and aliases are checked when connecting
+        if let Some(board_path) = &self.board_path
+            && !BoardSelector::is_remote(board_path)
+            && !BoardSelector::is_alias(board_path)
+        {
             if !board_path.exists() {
                 return Err(CpdError::InvalidBoardPath {
                     path: board_path.display().to_string(),
@@ -98,12 +237,32 @@ impl Cli {
         }
 
         // Validate backup directory if specified
-        if let Some(backup_dir) = &self.backup_dir {
-            if backup_dir.exists() && !backup_dir.is_dir() {
-                return Err(CpdError::Configuration {
-                    message: format!("Backup path exists but is not a directory: {}", backup_dir.display()),
-                });
-            }
+        if let Some(backup_dir) = &self.backup_dir
+            && backup_dir.exists()
+            && !backup_dir.is_dir()
+        {
+            return Err(CpdError::Configuration {
+                message: format!("Backup path exists but is not a directory: {}", backup_dir.display()),
+            });
+        }
+
+        if self.resume() && self.dry_run {
+            return Err(CpdError::Configuration {
+                message: "--resume can't be combined with --dry-run".to_string(),
+            });
+        }
+
+        // JSON output is for scripts, which can't answer the confirmation prompt
+        let deploys = !self.list_boards && !matches!(self.command, Some(Command::Ignore { .. }) | Some(Command::Alias { .. }));
+        if !self.is_human() && deploys && !self.assume_yes && !self.dry_run {
+            return Err(CpdError::Configuration {
+                message: "--output json can't prompt for confirmation; pass --yes or --dry-run".to_string(),
+            });
+        }
+
+        // Validate version file location on the board
+        if let Some(version_file) = &self.version_file {
+            version_file::validate_path(version_file)?;
         }
 
         Ok(())
@@ -117,16 +276,7 @@ mod tests {
 
     #[test]
     fn test_default_project_dir() {
-        let cli = Cli {
-            project_dir: None,
-            board_path: None,
-            backup_dir: None,
-            dry_run: false,
-            verbose: false,
-            force: false,
-            assume_yes: false,
-            list_boards: false,
-        };
+        let cli = Cli::parse_from(["cpd"]);
 
         let current_dir = env::current_dir().unwrap();
         assert_eq!(cli.project_dir(), current_dir);
@@ -135,17 +285,60 @@ mod tests {
     #[test]
     fn test_explicit_project_dir() {
         let test_path = PathBuf::from("/test/path");
-        let cli = Cli {
-            project_dir: Some(test_path.clone()),
-            board_path: None,
-            backup_dir: None,
-            dry_run: false,
-            verbose: false,
-            force: false,
-            assume_yes: false,
-            list_boards: false,
-        };
+        let cli = Cli::parse_from(["cpd", "/test/path"]);
 
         assert_eq!(cli.project_dir(), test_path);
     }
+
+    #[test]
+    fn test_deploy_subcommand() {
+        let cli = Cli::parse_from(["cpd", "deploy", "--resume", "--yes", "/test/path"]);
+
+        assert!(cli.resume());
+        assert!(cli.assume_yes);
+        assert_eq!(cli.project_dir(), PathBuf::from("/test/path"));
+        assert!(!Cli::parse_from(["cpd", "deploy"]).resume());
+    }
+
+    #[test]
+    fn test_ignore_subcommand() {
+        let cli = Cli::parse_from(["cpd", "ignore", "explain", "lib/helper.py"]);
+
+        match cli.command {
+            Some(Command::Ignore { action: IgnoreCommand::Explain { path } }) => {
+                assert_eq!(path, PathBuf::from("lib/helper.py"));
+            }
+            other => panic!("unexpected command: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_output_format() {
+        assert!(Cli::parse_from(["cpd"]).is_human());
+
+        let cli = Cli::parse_from(["cpd", "ignore", "list", "--output", "json"]);
+        assert_eq!(cli.output, OutputFormat::Json);
+        assert!(!cli.is_human());
+        assert!(cli.validate().is_ok());
+        assert!(Cli::parse_from(["cpd", "alias", "list", "--output", "json"]).validate().is_ok());
+
+        // Deploying can't prompt, so it must be confirmed up front
+        assert!(Cli::parse_from(["cpd", "--output", "json"]).validate().is_err());
+        assert!(Cli::parse_from(["cpd", "--output", "json", "--dry-run"]).validate().is_ok());
+    }
+
+    #[test]
+    fn test_version_file_validation() {
+        let cli = Cli::parse_from(["cpd", "--version-file", "lib/_cpd_version.py"]);
+        assert!(cli.validate().is_ok());
+
+        let cli = Cli::parse_from(["cpd", "--version-file", "version.txt"]);
+        assert!(cli.validate().is_err());
+
+        // The file must stay inside the board
+        let cli = Cli::parse_from(["cpd", "--version-file", "../version.py"]);
+        assert!(cli.validate().is_err());
+        let cli = Cli::parse_from(["cpd", "--version-file", "lib/../../version.py"]);
+        assert!(cli.validate().is_err());
+    }
 }
@@ -0,0 +1,308 @@
+use crate::error::{CpdError, Result};
+use crate::ignore::IgnoreProfile;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the per-project configuration file
+pub const CONFIG_FILE_NAME: &str = "cpd.toml";
+
+/// Name of the user-wide configuration file in `~/.config/cpd/`
+pub const USER_CONFIG_FILE_NAME: &str = "config.toml";
+
+/// Project configuration read from `cpd.toml` in the project root.
+///
+/// Every setting is optional; a project without a config file gets the
+/// same behaviour as an empty one.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProjectConfig {
+    pub ignore: IgnoreConfig,
+    pub protect: ProtectConfig,
+    pub layout: LayoutConfig,
+    /// Named deploy profiles for different target boards
+    pub profiles: BTreeMap<String, ProfileConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct IgnoreConfig {
+    /// Apply patterns from `.gitignore` files
+    pub gitignore: bool,
+    /// Apply cpd's built-in default ignore patterns
+    pub defaults: bool,
+    /// Which set of built-in patterns to use
+    pub profile: IgnoreProfile,
+}
+
+impl Default for IgnoreConfig {
+    fn default() -> Self {
+        Self {
+            gitignore: true,
+            defaults: true,
+            profile: IgnoreProfile::default(),
+        }
+    }
+}
+
+/// Board paths that deployments must not overwrite or delete
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProtectConfig {
+    /// Protect `settings.toml`, `boot_out.txt` and `sd/`
+    pub defaults: bool,
+    /// Additional board-relative patterns in `.gitignore` syntax
+    pub patterns: Vec<String>,
+}
+
+impl Default for ProtectConfig {
+    fn default() -> Self {
+        Self {
+            defaults: true,
+            patterns: Vec::new(),
+        }
+    }
+}
+
+/// How project directories map onto the board
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LayoutConfig {
+    /// Project directory whose contents go to the board root, e.g. `src/`
+    pub source_root: Option<PathBuf>,
+    /// Project directories installed elsewhere on the board, e.g. `"assets/fonts" = "/fonts"`
+    pub map: BTreeMap<PathBuf, PathBuf>,
+}
+
+/// Settings for deploying the project to one kind of board
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileConfig {
+    /// Board IDs from `boot_out.txt` that select this profile automatically
+    pub board_ids: Vec<String>,
+    /// Project file installed on the board as `code.py`
+    pub entry: Option<PathBuf>,
+    /// Patterns deployed even if ignore files exclude them
+    pub include: Vec<String>,
+    /// Additional patterns to leave out
+    pub exclude: Vec<String>,
+    /// Project files installed under another name, e.g. `config.feather.py` -> `config.py`
+    pub files: BTreeMap<PathBuf, PathBuf>,
+}
+
+impl ProjectConfig {
+    /// Path of the config file for a project
+    pub fn path_for(project_dir: &Path) -> PathBuf {
+        project_dir.join(CONFIG_FILE_NAME)
+    }
+
+    /// Load the project config, falling back to defaults if there is no config file
+    pub fn load(project_dir: &Path) -> Result<Self> {
+        let path = Self::path_for(project_dir);
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)?;
+        Self::parse(&content).map_err(|e| CpdError::Configuration {
+            message: format!("{}: {}", path.display(), e),
+        })
+    }
+
+    fn parse(content: &str) -> std::result::Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+}
+
+/// Settings shared by every project, read from `~/.config/cpd/config.toml`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserConfig {
+    /// Names for particular boards, usable as `--board NAME`
+    pub aliases: BTreeMap<String, BoardIdentity>,
+}
+
+/// What tells a board apart wherever it happens to be mounted
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum BoardIdentity {
+    /// The USB serial number, which CircuitPython also writes to
+    /// `boot_out.txt` as `UID`
+    Serial { serial: String },
+    /// For boards without a known serial number: the `Board ID` from
+    /// `boot_out.txt` plus a file on the drive marking this particular board
+    Marker { board_id: String, marker: PathBuf },
+}
+
+impl fmt::Display for BoardIdentity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BoardIdentity::Serial { serial } => write!(f, "USB serial {}", serial),
+            BoardIdentity::Marker { board_id, marker } => write!(f, "{} with {}", board_id, marker.display()),
+        }
+    }
+}
+
+impl UserConfig {
+    /// Default location of the user config, e.g. `~/.config/cpd/config.toml`
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("cpd").join(USER_CONFIG_FILE_NAME))
+    }
+
+    /// Load the user config from its default location
+    pub fn load() -> Result<Self> {
+        match Self::default_path() {
+            Some(path) => Self::load_from(&path),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Load the user config at `path`, falling back to defaults if there is no file
+    pub fn load_from(path: &Path) -> Result<Self> {
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| CpdError::Configuration {
+            message: format!("{}: {}", path.display(), e),
+        })
+    }
+
+    pub fn save_to(&self, path: &Path) -> Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let content = toml::to_string(self).map_err(|e| CpdError::Configuration {
+            message: format!("{}: {}", path.display(), e),
+        })?;
+        fs::write(path, content)?;
+        Ok(())
+    }
+}
+
+/// Whether `name` can be used as a board alias: letters, digits, `-` and `_`,
+/// so it can't be mistaken for a path or a `serial:`/`http://` location
+pub fn is_valid_alias(name: &str) -> bool {
+    !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_missing_config_uses_defaults() {
+        let temp_dir = TempDir::new().unwrap();
+        let config = ProjectConfig::load(temp_dir.path()).unwrap();
+
+        assert!(config.ignore.gitignore);
+        assert!(config.ignore.defaults);
+    }
+
+    #[test]
+    fn test_ignore_switches() {
+        let config = ProjectConfig::parse("[ignore]\ngitignore = false\n").unwrap();
+
+        assert!(!config.ignore.gitignore);
+        assert!(config.ignore.defaults);
+    }
+
+    #[test]
+    fn test_ignore_profile() {
+        let config = ProjectConfig::parse("[ignore]\nprofile = \"circuitpython\"\n").unwrap();
+        assert_eq!(config.ignore.profile, IgnoreProfile::CircuitPython);
+
+        assert_eq!(ProjectConfig::default().ignore.profile, IgnoreProfile::Default);
+        assert!(ProjectConfig::parse("[ignore]\nprofile = \"tiny\"\n").is_err());
+    }
+
+    #[test]
+    fn test_protect_patterns() {
+        let config = ProjectConfig::parse("[protect]\npatterns = [\"logs/\", \"!settings.toml\"]\n").unwrap();
+
+        assert!(config.protect.defaults);
+        assert_eq!(config.protect.patterns, vec!["logs/", "!settings.toml"]);
+    }
+
+    #[test]
+    fn test_profiles() {
+        let config = ProjectConfig::parse(
+            "[profiles.feather]\n\
+             board_ids = [\"adafruit_feather_esp32s3_nopsram\"]\n\
+             entry = \"code_feather.py\"\n\
+             exclude = [\"lib/qtpy/\"]\n\
+             files = { \"config.feather.py\" = \"config.py\" }\n",
+        )
+        .unwrap();
+
+        let feather = &config.profiles["feather"];
+        assert_eq!(feather.entry, Some(PathBuf::from("code_feather.py")));
+        assert_eq!(feather.files[Path::new("config.feather.py")], PathBuf::from("config.py"));
+        assert!(feather.include.is_empty());
+    }
+
+    #[test]
+    fn test_layout() {
+        let config = ProjectConfig::parse(
+            "[layout]\nsource_root = \"src/\"\nmap = { \"assets/fonts\" = \"/fonts\" }\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.layout.source_root, Some(PathBuf::from("src/")));
+        assert_eq!(config.layout.map[Path::new("assets/fonts")], PathBuf::from("/fonts"));
+    }
+
+    #[test]
+    fn test_unknown_keys_are_rejected() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(ProjectConfig::path_for(temp_dir.path()), "[ignore]\ngitingore = false\n").unwrap();
+
+        assert!(ProjectConfig::load(temp_dir.path()).is_err());
+    }
+
+    #[test]
+    fn test_user_config_aliases() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("cpd").join(USER_CONFIG_FILE_NAME);
+        assert!(UserConfig::load_from(&path).unwrap().aliases.is_empty());
+
+        let mut config = UserConfig::default();
+        config.aliases.insert(
+            "kitchen-sensor".to_string(),
+            BoardIdentity::Serial {
+                serial: "C7FD1A2B3C4D".to_string(),
+            },
+        );
+        config.aliases.insert(
+            "lab-3".to_string(),
+            BoardIdentity::Marker {
+                board_id: "adafruit_qtpy_rp2040".to_string(),
+                marker: PathBuf::from(".cpd_board_lab-3"),
+            },
+        );
+        config.save_to(&path).unwrap();
+
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(content.contains("[aliases.kitchen-sensor]\nserial = \"C7FD1A2B3C4D\""));
+        let loaded = UserConfig::load_from(&path).unwrap();
+        assert_eq!(loaded.aliases, config.aliases);
+        assert_eq!(loaded.aliases["lab-3"].to_string(), "adafruit_qtpy_rp2040 with .cpd_board_lab-3");
+
+        fs::write(&path, "[aliases.broken]\nboard_id = \"adafruit_qtpy_rp2040\"\n").unwrap();
+        assert!(UserConfig::load_from(&path).is_err());
+    }
+
+    #[test]
+    fn test_valid_alias() {
+        assert!(is_valid_alias("kitchen-sensor"));
+        assert!(is_valid_alias("lab_3"));
+        assert!(!is_valid_alias(""));
+        assert!(!is_valid_alias("CIRCUITPY/lib"));
+        assert!(!is_valid_alias("serial:COM3"));
+    }
+}
@@ -0,0 +1,561 @@
+use crate::board::{BoardDetector, CircuitPythonBoard};
+use crate::config::{is_valid_alias, ProjectConfig, UserConfig};
+use crate::error::{CpdError, Result};
+use crate::file_ops::{CopyResult, FileOperations, RetryPolicy};
+use crate::git;
+use crate::ignore::{IgnoreFilter, IgnoreOptions};
+use crate::journal::DeployJournal;
+use crate::layout::PathMapper;
+use crate::mdns::Discovery;
+use crate::manifest::DeployManifest;
+use crate::profile::DeployProfile;
+use crate::progress::{DeployObserver, SilentObserver};
+use crate::protect::ProtectedPaths;
+use crate::serial::{SerialBoard, SERIAL_PREFIX};
+use crate::storage::{BoardStorage, LocalBoard};
+use crate::template::Secrets;
+use crate::version_file::{self, VersionInfo};
+use crate::web::{web_api_password, WebBoard, HTTP_PREFIX};
+use serde::Serialize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Which board to deploy to
+#[derive(Debug, Clone, Default)]
+pub enum BoardSelector {
+    /// Detect connected boards. With several, the `select_board` hook picks
+    /// one; without a hook that is an error.
+    #[default]
+    Auto,
+    /// The board mounted at this path
+    Path(PathBuf),
+    /// A board reached through this storage backend instead of a mount
+    Storage(Arc<dyn BoardStorage + Send + Sync>),
+    /// The connected board registered under this alias in the user config
+    Alias(String),
+}
+
+impl BoardSelector {
+    /// Parse a `--board` value: `serial:PORT` connects to a board through the
+    /// raw REPL on that serial port, an `http://` URL through the Web
+    /// Workflow, a plain name that isn't an existing path is an alias, and
+    /// anything else is a mount path. The Web Workflow password is looked up
+    /// for `project_dir`.
+    pub fn from_location(location: &Path, project_dir: &Path) -> Result<Self> {
+        if Self::is_alias(location) {
+            return Ok(Self::Alias(location.to_string_lossy().into_owned()));
+        }
+        if !Self::is_remote(location) {
+            return Ok(Self::Path(location.to_path_buf()));
+        }
+
+        let location = location.to_string_lossy();
+        let storage: Arc<dyn BoardStorage + Send + Sync> = match location.strip_prefix(SERIAL_PREFIX) {
+            Some(port_name) => Arc::new(SerialBoard::open(port_name)?),
+            None => Arc::new(WebBoard::connect(&location, web_api_password(project_dir)?.as_deref())?),
+        };
+        Ok(Self::Storage(storage))
+    }
+
+    /// Whether a `--board` value names a serial port or network board rather than a mount path
+    pub fn is_remote(location: &Path) -> bool {
+        let location = location.to_string_lossy();
+        location.starts_with(SERIAL_PREFIX) || location.starts_with(HTTP_PREFIX)
+    }
+
+    /// Whether a `--board` value is an alias rather than a mount path; a
+    /// directory of the same name takes precedence
+    pub fn is_alias(location: &Path) -> bool {
+        !location.exists() && location.to_str().is_some_and(is_valid_alias)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DeployMode {
+    /// Copy the project to the board
+    #[default]
+    Deploy,
+    /// Report what would be copied without writing anything
+    DryRun,
+    /// Finish an interrupted deployment, copying only the files still missing
+    Resume,
+}
+
+/// What a deployment is about to do, passed to the `confirm` hook before
+/// anything is written to the board
+#[derive(Debug, Serialize)]
+pub struct DeployPlan {
+    #[serde(rename = "source")]
+    pub project_dir: PathBuf,
+    pub board: CircuitPythonBoard,
+    /// Name of the deploy profile from `cpd.toml` in use, if any
+    pub profile: Option<String>,
+    pub dry_run: bool,
+    pub files: Vec<PlannedFile>,
+    /// Protect patterns in effect on the board
+    pub protected: Vec<String>,
+    /// Set when resuming an interrupted deployment
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resume: Option<ResumeInfo>,
+}
+
+/// A project file and where it will be written on the board
+#[derive(Debug, Serialize)]
+pub struct PlannedFile {
+    pub path: PathBuf,
+    pub target: PathBuf,
+}
+
+/// Progress of the interrupted deployment being resumed
+#[derive(Debug, Serialize)]
+pub struct ResumeInfo {
+    pub started_at: String,
+    /// Files of the original plan already on the board
+    pub completed: usize,
+    /// Files in the original plan
+    pub planned: usize,
+}
+
+/// Outcome of a deployment that ran to the end.
+///
+/// Files that failed are listed in `result` rather than turning the whole
+/// deployment into an error; check `is_success()`.
+#[derive(Debug, Serialize)]
+pub struct DeployReport {
+    pub board: CircuitPythonBoard,
+    pub profile: Option<String>,
+    pub dry_run: bool,
+    #[serde(flatten)]
+    pub result: CopyResult,
+    /// Where the board was backed up to, if a backup was made
+    pub backup_dir: Option<PathBuf>,
+    /// Board path of the generated version file, if one was requested
+    pub version_file: Option<PathBuf>,
+    /// Whether the deployment manifest on the board was updated
+    pub manifest_written: bool,
+}
+
+impl DeployReport {
+    pub fn is_success(&self) -> bool {
+        self.result.is_success()
+    }
+}
+
+type SelectBoardHook = Box<dyn Fn(&[CircuitPythonBoard]) -> Result<usize> + Send + Sync>;
+type ConfirmHook = Box<dyn Fn(&DeployPlan) -> bool + Send + Sync>;
+type PathFilter = Box<dyn Fn(&Path) -> bool + Send + Sync>;
+
+/// Deploys a project to a board: picks the board and deploy profile, plans
+/// the files, asks for confirmation, backs up, copies, and records the
+/// manifest. This is what the `cpd` binary runs; build one with
+/// [`Deployer::builder`].
+pub struct Deployer {
+    project_dir: PathBuf,
+    board: BoardSelector,
+    mode: DeployMode,
+    profile: Option<String>,
+    use_gitignore: bool,
+    use_default_ignores: bool,
+    filter: Option<PathFilter>,
+    backup_dir: Option<PathBuf>,
+    force: bool,
+    overwrite_protected: bool,
+    network: bool,
+    verify_retries: Option<u32>,
+    retry_policy: RetryPolicy,
+    version_file: Option<PathBuf>,
+    verbose: bool,
+    select_board: Option<SelectBoardHook>,
+    confirm: Option<ConfirmHook>,
+    observer: Arc<dyn DeployObserver + Send + Sync>,
+}
+
+/// Builder for [`Deployer`]
+pub struct DeployerBuilder {
+    deployer: Deployer,
+}
+
+impl Deployer {
+    pub fn builder(project_dir: impl Into<PathBuf>) -> DeployerBuilder {
+        DeployerBuilder {
+            deployer: Deployer {
+                project_dir: project_dir.into(),
+                board: BoardSelector::Auto,
+                mode: DeployMode::Deploy,
+                profile: None,
+                use_gitignore: true,
+                use_default_ignores: true,
+                filter: None,
+                backup_dir: None,
+                force: false,
+                overwrite_protected: false,
+                network: false,
+                verify_retries: None,
+                retry_policy: RetryPolicy::default(),
+                version_file: None,
+                verbose: false,
+                select_board: None,
+                confirm: None,
+                observer: Arc::new(SilentObserver),
+            },
+        }
+    }
+
+    /// Run the deployment
+    pub fn run(&self) -> Result<DeployReport> {
+        let project_dir = &self.project_dir;
+        if !project_dir.is_dir() {
+            return Err(CpdError::Configuration {
+                message: format!("Project directory does not exist: {}", project_dir.display()),
+            });
+        }
+        if let Some(version_file) = &self.version_file {
+            version_file::validate_path(version_file)?;
+        }
+
+        let dry_run = self.mode == DeployMode::DryRun;
+        let config = ProjectConfig::load(project_dir)?;
+        let (board, storage) = self.select_board()?;
+        let storage = storage.as_ref();
+
+        // Every write would fail one by one on a read-only drive, so stop before starting
+        if !dry_run {
+            board.ensure_writable()?;
+        }
+
+        // Tailor the project to the board if a profile applies
+        let profile = DeployProfile::select(&config, self.profile.as_deref(), board.board_id.as_deref())?;
+        let ignore_filter = project_ignore_filter(
+            project_dir,
+            &config,
+            profile.as_ref(),
+            self.use_gitignore,
+            self.use_default_ignores,
+        )?;
+        let layout = PathMapper::new(&config.layout)?;
+
+        // Board files owned by the device or the user, unless forced
+        let protected = if self.force || self.overwrite_protected {
+            ProtectedPaths::none()
+        } else {
+            ProtectedPaths::new(&config.protect.patterns, config.protect.defaults)?
+        };
+        let protected_patterns = protected.patterns().to_vec();
+
+        let mut file_ops = FileOperations::new()
+            .with_retry_policy(self.retry_policy.clone())
+            .with_protected_paths(protected)
+            .with_templates(Secrets::load(project_dir)?)
+            .with_layout(layout.clone())
+            .with_renames(profile.as_ref().map(DeployProfile::renames).unwrap_or_default())
+            .with_observer(Arc::clone(&self.observer));
+        if let Some(retries) = self.verify_retries {
+            file_ops = file_ops.with_verification(retries);
+        }
+
+        // Build the deploy plan, or pick up the remainder of an interrupted one
+        let mut journal = None;
+        let mut resumed_files = Vec::new();
+        let mut resume = None;
+        let files_to_copy = if self.mode == DeployMode::Resume {
+            let Some(mut existing) = DeployJournal::load(project_dir)? else {
+                return Err(CpdError::Configuration {
+                    message: "No interrupted deployment to resume".to_string(),
+                });
+            };
+
+            if existing.board() != board.path {
+                return Err(CpdError::Configuration {
+                    message: format!(
+                        "The interrupted deployment targeted {}. Use --board {} to resume it.",
+                        existing.board().display(),
+                        existing.board().display()
+                    ),
+                });
+            }
+
+            resumed_files = existing.verify_completed(storage)?;
+            let remaining = existing.remaining_files(project_dir);
+            resume = Some(ResumeInfo {
+                started_at: existing.started_at().to_string(),
+                completed: resumed_files.len(),
+                planned: existing.planned_files().len(),
+            });
+
+            journal = Some(existing);
+            remaining
+        } else {
+            let filter_fn = ignore_filter.filter_fn();
+            let filter = |path: &Path| filter_fn(path) && self.filter.as_ref().is_none_or(|filter| filter(path));
+            let mut files = file_ops.collect_files(project_dir, &filter);
+            ignore_filter.check_nested()?;
+            files.retain(|file| layout.covers(file.strip_prefix(project_dir).unwrap_or(file)));
+            if let Some(profile) = &profile {
+                files = profile.plan_files(project_dir, files, &layout)?;
+            }
+            files
+        };
+
+        let plan = DeployPlan {
+            project_dir: project_dir.clone(),
+            board,
+            profile: profile.map(|profile| profile.name),
+            dry_run,
+            files: files_to_copy
+                .iter()
+                .map(|file| {
+                    let path = file.strip_prefix(project_dir).unwrap_or(file).to_path_buf();
+                    let target = file_ops.target_path(&path);
+                    PlannedFile { path, target }
+                })
+                .collect(),
+            protected: protected_patterns,
+            resume,
+        };
+
+        if let Some(confirm) = &self.confirm
+            && !confirm(&plan)
+        {
+            return Err(CpdError::Cancelled);
+        }
+
+        let DeployPlan { board, profile, .. } = plan;
+
+        // Create backup if requested
+        if let Some(backup_dir) = &self.backup_dir {
+            FileOperations::new()
+                .with_observer(Arc::clone(&self.observer))
+                .backup_board(storage, backup_dir)?;
+        }
+
+        let previous_manifest = DeployManifest::load(storage, &|message| self.observer.warning(message))?;
+        if !dry_run && journal.is_none() {
+            journal = Some(DeployJournal::create(project_dir, &board.path, &files_to_copy, &|path| {
+                file_ops.target_path(path)
+            })?);
+        }
+
+        let mut result = file_ops.copy_files_to(storage, project_dir, &files_to_copy, dry_run, journal.as_mut())?;
+        result.deployed_files.extend(resumed_files);
+
+        // Everything made it to the board, the journal is no longer needed
+        if result.files_failed == 0
+            && let Some(journal) = journal
+        {
+            journal.finish()?;
+        }
+
+        // Generate the version file so code.py can report what it is running
+        if let Some(version_file) = &self.version_file
+            && !dry_run
+        {
+            let deployed = VersionInfo::collect(project_dir).write_to_board(storage, version_file)?;
+            result.bytes_copied += deployed.size;
+            result.deployed_files.push(deployed);
+            self.observer.generated(&board.path.join(version_file));
+        }
+
+        // Record which files cpd owns on the board
+        let manifest_written = !dry_run && !result.deployed_files.is_empty();
+        if manifest_written {
+            let manifest = DeployManifest::merged(
+                previous_manifest.as_ref(),
+                &result.deployed_files,
+                git::head_commit(project_dir),
+            );
+            manifest.write(storage)?;
+            self.observer.generated(&DeployManifest::path_for(&board.path));
+        }
+
+        Ok(DeployReport {
+            board,
+            profile,
+            dry_run,
+            result,
+            backup_dir: self.backup_dir.clone(),
+            version_file: self.version_file.clone(),
+            manifest_written,
+        })
+    }
+
+    /// The board to deploy to and the storage to reach it through
+    fn select_board(&self) -> Result<(CircuitPythonBoard, Arc<dyn BoardStorage + Send + Sync>)> {
+        let detector = BoardDetector::new(self.verbose).with_discovery(self.network.then(Discovery::default));
+
+        let board = match &self.board {
+            BoardSelector::Storage(storage) => {
+                return Ok((CircuitPythonBoard::from_storage(storage.as_ref(), "Manual".to_string()), Arc::clone(storage)));
+            }
+            BoardSelector::Path(board_path) => {
+                if !detector.is_circuitpython_board(board_path) {
+                    if !self.force {
+                        return Err(CpdError::InvalidBoardPath {
+                            path: format!("{} doesn't appear to be a CircuitPython board", board_path.display()),
+                        });
+                    }
+                    self.observer.warning(&format!(
+                        "{} doesn't appear to be a CircuitPython board, but --force was specified",
+                        board_path.display()
+                    ));
+                }
+
+                CircuitPythonBoard::new(board_path.clone(), "Manual".to_string(), None, 0, 0)
+            }
+            BoardSelector::Alias(name) => detector.with_aliases(UserConfig::load()?.aliases).find_alias(name)?,
+            BoardSelector::Auto => {
+                let mut boards = detector.detect_boards()?;
+
+                match (boards.len(), &self.select_board) {
+                    (0, _) => return Err(CpdError::BoardNotFound),
+                    (1, _) => boards.remove(0),
+                    (_, Some(select_board)) => {
+                        let index = select_board(&boards)?;
+                        if index >= boards.len() {
+                            return Err(CpdError::MultipleBoardsFound);
+                        }
+                        boards.swap_remove(index)
+                    }
+                    (_, None) => return Err(CpdError::MultipleBoardsFound),
+                }
+            }
+        };
+
+        // Boards found over mDNS are written through the Web Workflow
+        let storage: Arc<dyn BoardStorage + Send + Sync> = if board.network.is_some() {
+            let url = board.path.to_string_lossy();
+            Arc::new(WebBoard::connect(&url, web_api_password(&self.project_dir)?.as_deref())?)
+        } else {
+            Arc::new(LocalBoard::new(&board.path))
+        };
+        Ok((board, storage))
+    }
+}
+
+impl DeployerBuilder {
+    pub fn board(mut self, board: BoardSelector) -> Self {
+        self.deployer.board = board;
+        self
+    }
+
+    pub fn mode(mut self, mode: DeployMode) -> Self {
+        self.deployer.mode = mode;
+        self
+    }
+
+    /// Use this deploy profile from `cpd.toml` instead of choosing one by Board ID
+    pub fn profile(mut self, name: impl Into<String>) -> Self {
+        self.deployer.profile = Some(name.into());
+        self
+    }
+
+    /// Whether to apply `.gitignore` files (if `cpd.toml` doesn't turn them off)
+    pub fn gitignore(mut self, enabled: bool) -> Self {
+        self.deployer.use_gitignore = enabled;
+        self
+    }
+
+    /// Whether to apply the built-in ignore patterns (if `cpd.toml` doesn't turn them off)
+    pub fn default_ignores(mut self, enabled: bool) -> Self {
+        self.deployer.use_default_ignores = enabled;
+        self
+    }
+
+    /// Only deploy paths that `filter` accepts, on top of the project's ignore rules
+    #[allow(dead_code)]
+    pub fn filter(mut self, filter: impl Fn(&Path) -> bool + Send + Sync + 'static) -> Self {
+        self.deployer.filter = Some(Box::new(filter));
+        self
+    }
+
+    /// Back up the board to `backup_dir` before writing to it
+    pub fn backup(mut self, backup_dir: impl Into<PathBuf>) -> Self {
+        self.deployer.backup_dir = Some(backup_dir.into());
+        self
+    }
+
+    /// Deploy to a board path that doesn't look like CircuitPython, and
+    /// overwrite protected board files
+    pub fn force(mut self, force: bool) -> Self {
+        self.deployer.force = force;
+        self
+    }
+
+    /// Overwrite and delete protected board files without skipping board validation
+    pub fn overwrite_protected(mut self, enabled: bool) -> Self {
+        self.deployer.overwrite_protected = enabled;
+        self
+    }
+
+    /// Offer Web Workflow boards found over mDNS alongside drives when no board is given
+    pub fn network(mut self, enabled: bool) -> Self {
+        self.deployer.network = enabled;
+        self
+    }
+
+    /// Read written files back and rewrite mismatches up to `retries` times
+    pub fn verify(mut self, retries: u32) -> Self {
+        self.deployer.verify_retries = Some(retries);
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.deployer.retry_policy = retry_policy;
+        self
+    }
+
+    /// Write git version info to this board path (`.py` or `.json`)
+    pub fn version_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.deployer.version_file = Some(path.into());
+        self
+    }
+
+    pub fn verbose(mut self, verbose: bool) -> Self {
+        self.deployer.verbose = verbose;
+        self
+    }
+
+    /// Pick one of several detected boards by index
+    pub fn select_board(
+        mut self,
+        hook: impl Fn(&[CircuitPythonBoard]) -> Result<usize> + Send + Sync + 'static,
+    ) -> Self {
+        self.deployer.select_board = Some(Box::new(hook));
+        self
+    }
+
+    /// Inspect the plan before anything is written; returning false cancels
+    /// the deployment with `CpdError::Cancelled`
+    pub fn confirm(mut self, hook: impl Fn(&DeployPlan) -> bool + Send + Sync + 'static) -> Self {
+        self.deployer.confirm = Some(Box::new(hook));
+        self
+    }
+
+    /// Report progress to `observer`; by default nothing is reported
+    pub fn observer(mut self, observer: impl DeployObserver + Send + Sync + 'static) -> Self {
+        self.deployer.observer = Arc::new(observer);
+        self
+    }
+
+    pub fn build(self) -> Deployer {
+        self.deployer
+    }
+}
+
+/// The ignore filter for a project: `cpd.toml` settings, narrowed by the
+/// `use_gitignore` and `use_defaults` switches, plus the deploy profile's rules
+pub fn project_ignore_filter(
+    project_dir: &Path,
+    config: &ProjectConfig,
+    profile: Option<&DeployProfile>,
+    use_gitignore: bool,
+    use_defaults: bool,
+) -> Result<IgnoreFilter> {
+    let ignore_options = IgnoreOptions {
+        use_gitignore: config.ignore.gitignore && use_gitignore,
+        use_defaults: config.ignore.defaults && use_defaults,
+        profile: config.ignore.profile,
+        extra_rules: profile.map(DeployProfile::ignore_rules).unwrap_or_default(),
+        ..IgnoreOptions::default()
+    };
+    IgnoreFilter::with_options(project_dir, &ignore_options)
+}
@@ -1,4 +1,6 @@
+use serde::ser::{Serialize, SerializeStruct, Serializer};
 use std::io;
+use std::path::Path;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -15,47 +17,344 @@ pub enum CpdError {
     #[error("Invalid board path: {path}\n\nThe specified path does not exist or is not a valid CircuitPython board.\nUse --list-boards to see detected boards.")]
     InvalidBoardPath { path: String },
 
-    #[error("Backup directory creation failed: {path}")]
-    BackupDirectoryCreationFailed { path: String },
+    #[error("The board at {path} is mounted read-only.\n\nThis usually means boot.py calls storage.remount(\"/\", readonly=False), which gives CircuitPython write access and takes it away from your computer.\n  • Rename or delete boot.py from the REPL, or make the remount depend on a button or pin\n  • Or restart the board in safe mode, which skips boot.py\n  • Then press RESET and deploy again")]
+    ReadOnlyBoard { path: String },
 
-    #[error("File copy failed: {from} -> {to}")]
-    FileCopyFailed { from: String, to: String },
+    #[error("Could not connect to the board at {board}: {source}")]
+    BoardConnectionFailed { board: String, source: io::Error },
+
+    #[error("The board at {path} disconnected and did not come back within {timeout_secs} seconds.\n\nCheck the USB cable and connection, then deploy again.")]
+    BoardDisconnected { path: String, timeout_secs: u64 },
+
+    #[error("No board path or alias named '{name}'.\n\nRegister the name for a connected board with `cpd alias add {name}`, or use --list-boards to see detected boards.")]
+    UnknownBoardAlias { name: String },
+
+    #[error("Board '{name}' ({identity}) is not connected.\n\nUse --list-boards to see detected boards and their aliases.")]
+    AliasedBoardNotFound { name: String, identity: String },
+
+    #[error("Backup directory creation failed: {path}: {source}")]
+    BackupDirectoryCreationFailed { path: String, source: io::Error },
+
+    #[error("Failed to create directory {path}: {source}")]
+    DirectoryCreationFailed { path: String, source: io::Error },
+
+    #[error("File copy failed: {from} -> {to}: {source}")]
+    FileCopyFailed { from: String, to: String, source: io::Error },
+
+    #[error("Failed to read {path}: {source}")]
+    FileReadFailed { path: String, source: io::Error },
+
+    #[error("Verification failed: {path} did not match the source after {attempts} attempt(s)")]
+    VerificationFailed { path: String, attempts: u32 },
 
     #[error("Invalid ignore pattern: {pattern}")]
     InvalidIgnorePattern { pattern: String },
 
+    #[error("Secret '{name}' used in {file} is not set.\n\nSet the environment variable {name} or add it to cpd.secrets.toml in the project directory.")]
+    MissingSecret { name: String, file: String },
+
     #[error("Configuration error: {message}")]
     Configuration { message: String },
 
-    #[error("Permission denied: {path}")]
-    #[allow(dead_code)]
-    PermissionDenied { path: String },
+    #[error("Permission denied: {path}: {source}")]
+    PermissionDenied { path: String, source: io::Error },
 
-    #[error("Disk full or insufficient space")]
-    #[allow(dead_code)]
-    InsufficientSpace,
+    #[error("Disk full or insufficient space writing {path}")]
+    InsufficientSpace { path: String, source: io::Error },
+
+    #[error("{failed} of {total} files failed to deploy")]
+    PartialCopyFailure { failed: usize, total: usize },
 
     #[error("Deployment was cancelled by user")]
-    #[allow(dead_code)]
     Cancelled,
 }
 
+/// Process exit codes, one per kind of failure.
+///
+/// These are part of the command-line interface and documented in the
+/// README; scripts depend on them, so existing values must never change.
+pub mod exit_code {
+    /// Any failure without a more specific code, such as an I/O error
+    pub const FAILURE: i32 = 1;
+    // 2 is used by clap for invalid command-line arguments
+    /// Invalid cpd.toml, ignore pattern, missing secret or argument combination
+    pub const CONFIGURATION: i32 = 3;
+    /// No board was found, the one given with --board could not be reached,
+    /// or the board went away during the deployment
+    pub const BOARD_NOT_FOUND: i32 = 4;
+    pub const MULTIPLE_BOARDS_FOUND: i32 = 5;
+    /// --board is not a usable board path or a registered alias
+    pub const INVALID_BOARD_PATH: i32 = 6;
+    pub const INSUFFICIENT_SPACE: i32 = 7;
+    /// The deployment finished, but some files could not be written
+    pub const PARTIAL_COPY_FAILURE: i32 = 8;
+    pub const CANCELLED: i32 = 9;
+    /// The board's drive is mounted read-only
+    pub const READ_ONLY_BOARD: i32 = 10;
+}
+
 pub type Result<T> = std::result::Result<T, CpdError>;
 
 impl CpdError {
+    /// Map an I/O error from writing `path` to `InsufficientSpace` or
+    /// `PermissionDenied` when its kind calls for one, and to `otherwise`
+    /// for anything else
+    pub fn from_write_error(source: io::Error, path: &Path, otherwise: impl FnOnce(io::Error) -> CpdError) -> CpdError {
+        let path = path.display().to_string();
+
+        if is_storage_full_error(&source) {
+            CpdError::InsufficientSpace { path, source }
+        } else if is_read_only_error(&source) || source.kind() == io::ErrorKind::PermissionDenied {
+            CpdError::PermissionDenied { path, source }
+        } else {
+            otherwise(source)
+        }
+    }
+
     pub fn is_recoverable(&self) -> bool {
         match self {
             CpdError::Io(_) => false,
             CpdError::BoardNotFound => false,
             CpdError::MultipleBoardsFound => false,
             CpdError::InvalidBoardPath { .. } => false,
+            CpdError::ReadOnlyBoard { .. } => false,
+            CpdError::BoardConnectionFailed { .. } => false,
+            CpdError::BoardDisconnected { .. } => false,
+            CpdError::UnknownBoardAlias { .. } => false,
+            CpdError::AliasedBoardNotFound { .. } => false,
             CpdError::BackupDirectoryCreationFailed { .. } => false,
-            CpdError::FileCopyFailed { .. } => true, // Can continue with other files
+            // Can continue with other files, unless the whole board has stopped accepting writes
+            CpdError::DirectoryCreationFailed { source, .. } | CpdError::FileCopyFailed { source, .. } => {
+                !is_storage_full_error(source) && !is_read_only_error(source)
+            }
+            CpdError::FileReadFailed { .. } => true,
+            CpdError::VerificationFailed { .. } => true,
             CpdError::InvalidIgnorePattern { .. } => true,
+            CpdError::MissingSecret { .. } => true,
             CpdError::Configuration { .. } => false,
-            CpdError::PermissionDenied { .. } => true,
-            CpdError::InsufficientSpace => false,
+            CpdError::PermissionDenied { source, .. } => !is_read_only_error(source),
+            CpdError::InsufficientSpace { .. } => false,
+            CpdError::PartialCopyFailure { .. } => false,
             CpdError::Cancelled => false,
         }
     }
+
+    /// Exit status for the process when the command fails with this error
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CpdError::BoardNotFound
+            | CpdError::BoardConnectionFailed { .. }
+            | CpdError::BoardDisconnected { .. }
+            | CpdError::AliasedBoardNotFound { .. } => exit_code::BOARD_NOT_FOUND,
+            CpdError::MultipleBoardsFound => exit_code::MULTIPLE_BOARDS_FOUND,
+            CpdError::InvalidBoardPath { .. } | CpdError::UnknownBoardAlias { .. } => exit_code::INVALID_BOARD_PATH,
+            CpdError::ReadOnlyBoard { .. } => exit_code::READ_ONLY_BOARD,
+            // A drive that turned out to be read-only only once writing started
+            CpdError::PermissionDenied { source, .. } if is_read_only_error(source) => exit_code::READ_ONLY_BOARD,
+            CpdError::InsufficientSpace { .. } => exit_code::INSUFFICIENT_SPACE,
+            CpdError::PartialCopyFailure { .. } => exit_code::PARTIAL_COPY_FAILURE,
+            CpdError::Cancelled => exit_code::CANCELLED,
+            CpdError::Configuration { .. }
+            | CpdError::InvalidIgnorePattern { .. }
+            | CpdError::MissingSecret { .. } => exit_code::CONFIGURATION,
+            CpdError::Io(_)
+            | CpdError::BackupDirectoryCreationFailed { .. }
+            | CpdError::DirectoryCreationFailed { .. }
+            | CpdError::FileCopyFailed { .. }
+            | CpdError::FileReadFailed { .. }
+            | CpdError::VerificationFailed { .. }
+            | CpdError::PermissionDenied { .. } => exit_code::FAILURE,
+        }
+    }
+
+    /// Stable identifier for the kind of error, used in JSON output
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CpdError::Io(_) => "io",
+            CpdError::BoardNotFound => "board_not_found",
+            CpdError::MultipleBoardsFound => "multiple_boards_found",
+            CpdError::InvalidBoardPath { .. } => "invalid_board_path",
+            CpdError::ReadOnlyBoard { .. } => "read_only_board",
+            CpdError::BoardConnectionFailed { .. } => "board_connection_failed",
+            CpdError::BoardDisconnected { .. } => "board_disconnected",
+            CpdError::UnknownBoardAlias { .. } => "unknown_board_alias",
+            CpdError::AliasedBoardNotFound { .. } => "aliased_board_not_found",
+            CpdError::BackupDirectoryCreationFailed { .. } => "backup_directory_creation_failed",
+            CpdError::DirectoryCreationFailed { .. } => "directory_creation_failed",
+            CpdError::FileCopyFailed { .. } => "file_copy_failed",
+            CpdError::FileReadFailed { .. } => "file_read_failed",
+            CpdError::VerificationFailed { .. } => "verification_failed",
+            CpdError::InvalidIgnorePattern { .. } => "invalid_ignore_pattern",
+            CpdError::MissingSecret { .. } => "missing_secret",
+            CpdError::Configuration { .. } => "configuration",
+            CpdError::PermissionDenied { .. } => "permission_denied",
+            CpdError::InsufficientSpace { .. } => "insufficient_space",
+            CpdError::PartialCopyFailure { .. } => "partial_copy_failure",
+            CpdError::Cancelled => "cancelled",
+        }
+    }
+
+    /// The underlying I/O error, if this error was caused by one
+    pub fn io_error(&self) -> Option<&io::Error> {
+        match self {
+            CpdError::Io(source)
+            | CpdError::BoardConnectionFailed { source, .. }
+            | CpdError::BackupDirectoryCreationFailed { source, .. }
+            | CpdError::DirectoryCreationFailed { source, .. }
+            | CpdError::FileCopyFailed { source, .. }
+            | CpdError::FileReadFailed { source, .. }
+            | CpdError::PermissionDenied { source, .. }
+            | CpdError::InsufficientSpace { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Errors serialize as `{"kind": ..., "message": ..., "recoverable": ...}`,
+/// plus `"os_error"` with the errno when an I/O error caused them
+impl Serialize for CpdError {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        let os_error = self.io_error().and_then(io::Error::raw_os_error);
+
+        let mut state = serializer.serialize_struct("CpdError", 4)?;
+        state.serialize_field("kind", self.kind())?;
+        state.serialize_field("message", &self.to_string())?;
+        state.serialize_field("recoverable", &self.is_recoverable())?;
+        if let Some(code) = os_error {
+            state.serialize_field("os_error", &code)?;
+        } else {
+            state.skip_field("os_error")?;
+        }
+        state.end()
+    }
+}
+
+/// Check whether an I/O error means the device is out of space
+pub fn is_storage_full_error(error: &io::Error) -> bool {
+    matches!(error.kind(), io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded | io::ErrorKind::FileTooLarge)
+}
+
+/// Check whether an I/O error means the filesystem is mounted read-only, as
+/// CIRCUITPY is when `boot.py` hands write access to CircuitPython
+pub fn is_read_only_error(error: &io::Error) -> bool {
+    if error.kind() == io::ErrorKind::ReadOnlyFilesystem {
+        return true;
+    }
+
+    // ERROR_WRITE_PROTECT
+    #[cfg(windows)]
+    if error.raw_os_error() == Some(19) {
+        return true;
+    }
+
+    false
+}
+
+/// Check whether an I/O error is likely to go away on its own, such as a
+/// busy device or a board that is briefly unmounted during auto-reload
+pub fn is_transient_io_error(error: &io::Error) -> bool {
+    match error.kind() {
+        io::ErrorKind::Interrupted
+        | io::ErrorKind::TimedOut
+        | io::ErrorKind::WouldBlock
+        | io::ErrorKind::ResourceBusy => return true,
+        _ => {}
+    }
+
+    #[cfg(unix)]
+    if let Some(code) = error.raw_os_error() {
+        return matches!(code, libc::EIO | libc::EBUSY | libc::EAGAIN | libc::ENODEV | libc::ENXIO);
+    }
+
+    #[cfg(windows)]
+    if let Some(code) = error.raw_os_error() {
+        // ERROR_NOT_READY, ERROR_SHARING_VIOLATION, ERROR_LOCK_VIOLATION, ERROR_DEV_NOT_EXIST
+        return matches!(code, 21 | 32 | 33 | 55);
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transient_io_errors() {
+        assert!(is_transient_io_error(&io::Error::from(io::ErrorKind::Interrupted)));
+        assert!(is_transient_io_error(&io::Error::from(io::ErrorKind::ResourceBusy)));
+        assert!(!is_transient_io_error(&io::Error::from(io::ErrorKind::PermissionDenied)));
+
+        #[cfg(unix)]
+        assert!(is_transient_io_error(&io::Error::from_raw_os_error(libc::EIO)));
+    }
+
+    #[test]
+    fn test_write_error_classification() {
+        let path = Path::new("/media/CIRCUITPY/code.py");
+        let copy_failed = |source| CpdError::FileCopyFailed {
+            from: "code.py".to_string(),
+            to: path.display().to_string(),
+            source,
+        };
+
+        let error = CpdError::from_write_error(io::Error::from(io::ErrorKind::StorageFull), path, copy_failed);
+        assert!(matches!(error, CpdError::InsufficientSpace { .. }));
+        assert!(!error.is_recoverable());
+
+        let error = CpdError::from_write_error(io::Error::from(io::ErrorKind::ReadOnlyFilesystem), path, copy_failed);
+        assert!(matches!(error, CpdError::PermissionDenied { .. }));
+        assert!(!error.is_recoverable());
+        assert!(error.to_string().contains("read-only"));
+
+        let error = CpdError::from_write_error(io::Error::from(io::ErrorKind::PermissionDenied), path, copy_failed);
+        assert!(matches!(error, CpdError::PermissionDenied { .. }));
+        assert!(error.is_recoverable());
+
+        let error = CpdError::from_write_error(io::Error::from(io::ErrorKind::InvalidFilename), path, copy_failed);
+        assert!(matches!(error, CpdError::FileCopyFailed { .. }));
+        assert!(error.is_recoverable());
+        assert_eq!(error.io_error().unwrap().kind(), io::ErrorKind::InvalidFilename);
+    }
+
+    #[test]
+    fn test_exit_codes() {
+        assert_eq!(CpdError::BoardNotFound.exit_code(), 4);
+        assert_eq!(
+            CpdError::BoardConnectionFailed {
+                board: "serial:/dev/ttyUSB0".to_string(),
+                source: io::Error::from(io::ErrorKind::NotFound),
+            }
+            .exit_code(),
+            exit_code::BOARD_NOT_FOUND
+        );
+        assert_eq!(
+            CpdError::UnknownBoardAlias {
+                name: "kitchen-sensor".to_string()
+            }
+            .exit_code(),
+            exit_code::INVALID_BOARD_PATH
+        );
+        assert_eq!(CpdError::PartialCopyFailure { failed: 1, total: 3 }.exit_code(), 8);
+        assert_eq!(
+            CpdError::Configuration {
+                message: String::new()
+            }
+            .exit_code(),
+            exit_code::CONFIGURATION
+        );
+        assert_eq!(CpdError::Io(io::Error::from(io::ErrorKind::NotFound)).exit_code(), exit_code::FAILURE);
+    }
+
+    #[test]
+    fn test_serialize_error() {
+        let error = CpdError::InvalidBoardPath {
+            path: "/media/CIRCUITPY".to_string(),
+        };
+        let value = serde_json::to_value(&error).unwrap();
+
+        assert_eq!(value["kind"], "invalid_board_path");
+        assert_eq!(value["recoverable"], false);
+        assert!(value["message"].as_str().unwrap().contains("/media/CIRCUITPY"));
+    }
 }
@@ -1,54 +1,200 @@
-use crate::error::{CpdError, Result};
-use indicatif::{ProgressBar, ProgressStyle};
+use crate::error::{is_transient_io_error, CpdError, Result};
+use crate::journal::DeployJournal;
+use crate::layout::PathMapper;
+use crate::manifest::{board_path_string, DeployManifest};
+use crate::progress::{Batch, DeployObserver, Operation, SilentObserver, SkipReason, VerifyFailure};
+use crate::protect::ProtectedPaths;
+use crate::storage::{BoardStorage, LocalBoard};
+use crate::template::{self, Secrets};
+use serde::{Serialize, Serializer};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 use walkdir::WalkDir;
 
 pub struct FileOperations {
-    verbose: bool,
+    verify_retries: Option<u32>,
+    retry_policy: RetryPolicy,
+    protected: ProtectedPaths,
+    secrets: Option<Secrets>,
+    renames: BTreeMap<PathBuf, PathBuf>,
+    layout: PathMapper,
+    observer: Arc<dyn DeployObserver + Send + Sync>,
 }
 
 impl FileOperations {
-    pub fn new(verbose: bool) -> Self {
-        Self { verbose }
+    pub fn new() -> Self {
+        Self {
+            verify_retries: None,
+            retry_policy: RetryPolicy::default(),
+            protected: ProtectedPaths::none(),
+            secrets: None,
+            renames: BTreeMap::new(),
+            layout: PathMapper::default(),
+            observer: Arc::new(SilentObserver),
+        }
+    }
+
+    /// Read every written file back and compare its hash with the source,
+    /// rewriting it up to `retries` times on mismatch
+    pub fn with_verification(mut self, retries: u32) -> Self {
+        self.verify_retries = Some(retries);
+        self
+    }
+
+    /// Never overwrite or delete existing destination files matching these paths
+    pub fn with_protected_paths(mut self, protected: ProtectedPaths) -> Self {
+        self.protected = protected;
+        self
+    }
+
+    /// Render `settings.toml` and `*.tmpl` files with these secrets before writing them
+    pub fn with_templates(mut self, secrets: Secrets) -> Self {
+        self.secrets = Some(secrets);
+        self
+    }
+
+    /// Map source directories to different destination directories
+    pub fn with_layout(mut self, layout: PathMapper) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    /// Install files under a different destination path, keyed by source-relative path.
+    ///
+    /// Renames take precedence over the layout.
+    pub fn with_renames(mut self, renames: BTreeMap<PathBuf, PathBuf>) -> Self {
+        self.renames = renames;
+        self
+    }
+
+    /// Report progress to `observer`; by default nothing is reported
+    pub fn with_observer(mut self, observer: Arc<dyn DeployObserver + Send + Sync>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Set how transient write errors are retried
+    pub fn with_retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = retry_policy;
+        self
     }
 
     /// Copy a single file from source to destination
+    #[allow(dead_code)]
     pub fn copy_file(&self, from: &Path, to: &Path) -> Result<()> {
-        if let Some(parent) = to.parent() {
-            fs::create_dir_all(parent).map_err(|e| {
-                if self.verbose {
-                    eprintln!("Failed to create directory {}: {}", parent.display(), e);
+        let (Some(parent), Some(file_name)) = (to.parent(), to.file_name()) else {
+            return Err(CpdError::InvalidBoardPath {
+                path: to.display().to_string(),
+            });
+        };
+        let content = read_source(from)?;
+        self.copy_file_with_retries(&LocalBoard::new(parent), from, Path::new(file_name), &content)
+            .map(|_| ())
+    }
+
+    /// Write `content` to `target` on the board, returning how many
+    /// retries transient errors needed.
+    ///
+    /// `from` is where the content came from, used for timestamps and messages.
+    fn copy_file_with_retries(&self, board: &dyn BoardStorage, from: &Path, target: &Path, content: &[u8]) -> Result<u32> {
+        let mut retries = self.write_file(board, from, target, content)?;
+
+        let Some(verify_retries) = self.verify_retries else {
+            return Ok(retries);
+        };
+
+        let to = board.root().join(target);
+        let expected = hash_bytes(content);
+        for attempt in 0..=verify_retries {
+            if attempt > 0 {
+                retries += self.write_file(board, from, target, content)?;
+            }
+
+            // A file that can't be read back counts as a mismatch and is rewritten
+            match board.read_back(target) {
+                Ok(written) if hash_bytes(&written) == expected => {
+                    self.observer.verified(target);
+                    return Ok(retries);
                 }
-                CpdError::Io(e)
-            })?;
+                Ok(_) => self.observer.verify_failed(target, attempt + 1, VerifyFailure::Mismatch),
+                Err(e) => self.observer.verify_failed(target, attempt + 1, VerifyFailure::Unreadable(&e)),
+            }
         }
 
-        fs::copy(from, to).map_err(|e| {
-            if self.verbose {
-                eprintln!("Failed to copy {} to {}: {}", from.display(), to.display(), e);
-            }
-            CpdError::FileCopyFailed {
-                from: from.display().to_string(),
-                to: to.display().to_string(),
+        Err(CpdError::VerificationFailed {
+            path: to.display().to_string(),
+            attempts: verify_retries + 1,
+        })
+    }
+
+    /// Write a file, retrying transient errors with exponential backoff.
+    ///
+    /// If the board goes away while retrying, wait for it to come back
+    /// before the next attempt.
+    fn write_file(&self, board: &dyn BoardStorage, from: &Path, target: &Path, content: &[u8]) -> Result<u32> {
+        let mut retries = 0;
+
+        loop {
+            let error = match self.try_write_file(board, from, target, content) {
+                Ok(()) => return Ok(retries),
+                Err(error) => error,
+            };
+
+            // A board that vanished mid-write is usually remounting after auto-reload
+            let board_missing = !board.is_connected();
+            let to = board.root().join(target);
+            if retries >= self.retry_policy.max_retries || !(board_missing || is_transient_io_error(error.io_error())) {
+                return Err(error.into_cpd_error(from, &to));
             }
-        })?;
 
-        // Preserve timestamps
-        if let Ok(metadata) = fs::metadata(from) {
-            if let Ok(modified) = metadata.modified() {
-                let _ = filetime::set_file_mtime(to, filetime::FileTime::from_system_time(modified));
+            retries += 1;
+            let delay = self.retry_policy.delay_for(retries);
+            self.observer.retrying(target, error.io_error(), retries, delay);
+            thread::sleep(delay);
+
+            if !board.is_connected() {
+                self.observer.waiting_for_board(&board.root());
+                if !board.wait_for_reconnect(self.retry_policy.remount_timeout) {
+                    return Err(CpdError::BoardDisconnected {
+                        path: board.root().display().to_string(),
+                        timeout_secs: self.retry_policy.remount_timeout.as_secs(),
+                    });
+                }
             }
         }
+    }
+
+    fn try_write_file(
+        &self,
+        board: &dyn BoardStorage,
+        from: &Path,
+        target: &Path,
+        content: &[u8],
+    ) -> std::result::Result<(), WriteError> {
+        if let Some(parent) = target.parent() {
+            board.mkdir(parent).map_err(WriteError::CreateDir)?;
+        }
+
+        board.write(target, content).map_err(WriteError::Copy)?;
 
-        if self.verbose {
-            println!("Copied: {} -> {}", from.display(), to.display());
+        // Preserve timestamps
+        if let Ok(metadata) = fs::metadata(from)
+            && let Ok(modified) = metadata.modified()
+        {
+            board.set_modified(target, modified);
         }
 
         Ok(())
     }
 
-    /// Copy directory contents with progress tracking
+    /// Copy directory contents, reporting progress to the observer
+    #[allow(dead_code)]
     pub fn copy_directory_contents(
         &self,
         from_dir: &Path,
@@ -56,167 +202,439 @@ impl FileOperations {
         filter: &dyn Fn(&Path) -> bool,
         dry_run: bool,
     ) -> Result<CopyResult> {
-        let mut files_to_copy = Vec::new();
-        let mut _total_size = 0u64;
+        // First pass: collect files
+        let files_to_copy = self.collect_files(from_dir, filter);
+
+        // Second pass: copy files
+        self.copy_files_to(&LocalBoard::new(to_dir), from_dir, &files_to_copy, dry_run, None)
+    }
 
-        // First pass: collect files and calculate total size
-        for entry in WalkDir::new(from_dir)
+    /// Collect the files under `from_dir` that pass the filter.
+    ///
+    /// The filter is also asked about directories; a directory it rejects
+    /// is skipped without being descended into.
+    pub fn collect_files(&self, from_dir: &Path, filter: &dyn Fn(&Path) -> bool) -> Vec<PathBuf> {
+        WalkDir::new(from_dir)
             .into_iter()
+            .filter_entry(|e| e.depth() == 0 || !e.file_type().is_dir() || filter(e.path()))
             .filter_map(|e| e.ok())
             .filter(|e| e.file_type().is_file())
-        {
-            let path = entry.path();
-            if filter(path) {
-                if let Ok(metadata) = entry.metadata() {
-                    _total_size += metadata.len();
-                }
-                files_to_copy.push(path.to_path_buf());
-            }
-        }
-
-        let progress = if !dry_run && !files_to_copy.is_empty() {
-            let pb = ProgressBar::new(files_to_copy.len() as u64);
-            pb.set_style(
-                ProgressStyle::default_bar()
-                    .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
-                    .unwrap()
-                    .progress_chars("##-"),
-            );
-            Some(pb)
-        } else {
-            None
-        };
+            .map(|e| e.into_path())
+            .filter(|path| filter(path))
+            .collect()
+    }
 
-        let mut result = CopyResult {
-            files_copied: 0,
-            files_failed: 0,
-            bytes_copied: 0,
-            failed_files: Vec::new(),
-        };
+    /// Copy a list of files from `from_dir` to the same relative paths under `to_dir`.
+    ///
+    /// When a journal is given, each completed file is recorded in it so an
+    /// interrupted deployment can be resumed.
+    #[allow(dead_code)]
+    pub fn copy_files(
+        &self,
+        from_dir: &Path,
+        to_dir: &Path,
+        files_to_copy: &[PathBuf],
+        dry_run: bool,
+        journal: Option<&mut DeployJournal>,
+    ) -> Result<CopyResult> {
+        self.copy_files_to(&LocalBoard::new(to_dir), from_dir, files_to_copy, dry_run, journal)
+    }
 
-        // Second pass: copy files
-        for file_path in &files_to_copy {
+    /// Copy a list of files from `from_dir` to the board, like [`copy_files`](Self::copy_files)
+    pub fn copy_files_to(
+        &self,
+        board: &dyn BoardStorage,
+        from_dir: &Path,
+        files_to_copy: &[PathBuf],
+        dry_run: bool,
+        mut journal: Option<&mut DeployJournal>,
+    ) -> Result<CopyResult> {
+        let operation = Operation::Deploy;
+        self.observer.planned(&Batch {
+            operation,
+            from: from_dir,
+            to: &board.root(),
+            total: files_to_copy.len(),
+            dry_run,
+        });
+
+        let mut result = CopyResult::default();
+
+        for file_path in files_to_copy {
             let relative_path = file_path.strip_prefix(from_dir).unwrap();
-            let dest_path = to_dir.join(relative_path);
+            let target_path = self.target_path(relative_path);
 
-            if let Some(pb) = &progress {
-                pb.set_message(format!("Copying {}", relative_path.display()));
-            }
+            self.observer.started(relative_path);
 
-            if dry_run {
-                println!("Would copy: {} -> {}", file_path.display(), dest_path.display());
-                result.files_copied += 1;
+            // Render templates up front so a missing secret fails before anything is written
+            let rendered = self.render_template(file_path, relative_path);
+
+            let blocked = if self.renders(relative_path) {
+                self.protected.blocks_rendered(board, &target_path)
+            } else {
+                self.protected.blocks(board, &target_path)
+            };
+
+            if blocked {
+                self.observer.skipped(relative_path, &target_path, SkipReason::Protected);
+                result.protected_files.push(target_path);
+            } else if dry_run {
+                match rendered {
+                    Ok(rendered) => {
+                        self.observer.skipped(
+                            relative_path,
+                            &target_path,
+                            SkipReason::DryRun {
+                                rendered: rendered.as_ref(),
+                            },
+                        );
+                        result.files_copied += 1;
+                    }
+                    Err(e) => {
+                        self.observer.failed(relative_path, &target_path, &e);
+                        result.files_failed += 1;
+                        result.failed_files.push((file_path.clone(), e.to_string()));
+                    }
+                }
             } else {
-                match self.copy_file(file_path, &dest_path) {
-                    Ok(_) => {
+                let copied = rendered.and_then(|rendered| {
+                    let content = match rendered {
+                        Some(rendered) => rendered.content.into_bytes(),
+                        None => read_source(file_path)?,
+                    };
+                    let retries = self.copy_file_with_retries(board, file_path, &target_path, &content)?;
+                    Ok((retries, content))
+                });
+
+                match copied {
+                    Ok((retries, content)) => {
                         result.files_copied += 1;
-                        if let Ok(metadata) = fs::metadata(file_path) {
-                            result.bytes_copied += metadata.len();
+                        if retries > 0 {
+                            result.retried_files.push((relative_path.to_path_buf(), retries));
                         }
+                        if let Some(journal) = journal.as_deref_mut() {
+                            journal.mark_completed(relative_path)?;
+                        }
+
+                        let deployed = DeployedFile {
+                            relative_path: target_path.clone(),
+                            size: content.len() as u64,
+                            sha256: hash_bytes(&content),
+                        };
+
+                        result.bytes_copied += deployed.size;
+                        self.observer.copied(relative_path, &target_path, deployed.size, retries);
+                        result.deployed_files.push(deployed);
                     }
                     Err(e) => {
+                        self.observer.failed(relative_path, &target_path, &e);
                         result.files_failed += 1;
                         result.failed_files.push((file_path.clone(), e.to_string()));
                         
                         // Continue with other files if error is recoverable
                         if !e.is_recoverable() {
-                            if let Some(pb) = &progress {
-                                pb.finish_with_message("Deployment failed");
-                            }
+                            self.observer.finished(operation);
                             return Err(e);
                         }
                     }
                 }
             }
+        }
 
-            if let Some(pb) = &progress {
-                pb.inc(1);
-            }
+        self.observer.finished(operation);
+
+        Ok(result)
+    }
+
+    /// Path a source-relative file is written to, relative to the destination root
+    pub fn target_path(&self, relative_path: &Path) -> PathBuf {
+        if let Some(target) = self.renames.get(relative_path) {
+            return target.clone();
         }
 
-        if let Some(pb) = &progress {
-            pb.finish_with_message("Deployment completed");
+        let mapped = self.layout.map(relative_path);
+        if self.secrets.is_some() {
+            template::target_path(&mapped)
+        } else {
+            mapped
         }
+    }
 
-        Ok(result)
+    /// Whether a source-relative file is a template that gets rendered
+    fn renders(&self, relative_path: &Path) -> bool {
+        self.secrets.is_some() && template::is_template(relative_path)
+    }
+
+    /// Render a file if it is a template and templating is enabled
+    fn render_template(&self, file_path: &Path, relative_path: &Path) -> Result<Option<template::RenderedTemplate>> {
+        let Some(secrets) = self.secrets.as_ref().filter(|_| template::is_template(relative_path)) else {
+            return Ok(None);
+        };
+
+        let content = fs::read_to_string(file_path).map_err(|source| CpdError::FileReadFailed {
+            path: file_path.display().to_string(),
+            source,
+        })?;
+        template::render(&content, secrets, relative_path).map(Some)
     }
 
     /// Create a backup of the destination directory
+    #[allow(dead_code)]
     pub fn create_backup(&self, source_dir: &Path, backup_dir: &Path) -> Result<()> {
-        if !source_dir.exists() {
+        self.backup_board(&LocalBoard::new(source_dir), backup_dir)
+    }
+
+    /// Copy every file on the board to `backup_dir`
+    pub fn backup_board(&self, board: &dyn BoardStorage, backup_dir: &Path) -> Result<()> {
+        if !board.is_connected() {
             return Ok(()); // Nothing to backup
         }
 
-        fs::create_dir_all(backup_dir).map_err(|_| CpdError::BackupDirectoryCreationFailed {
+        fs::create_dir_all(backup_dir).map_err(|source| CpdError::BackupDirectoryCreationFailed {
             path: backup_dir.display().to_string(),
+            source,
         })?;
 
-        let result = self.copy_directory_contents(
-            source_dir,
-            backup_dir,
-            &|_| true, // Backup everything
-            false,
-        )?;
-
-        if self.verbose {
-            println!(
-                "Backup completed: {} files, {} bytes",
-                result.files_copied,
-                format_bytes(result.bytes_copied)
-            );
+        let files = board.walk()?; // Backup everything
+        let root = board.root();
+        let backup = LocalBoard::new(backup_dir);
+
+        self.observer.planned(&Batch {
+            operation: Operation::Backup,
+            from: &root,
+            to: backup_dir,
+            total: files.len(),
+            dry_run: false,
+        });
+
+        let mut result = CopyResult::default();
+
+        for file in &files {
+            let relative_path = file.path.as_path();
+            self.observer.started(relative_path);
+
+            // The board path is only used for messages and timestamps
+            let copied = board
+                .read(relative_path)
+                .map_err(CpdError::Io)
+                .and_then(|content| {
+                    let retries =
+                        self.copy_file_with_retries(&backup, &root.join(relative_path), relative_path, &content)?;
+                    Ok((retries, content.len() as u64))
+                });
+
+            match copied {
+                Ok((retries, bytes)) => {
+                    result.files_copied += 1;
+                    result.bytes_copied += bytes;
+                    self.observer.copied(relative_path, relative_path, bytes, retries);
+                }
+                Err(e) => {
+                    self.observer.failed(relative_path, relative_path, &e);
+                    result.files_failed += 1;
+
+                    if !e.is_recoverable() {
+                        self.observer.finished(Operation::Backup);
+                        return Err(e);
+                    }
+                }
+            }
         }
 
+        self.observer.finished(Operation::Backup);
+
         Ok(())
     }
 
-    /// Remove files that don't exist in source (for clean deployment)
+    /// Remove previously deployed files that no longer exist in source (for clean deployment).
+    ///
+    /// Only files listed in the board's deployment manifest are considered;
+    /// files the user created on the device are never deleted.
     #[allow(dead_code)]
     pub fn clean_destination(&self, source_dir: &Path, dest_dir: &Path, filter: &dyn Fn(&Path) -> bool) -> Result<()> {
-        if !dest_dir.exists() {
-            return Ok(());
-        }
-
-        let mut files_to_remove = Vec::new();
+        self.clean_board(&LocalBoard::new(dest_dir), source_dir, filter)
+    }
 
-        for entry in WalkDir::new(dest_dir)
-            .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-        {
-            let dest_path = entry.path();
-            let relative_path = dest_path.strip_prefix(dest_dir).unwrap();
-            let source_path = source_dir.join(relative_path);
+    /// Remove stale deployed files from the board, like [`clean_destination`](Self::clean_destination)
+    #[allow(dead_code)]
+    pub fn clean_board(&self, board: &dyn BoardStorage, source_dir: &Path, filter: &dyn Fn(&Path) -> bool) -> Result<()> {
+        let root = board.root();
+        let Some(mut manifest) = DeployManifest::load(board, &|message| self.observer.warning(message))? else {
+            // Nothing was deployed before, so nothing can be stale
+            self.observer.planned(&Batch {
+                operation: Operation::Clean,
+                from: source_dir,
+                to: &root,
+                total: 0,
+                dry_run: false,
+            });
+            self.observer.finished(Operation::Clean);
+            return Ok(());
+        };
 
-            // If the file doesn't exist in source or would be filtered out, mark for removal
-            if !source_path.exists() || !filter(&source_path) {
-                files_to_remove.push(dest_path.to_path_buf());
+        // Destination paths the current source files would be written to
+        let mut sources = self.collect_files(source_dir, filter);
+        sources.extend(self.renames.keys().map(|source| source_dir.join(source)));
+        let live: BTreeSet<String> = sources
+            .iter()
+            .filter_map(|file| file.strip_prefix(source_dir).ok())
+            .filter(|relative_path| self.renames.contains_key(*relative_path) || self.layout.covers(relative_path))
+            .map(|relative_path| board_path_string(&self.target_path(relative_path)))
+            .collect();
+
+        // Files deployed earlier that are no longer part of the project
+        let stale: Vec<&str> = manifest
+            .files
+            .iter()
+            .map(|entry| entry.path.as_str())
+            .filter(|path| !live.contains(*path))
+            .collect();
+
+        self.observer.planned(&Batch {
+            operation: Operation::Clean,
+            from: source_dir,
+            to: &root,
+            total: stale.len(),
+            dry_run: false,
+        });
+
+        let mut removed = Vec::new();
+
+        for path in stale {
+            let relative_path = Path::new(path);
+            self.observer.started(relative_path);
+
+            if self.protected.blocks(board, relative_path) {
+                self.observer.skipped(relative_path, relative_path, SkipReason::Protected);
+                continue;
             }
-        }
 
-        for file_path in files_to_remove {
-            if let Err(e) = fs::remove_file(&file_path) {
-                if self.verbose {
-                    eprintln!("Failed to remove {}: {}", file_path.display(), e);
+            match board.delete(relative_path) {
+                Ok(()) => {
+                    self.observer.removed(relative_path);
+                    removed.push(path.to_string());
+                }
+                Err(e) if e.kind() == io::ErrorKind::NotFound => removed.push(path.to_string()),
+                Err(e) => {
+                    let error = CpdError::from_write_error(e, &root.join(relative_path), CpdError::Io);
+                    self.observer.failed(relative_path, relative_path, &error);
                 }
-            } else if self.verbose {
-                println!("Removed: {}", file_path.display());
             }
         }
 
+        self.observer.finished(Operation::Clean);
+
+        if !removed.is_empty() {
+            manifest.files.retain(|entry| !removed.contains(&entry.path));
+            manifest.write(board)?;
+        }
+
         Ok(())
     }
 }
 
-#[derive(Debug)]
+impl Default for FileOperations {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// How transient write errors (I/O errors, busy device, briefly missing
+/// mount during auto-reload) are retried
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    /// Retries after the first attempt; zero disables retrying
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    /// How long to wait for a vanished board to be remounted
+    pub remount_timeout: Duration,
+}
+
+impl RetryPolicy {
+    /// Policy that never retries
+    #[allow(dead_code)]
+    pub fn none() -> Self {
+        Self {
+            max_retries: 0,
+            ..Self::default()
+        }
+    }
+
+    /// Delay before the given retry (1-based), doubling each time up to `max_backoff`
+    pub fn delay_for(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial_backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(250),
+            max_backoff: Duration::from_secs(4),
+            remount_timeout: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Failure from a single write attempt, before it is mapped to a `CpdError`
+enum WriteError {
+    CreateDir(io::Error),
+    Copy(io::Error),
+}
+
+impl WriteError {
+    fn io_error(&self) -> &io::Error {
+        match self {
+            WriteError::CreateDir(e) | WriteError::Copy(e) => e,
+        }
+    }
+
+    fn into_cpd_error(self, from: &Path, to: &Path) -> CpdError {
+        match self {
+            WriteError::CreateDir(e) => {
+                let parent = to.parent().unwrap_or(to);
+                CpdError::from_write_error(e, parent, |source| CpdError::DirectoryCreationFailed {
+                    path: parent.display().to_string(),
+                    source,
+                })
+            }
+            WriteError::Copy(e) => CpdError::from_write_error(e, to, |source| CpdError::FileCopyFailed {
+                from: from.display().to_string(),
+                to: to.display().to_string(),
+                source,
+            }),
+        }
+    }
+}
+
+/// A file written to the destination, as recorded in the deployment manifest
+#[derive(Debug, Clone, Serialize)]
+pub struct DeployedFile {
+    #[serde(rename = "path")]
+    pub relative_path: PathBuf,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Default, Serialize)]
 pub struct CopyResult {
     pub files_copied: usize,
     pub files_failed: usize,
     pub bytes_copied: u64,
+    #[serde(serialize_with = "serialize_failed_files")]
     pub failed_files: Vec<(PathBuf, String)>,
+    pub deployed_files: Vec<DeployedFile>,
+    /// Files that only succeeded after retrying, with the number of retries
+    #[serde(serialize_with = "serialize_retried_files")]
+    pub retried_files: Vec<(PathBuf, u32)>,
+    /// Files left untouched because they are protected on the destination
+    pub protected_files: Vec<PathBuf>,
 }
 
 impl CopyResult {
-    #[allow(dead_code)]
     pub fn is_success(&self) -> bool {
         self.files_failed == 0
     }
@@ -239,7 +657,49 @@ impl CopyResult {
     }
 }
 
-fn format_bytes(bytes: u64) -> String {
+fn serialize_failed_files<S: Serializer>(files: &[(PathBuf, String)], serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct FailedFile<'a> {
+        path: &'a Path,
+        error: &'a str,
+    }
+
+    serializer.collect_seq(files.iter().map(|(path, error)| FailedFile { path, error }))
+}
+
+fn serialize_retried_files<S: Serializer>(files: &[(PathBuf, u32)], serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    #[derive(Serialize)]
+    struct RetriedFile<'a> {
+        path: &'a Path,
+        retries: u32,
+    }
+
+    serializer.collect_seq(files.iter().map(|(path, retries)| RetriedFile { path, retries: *retries }))
+}
+
+/// Read a project file to deploy. Failing to read it is a problem on this
+/// side that retrying the write can't fix, so it is reported on its own.
+fn read_source(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).map_err(|source| CpdError::FileReadFailed {
+        path: path.display().to_string(),
+        source,
+    })
+}
+
+/// Compute the hex-encoded SHA-256 digest of a file
+pub fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Compute the hex-encoded SHA-256 digest of in-memory content
+pub fn hash_bytes(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+pub(crate) fn format_bytes(bytes: u64) -> String {
     const UNITS: &[&str] = &["B", "KB", "MB", "GB"];
     let mut size = bytes as f64;
     let mut unit_index = 0;
@@ -256,12 +716,107 @@ fn format_bytes(bytes: u64) -> String {
     }
 }
 
-// Add filetime dependency to Cargo.toml for timestamp preservation
-extern crate filetime;
-
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::storage::{BoardEntry, MemoryBoard};
+    use std::cell::Cell;
+    use std::sync::Mutex;
+
+    /// Records the retry and verification events
+    #[derive(Default)]
+    struct Recorder {
+        events: Mutex<Vec<String>>,
+    }
+
+    impl DeployObserver for Recorder {
+        fn retrying(&self, target: &Path, _error: &io::Error, retry: u32, _delay: Duration) {
+            self.events.lock().unwrap().push(format!("retrying {} {}", target.display(), retry));
+        }
+
+        fn verified(&self, target: &Path) {
+            self.events.lock().unwrap().push(format!("verified {}", target.display()));
+        }
+
+        fn verify_failed(&self, target: &Path, attempt: u32, failure: VerifyFailure) {
+            let failure = match failure {
+                VerifyFailure::Mismatch => "mismatch",
+                VerifyFailure::Unreadable(_) => "unreadable",
+            };
+            self.events.lock().unwrap().push(format!("{} {} {}", failure, target.display(), attempt));
+        }
+    }
+
+    /// A memory board whose first few writes fail with a transient error or
+    /// garble the content, and which can be unplugged
+    #[derive(Debug, Default)]
+    struct FlakyBoard {
+        board: MemoryBoard,
+        failed_writes: Cell<u32>,
+        corrupt_writes: Cell<u32>,
+        writes: Cell<u32>,
+        unplugged: Cell<bool>,
+    }
+
+    impl BoardStorage for FlakyBoard {
+        fn root(&self) -> PathBuf {
+            self.board.root()
+        }
+
+        fn list(&self, dir: &Path) -> io::Result<Vec<BoardEntry>> {
+            self.board.list(dir)
+        }
+
+        fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+            self.board.read(path)
+        }
+
+        fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+            self.writes.set(self.writes.get() + 1);
+            if self.failed_writes.get() > 0 {
+                self.failed_writes.set(self.failed_writes.get() - 1);
+                return Err(io::ErrorKind::ResourceBusy.into());
+            }
+            if self.corrupt_writes.get() > 0 {
+                self.corrupt_writes.set(self.corrupt_writes.get() - 1);
+                return self.board.write(path, &content[..content.len() / 2]);
+            }
+            self.board.write(path, content)
+        }
+
+        fn delete(&self, path: &Path) -> io::Result<()> {
+            self.board.delete(path)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+            self.board.rename(from, to)
+        }
+
+        fn mkdir(&self, path: &Path) -> io::Result<()> {
+            self.board.mkdir(path)
+        }
+
+        fn stat(&self, path: &Path) -> io::Result<Option<BoardEntry>> {
+            self.board.stat(path)
+        }
+
+        fn free_space(&self) -> Option<u64> {
+            self.board.free_space()
+        }
+
+        fn is_connected(&self) -> bool {
+            !self.unplugged.get()
+        }
+    }
+
+    fn fast_retries() -> RetryPolicy {
+        RetryPolicy {
+            initial_backoff: Duration::from_millis(1),
+            max_backoff: Duration::from_millis(1),
+            remount_timeout: Duration::from_millis(1),
+            ..RetryPolicy::default()
+        }
+    }
 
     #[test]
     fn test_format_bytes() {
@@ -272,4 +827,157 @@ mod tests {
         assert_eq!(format_bytes(1048576), "1.0 MB");
         assert_eq!(format_bytes(1073741824), "1.0 GB");
     }
+
+    #[test]
+    fn test_copy_file_with_verification() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let from = temp_dir.path().join("code.py");
+        let to = temp_dir.path().join("board/code.py");
+        fs::write(&from, "print('hello')").unwrap();
+
+        let file_ops = FileOperations::new().with_verification(2);
+        file_ops.copy_file(&from, &to).unwrap();
+
+        assert_eq!(fs::read_to_string(&to).unwrap(), "print('hello')");
+    }
+
+    #[test]
+    fn test_verification_rewrites_corrupted_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let from = temp_dir.path().join("code.py");
+        fs::write(&from, "print('hello')").unwrap();
+
+        let board = FlakyBoard::default();
+        board.corrupt_writes.set(1);
+
+        let recorder = Arc::new(Recorder::default());
+        let file_ops = FileOperations::new().with_verification(2).with_observer(recorder.clone());
+        let result = file_ops.copy_files_to(&board, temp_dir.path(), &[from], false, None).unwrap();
+
+        assert_eq!(result.files_copied, 1);
+        assert_eq!(board.writes.get(), 2);
+        assert_eq!(*recorder.events.lock().unwrap(), ["mismatch code.py 1", "verified code.py"]);
+        assert_eq!(board.board.file("code.py").unwrap(), b"print('hello')");
+    }
+
+    #[test]
+    fn test_verification_failure_is_per_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let code = temp_dir.path().join("code.py");
+        let lib = temp_dir.path().join("lib.py");
+        fs::write(&code, "print('hello')").unwrap();
+        fs::write(&lib, "x = 1").unwrap();
+
+        let board = FlakyBoard::default();
+        board.corrupt_writes.set(3);
+
+        // Every attempt at code.py is corrupted, but lib.py still gets deployed
+        let file_ops = FileOperations::new().with_verification(2);
+        let result = file_ops
+            .copy_files_to(&board, temp_dir.path(), &[code.clone(), lib], false, None)
+            .unwrap();
+
+        assert_eq!(result.files_copied, 1);
+        assert_eq!(result.files_failed, 1);
+        assert_eq!(result.failed_files[0].0, code);
+        assert!(result.failed_files[0].1.contains("Verification failed"));
+        assert_eq!(board.board.file("lib.py").unwrap(), b"x = 1");
+    }
+
+    #[test]
+    fn test_unreadable_source_is_not_retried() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let missing = temp_dir.path().join("code.py");
+        let lib = temp_dir.path().join("lib.py");
+        fs::write(&lib, "x = 1").unwrap();
+
+        let board = FlakyBoard::default();
+        let recorder = Arc::new(Recorder::default());
+        let file_ops = FileOperations::new()
+            .with_retry_policy(fast_retries())
+            .with_observer(recorder.clone());
+        let result = file_ops
+            .copy_files_to(&board, temp_dir.path(), &[missing.clone(), lib], false, None)
+            .unwrap();
+
+        assert_eq!(result.files_copied, 1);
+        assert_eq!(result.failed_files[0].0, missing);
+        assert!(result.failed_files[0].1.starts_with("Failed to read"));
+        assert!(recorder.events.lock().unwrap().is_empty());
+        assert_eq!(board.writes.get(), 1);
+    }
+
+    #[test]
+    fn test_transient_write_errors_are_retried() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let from = temp_dir.path().join("code.py");
+        fs::write(&from, "print('hello')").unwrap();
+
+        let board = FlakyBoard::default();
+        board.failed_writes.set(2);
+
+        let recorder = Arc::new(Recorder::default());
+        let file_ops = FileOperations::new()
+            .with_retry_policy(fast_retries())
+            .with_observer(recorder.clone());
+        let result = file_ops.copy_files_to(&board, temp_dir.path(), &[from], false, None).unwrap();
+
+        assert_eq!(result.files_copied, 1);
+        assert_eq!(*recorder.events.lock().unwrap(), ["retrying code.py 1", "retrying code.py 2"]);
+        assert_eq!(result.retried_files, vec![(PathBuf::from("code.py"), 2)]);
+        assert_eq!(board.board.file("code.py").unwrap(), b"print('hello')");
+
+        // Failing more often than the policy allows fails the file
+        let board = FlakyBoard::default();
+        board.failed_writes.set(4);
+        let result = file_ops
+            .copy_files_to(&board, temp_dir.path(), &[temp_dir.path().join("code.py")], false, None)
+            .unwrap();
+
+        assert_eq!(result.files_failed, 1);
+        assert_eq!(board.writes.get(), 4);
+    }
+
+    #[test]
+    fn test_board_that_never_comes_back() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let from = temp_dir.path().join("code.py");
+        fs::write(&from, "print('hello')").unwrap();
+
+        let board = FlakyBoard::default();
+        board.failed_writes.set(1);
+        board.unplugged.set(true);
+
+        let file_ops = FileOperations::new().with_retry_policy(fast_retries());
+        let error = file_ops
+            .copy_files_to(&board, temp_dir.path(), &[from], false, None)
+            .unwrap_err();
+
+        assert!(matches!(error, CpdError::BoardDisconnected { .. }));
+        assert_eq!(error.exit_code(), crate::error::exit_code::BOARD_NOT_FOUND);
+        assert_eq!(board.writes.get(), 1);
+    }
+
+    #[test]
+    fn test_retry_backoff() {
+        let policy = RetryPolicy::default();
+
+        assert_eq!(policy.delay_for(1), Duration::from_millis(250));
+        assert_eq!(policy.delay_for(2), Duration::from_millis(500));
+        assert_eq!(policy.delay_for(3), Duration::from_secs(1));
+        assert_eq!(policy.delay_for(10), Duration::from_secs(4));
+        assert_eq!(RetryPolicy::none().max_retries, 0);
+    }
+
+    #[test]
+    fn test_hash_file() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("code.py");
+        fs::write(&path, "abc").unwrap();
+
+        assert_eq!(
+            hash_file(&path).unwrap(),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
 }
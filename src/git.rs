@@ -0,0 +1,350 @@
+use sha1::{Digest, Sha1};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Git state of a project, read directly from the `.git` directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GitInfo {
+    pub commit: Option<String>,
+    pub branch: Option<String>,
+    pub dirty: bool,
+}
+
+impl GitInfo {
+    /// Read commit, branch and dirty state for the repository containing `project_dir`
+    pub fn read(project_dir: &Path) -> Option<Self> {
+        let repo = Repository::discover(project_dir)?;
+        let (commit, branch) = repo.head()?;
+
+        Some(Self {
+            commit,
+            branch,
+            dirty: repo.is_dirty(),
+        })
+    }
+}
+
+/// A git directory and the work tree it belongs to
+struct Repository {
+    git_dir: PathBuf,
+    work_tree: PathBuf,
+}
+
+impl Repository {
+    /// Locate the repository for a project, walking up from `project_dir`.
+    ///
+    /// Handles both regular repositories (`.git/` directory) and worktrees or
+    /// submodules where `.git` is a file containing `gitdir: <path>`.
+    fn discover(project_dir: &Path) -> Option<Self> {
+        let start = project_dir.canonicalize().unwrap_or_else(|_| project_dir.to_path_buf());
+
+        for dir in start.ancestors() {
+            let candidate = dir.join(".git");
+
+            if candidate.is_dir() {
+                return Some(Self {
+                    git_dir: candidate,
+                    work_tree: dir.to_path_buf(),
+                });
+            }
+
+            if candidate.is_file() {
+                let content = fs::read_to_string(&candidate).ok()?;
+                let gitdir = Path::new(content.strip_prefix("gitdir:")?.trim());
+                return Some(Self {
+                    git_dir: if gitdir.is_absolute() {
+                        gitdir.to_path_buf()
+                    } else {
+                        dir.join(gitdir)
+                    },
+                    work_tree: dir.to_path_buf(),
+                });
+            }
+        }
+
+        None
+    }
+
+    /// The commit HEAD points to, and its branch unless HEAD is detached
+    fn head(&self) -> Option<(Option<String>, Option<String>)> {
+        let head = fs::read_to_string(self.git_dir.join("HEAD")).ok()?;
+        let head = head.trim();
+
+        Some(match head.strip_prefix("ref:") {
+            Some(reference) => {
+                let reference = reference.trim();
+                let branch = reference.strip_prefix("refs/heads/").unwrap_or(reference);
+                (self.resolve_ref(reference), Some(branch.to_string()))
+            }
+            None => (Some(head.to_string()), None),
+        })
+    }
+
+    /// Resolve a ref name such as `refs/heads/main` to a commit hash
+    fn resolve_ref(&self, reference: &str) -> Option<String> {
+        // Worktrees keep their refs in the common git directory
+        let common_dir = fs::read_to_string(self.git_dir.join("commondir"))
+            .map(|dir| self.git_dir.join(dir.trim()))
+            .unwrap_or_else(|_| self.git_dir.clone());
+
+        for dir in [self.git_dir.as_path(), common_dir.as_path()] {
+            if let Ok(hash) = fs::read_to_string(dir.join(reference)) {
+                return Some(hash.trim().to_string());
+            }
+        }
+
+        // Fall back to packed-refs
+        let packed = fs::read_to_string(common_dir.join("packed-refs")).ok()?;
+        packed
+            .lines()
+            .filter(|line| !line.starts_with('#') && !line.starts_with('^'))
+            .filter_map(|line| line.split_once(' '))
+            .find(|(_, name)| *name == reference)
+            .map(|(hash, _)| hash.to_string())
+    }
+
+    /// Check whether any tracked file in the work tree differs from the index.
+    ///
+    /// Like `git describe --dirty`, untracked files don't count. Changes that
+    /// are staged but not committed aren't detected since that would require
+    /// reading the object database.
+    fn is_dirty(&self) -> bool {
+        let Ok(data) = fs::read(self.git_dir.join("index")) else {
+            return false;
+        };
+
+        match parse_index(&data) {
+            Some(entries) => entries.iter().any(|entry| self.entry_modified(entry)),
+            // Unsupported index format, err on the side of caution
+            None => true,
+        }
+    }
+
+    fn entry_modified(&self, entry: &IndexEntry) -> bool {
+        // Unmerged entries always mean a dirty tree
+        if entry.stage != 0 {
+            return true;
+        }
+
+        // Submodules are tracked by commit, not content
+        if entry.mode & 0o170000 == 0o160000 {
+            return false;
+        }
+
+        let path = self.work_tree.join(&entry.path);
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            return true;
+        };
+
+        let content = if metadata.file_type().is_symlink() {
+            match fs::read_link(&path) {
+                Ok(target) => target.to_string_lossy().into_owned().into_bytes(),
+                Err(_) => return true,
+            }
+        } else {
+            if metadata.len() as u32 != entry.size {
+                return true;
+            }
+
+            // Same size and mtime as recorded in the index, trust it like git does
+            let mtime = filetime::FileTime::from_last_modification_time(&metadata);
+            if mtime.unix_seconds() as u32 == entry.mtime_seconds && mtime.nanoseconds() == entry.mtime_nanoseconds {
+                return false;
+            }
+
+            match fs::read(&path) {
+                Ok(content) => content,
+                Err(_) => return true,
+            }
+        };
+
+        blob_hash(&content) != entry.sha1
+    }
+}
+
+struct IndexEntry {
+    mtime_seconds: u32,
+    mtime_nanoseconds: u32,
+    mode: u32,
+    size: u32,
+    sha1: [u8; 20],
+    stage: u16,
+    path: String,
+}
+
+/// Size of an index entry with an empty path: 62 bytes of fixed fields plus the NUL
+const INDEX_ENTRY_MIN_SIZE: usize = 63;
+
+/// Parse the entries of a version 2 or 3 git index file
+fn parse_index(data: &[u8]) -> Option<Vec<IndexEntry>> {
+    let read_u32 = |offset: usize| -> Option<u32> {
+        Some(u32::from_be_bytes(data.get(offset..offset + 4)?.try_into().ok()?))
+    };
+    let read_u16 = |offset: usize| -> Option<u16> {
+        Some(u16::from_be_bytes(data.get(offset..offset + 2)?.try_into().ok()?))
+    };
+
+    if data.get(0..4)? != b"DIRC" {
+        return None;
+    }
+
+    let version = read_u32(4)?;
+    if version != 2 && version != 3 {
+        return None;
+    }
+
+    let count = read_u32(8)? as usize;
+    // The count comes from the file, so don't trust it beyond what the data can hold
+    let mut entries = Vec::with_capacity(count.min(data.len() / INDEX_ENTRY_MIN_SIZE));
+    let mut offset = 12;
+
+    for _ in 0..count {
+        let start = offset;
+        let flags = read_u16(start + 60)?;
+        let mut path_start = start + 62;
+
+        // Extended flags are only present in version 3
+        if flags & 0x4000 != 0 {
+            path_start += 2;
+        }
+
+        let path_len = data.get(path_start..)?.iter().position(|&b| b == 0)?;
+        let path = String::from_utf8_lossy(&data[path_start..path_start + path_len]).into_owned();
+
+        entries.push(IndexEntry {
+            mtime_seconds: read_u32(start + 8)?,
+            mtime_nanoseconds: read_u32(start + 12)?,
+            mode: read_u32(start + 24)?,
+            size: read_u32(start + 36)?,
+            sha1: data.get(start + 40..start + 60)?.try_into().ok()?,
+            stage: (flags >> 12) & 0x3,
+            path,
+        });
+
+        // Entries are NUL-padded to a multiple of eight bytes
+        let entry_len = path_start - start + path_len;
+        offset = start + (entry_len + 8) / 8 * 8;
+    }
+
+    Some(entries)
+}
+
+/// Compute the git object id of a blob with the given content
+fn blob_hash(content: &[u8]) -> [u8; 20] {
+    let mut hasher = Sha1::new();
+    hasher.update(format!("blob {}\0", content.len()).as_bytes());
+    hasher.update(content);
+    hasher.finalize().into()
+}
+
+/// Read the commit hash that HEAD points to, without shelling out to git.
+///
+/// Unlike [`GitInfo::read`] this doesn't check the work tree for changes.
+pub fn head_commit(project_dir: &Path) -> Option<String> {
+    Repository::discover(project_dir)?.head()?.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    const COMMIT: &str = "0123456789abcdef0123456789abcdef01234567";
+
+    /// Build a minimal version 2 index containing a single entry
+    fn write_index(git_dir: &Path, path: &str, content: &[u8]) {
+        let mut entry = Vec::new();
+        entry.extend_from_slice(&[0u8; 24]); // ctime, mtime, dev, ino
+        entry.extend_from_slice(&0o100644u32.to_be_bytes());
+        entry.extend_from_slice(&[0u8; 8]); // uid, gid
+        entry.extend_from_slice(&(content.len() as u32).to_be_bytes());
+        entry.extend_from_slice(&blob_hash(content));
+        entry.extend_from_slice(&(path.len() as u16).to_be_bytes());
+        entry.extend_from_slice(path.as_bytes());
+        let padded = (entry.len() + 8) / 8 * 8;
+        entry.resize(padded, 0);
+
+        let mut index = b"DIRC".to_vec();
+        index.extend_from_slice(&2u32.to_be_bytes());
+        index.extend_from_slice(&1u32.to_be_bytes());
+        index.extend_from_slice(&entry);
+        fs::write(git_dir.join("index"), index).unwrap();
+    }
+
+    fn init_repo(root: &Path) -> PathBuf {
+        let git_dir = root.join(".git");
+        fs::create_dir_all(git_dir.join("refs/heads")).unwrap();
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(git_dir.join("refs/heads/main"), format!("{}\n", COMMIT)).unwrap();
+        git_dir
+    }
+
+    #[test]
+    fn test_corrupt_index_count() {
+        // Claims four billion entries but holds none
+        let mut data = b"DIRC".to_vec();
+        data.extend_from_slice(&2u32.to_be_bytes());
+        data.extend_from_slice(&u32::MAX.to_be_bytes());
+
+        assert!(parse_index(&data).is_none());
+    }
+
+    #[test]
+    fn test_head_commit_from_loose_ref() {
+        let temp_dir = TempDir::new().unwrap();
+        init_repo(temp_dir.path());
+
+        // Subdirectories of the repository resolve to the same commit
+        let project = temp_dir.path().join("device");
+        fs::create_dir_all(&project).unwrap();
+
+        assert_eq!(head_commit(&project).as_deref(), Some(COMMIT));
+    }
+
+    #[test]
+    fn test_head_commit_from_packed_refs() {
+        let temp_dir = TempDir::new().unwrap();
+        let git_dir = temp_dir.path().join(".git");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(git_dir.join("HEAD"), "ref: refs/heads/main\n").unwrap();
+        fs::write(
+            git_dir.join("packed-refs"),
+            format!("# pack-refs with: peeled fully-peeled sorted\n{} refs/heads/main\n", COMMIT),
+        )
+        .unwrap();
+
+        assert_eq!(head_commit(temp_dir.path()).as_deref(), Some(COMMIT));
+    }
+
+    #[test]
+    fn test_detached_head_has_no_branch() {
+        let temp_dir = TempDir::new().unwrap();
+        let git_dir = temp_dir.path().join(".git");
+        fs::create_dir_all(&git_dir).unwrap();
+        fs::write(git_dir.join("HEAD"), format!("{}\n", COMMIT)).unwrap();
+
+        let info = GitInfo::read(temp_dir.path()).unwrap();
+        assert_eq!(info.commit.as_deref(), Some(COMMIT));
+        assert_eq!(info.branch, None);
+    }
+
+    #[test]
+    fn test_dirty_state() {
+        let temp_dir = TempDir::new().unwrap();
+        let git_dir = init_repo(temp_dir.path());
+        fs::write(temp_dir.path().join("code.py"), "print('hi')").unwrap();
+        write_index(&git_dir, "code.py", b"print('hi')");
+
+        let info = GitInfo::read(temp_dir.path()).unwrap();
+        assert_eq!(info.branch.as_deref(), Some("main"));
+        assert!(!info.dirty);
+
+        // Same size, different content
+        fs::write(temp_dir.path().join("code.py"), "print('yo')").unwrap();
+        assert!(GitInfo::read(temp_dir.path()).unwrap().dirty);
+
+        // Deleted tracked file
+        fs::remove_file(temp_dir.path().join("code.py")).unwrap();
+        assert!(GitInfo::read(temp_dir.path()).unwrap().dirty);
+    }
+}
@@ -1,48 +1,391 @@
 use crate::error::{CpdError, Result};
-use ignore::{gitignore::GitignoreBuilder, Match};
+use crate::template::SECRETS_FILE_NAME;
+use ignore::{
+    gitignore::{Gitignore, GitignoreBuilder, Glob},
+    Match,
+};
+use serde::{Deserialize, Serialize, Serializer};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use walkdir::WalkDir;
+
+/// Where an ignore pattern was loaded from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternSource {
+    /// Built into cpd and always applied (VCS and cpd's own files)
+    Internal,
+    /// Built into cpd, can be turned off
+    Default,
+    /// Extra built-in patterns from the selected ignore profile
+    Profile(IgnoreProfile),
+    /// Include and exclude patterns of a deploy profile in `cpd.toml`
+    DeployProfile(String),
+    /// An ignore file such as `.cpdignore` or `.gitignore`
+    File(PathBuf),
+}
+
+impl fmt::Display for PatternSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternSource::Internal => write!(f, "built-in (always applied)"),
+            PatternSource::Default => write!(f, "built-in defaults"),
+            PatternSource::Profile(profile) => write!(f, "built-in '{}' profile", profile),
+            PatternSource::DeployProfile(name) => write!(f, "profile '{}' in cpd.toml", name),
+            PatternSource::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+/// A single ignore pattern together with where it came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IgnoreRule {
+    #[serde(serialize_with = "serialize_display")]
+    pub source: PatternSource,
+    /// 1-based line number within the source file
+    pub line: Option<usize>,
+    pub pattern: String,
+}
+
+impl IgnoreRule {
+    /// Whether this is a negated (`!pattern`) rule that re-includes paths
+    pub fn is_whitelist(&self) -> bool {
+        self.pattern.starts_with('!')
+    }
+
+    /// Human-readable location, e.g. `.cpdignore:3`
+    pub fn location(&self) -> String {
+        match self.line {
+            Some(line) => format!("{}:{}", self.source, line),
+            None => self.source.to_string(),
+        }
+    }
+}
+
+/// Why a path is included in or excluded from deployment
+#[derive(Debug, Clone, Serialize)]
+pub struct Explanation {
+    pub path: PathBuf,
+    pub included: bool,
+    /// The rule that decided the outcome, if any pattern matched
+    pub rule: Option<IgnoreRule>,
+    /// Excluded because an allowlist is in use and doesn't list the path
+    pub outside_allowlist: bool,
+}
+
+fn serialize_display<T: fmt::Display, S: Serializer>(value: &T, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(value)
+}
+
+/// Options controlling which ignore sources are loaded
+#[derive(Debug, Clone)]
+pub struct IgnoreOptions {
+    /// User-wide ignore file applied to every project
+    pub global_ignore_file: Option<PathBuf>,
+    /// Apply patterns from `.gitignore` files
+    pub use_gitignore: bool,
+    /// Apply the built-in default patterns
+    pub use_defaults: bool,
+    /// Which set of built-in patterns to apply when defaults are enabled
+    pub profile: IgnoreProfile,
+    /// Rules that take precedence over every ignore file, such as those of a deploy profile
+    pub extra_rules: Vec<IgnoreRule>,
+}
+
+impl Default for IgnoreOptions {
+    fn default() -> Self {
+        Self {
+            global_ignore_file: global_ignore_path(),
+            use_gitignore: true,
+            use_defaults: true,
+            profile: IgnoreProfile::default(),
+            extra_rules: Vec::new(),
+        }
+    }
+}
+
+/// Named sets of built-in ignore patterns
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IgnoreProfile {
+    /// Common development files (editor settings, caches, build output)
+    #[default]
+    Default,
+    /// The defaults plus host-only files from CircuitPython projects and
+    /// vendored libraries: tests, docs, stubs and packaging metadata
+    CircuitPython,
+    /// Everything in `circuitpython`, plus licences, CI configuration and
+    /// examples anywhere in the project, for boards that are short on space
+    Minimal,
+}
+
+impl IgnoreProfile {
+    /// Patterns applied on top of the generic defaults
+    fn extra_patterns(self) -> Vec<&'static str> {
+        match self {
+            IgnoreProfile::Default => Vec::new(),
+            IgnoreProfile::CircuitPython => CIRCUITPYTHON_PATTERNS.to_vec(),
+            IgnoreProfile::Minimal => CIRCUITPYTHON_PATTERNS.iter().chain(&MINIMAL_PATTERNS).copied().collect(),
+        }
+    }
+}
+
+impl fmt::Display for IgnoreProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IgnoreProfile::Default => write!(f, "default"),
+            IgnoreProfile::CircuitPython => write!(f, "circuitpython"),
+            IgnoreProfile::Minimal => write!(f, "minimal"),
+        }
+    }
+}
+
+/// Files that are only useful on the host in a CircuitPython project
+const CIRCUITPYTHON_PATTERNS: [&str; 8] = [
+    "tests/",
+    "docs/",
+    "*.md",
+    "requirements*.txt",
+    ".pre-commit-config.yaml",
+    "pyproject.toml",
+    "*.pyi",
+    "lib/**/examples/",
+];
+
+/// Ignored by the minimal profile on top of `CIRCUITPYTHON_PATTERNS`
+const MINIMAL_PATTERNS: [&str; 9] = [
+    "examples/",
+    "LICENSE*",
+    "*.license",
+    "LICENSES/",
+    ".github/",
+    ".readthedocs.yaml",
+    "setup.py",
+    "setup.cfg",
+    "Makefile",
+];
+
+/// Default location of the user-wide ignore file, e.g. `~/.config/cpd/ignore`
+pub fn global_ignore_path() -> Option<PathBuf> {
+    dirs::config_dir().map(|dir| dir.join("cpd").join("ignore"))
+}
+
+/// Name of the allowlist file; when present only matching paths are deployed
+pub const ALLOWLIST_FILE_NAME: &str = ".cpdinclude";
+
+/// Patterns that are applied even when the defaults are turned off, since
+/// these files never belong on a board
+const INTERNAL_PATTERNS: [&str; 8] = [
+    ".git/",
+    ".git",
+    ".gitignore",
+    ".cpdignore",
+    ALLOWLIST_FILE_NAME,
+    ".cpd/",
+    "cpd.toml",
+    SECRETS_FILE_NAME,
+];
+
+/// Whether a project-relative path matches one of the `INTERNAL_PATTERNS`,
+/// i.e. it is a file that is never deployed, whatever the ignore rules say
+pub fn is_internal(relative_path: &Path) -> bool {
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in INTERNAL_PATTERNS {
+        // The patterns are constant and known to be valid
+        let _ = builder.add_line(None, pattern);
+    }
+    builder
+        .build()
+        .is_ok_and(|internal| internal.matched_path_or_any_parents(relative_path, false).is_ignore())
+}
+
+/// Patterns from the `.cpdinclude` allowlist in the project root
+struct Allowlist {
+    gitignore: Gitignore,
+    rules: Vec<IgnoreRule>,
+}
+
+/// Patterns from the ignore files of a subdirectory, relative to that directory
+struct ScopedMatcher {
+    gitignore: Gitignore,
+    rules: Vec<IgnoreRule>,
+}
 
 pub struct IgnoreFilter {
-    gitignore: ignore::gitignore::Gitignore,
+    /// `INTERNAL_PATTERNS`, checked before anything that could re-include them
+    internal: Gitignore,
+    /// Built-in patterns, the global ignore file and the project root's ignore files
+    root: Gitignore,
+    /// Ignore files of subdirectories, keyed by project-relative directory.
+    ///
+    /// Each directory is loaded the first time a path inside it is matched,
+    /// so directories that are never walked into are never read. `None`
+    /// means the directory has no ignore files.
+    nested: RefCell<BTreeMap<PathBuf, Option<Rc<ScopedMatcher>>>>,
+    /// The first nested ignore file that failed to load
+    nested_error: RefCell<Option<CpdError>>,
+    /// Rules from `IgnoreOptions::extra_rules`, checked before any ignore file
+    overrides: Option<Gitignore>,
+    /// Rules of `root`
+    rules: Vec<IgnoreRule>,
+    extra_rules: Vec<IgnoreRule>,
+    allowlist: Option<Allowlist>,
+    /// Ignore files looked for in the project root and every subdirectory
+    ignore_file_names: Vec<&'static str>,
     project_root: PathBuf,
 }
 
 impl IgnoreFilter {
+    #[allow(dead_code)]
     pub fn new(project_root: &Path) -> Result<Self> {
+        Self::with_options(project_root, &IgnoreOptions::default())
+    }
+    
+    pub fn with_options(project_root: &Path, options: &IgnoreOptions) -> Result<Self> {
+        let mut rules = Vec::new();
+        
+        let mut builder = GitignoreBuilder::new(project_root);
+        Self::add_builtin_patterns(&mut builder, &mut rules, PatternSource::Internal, &INTERNAL_PATTERNS)?;
+        let internal = builder.build().map_err(|e| CpdError::InvalidIgnorePattern {
+            pattern: e.to_string(),
+        })?;
+        
         let mut builder = GitignoreBuilder::new(project_root);
         
         // Add default ignores
-        Self::add_default_patterns(&mut builder)?;
+        if options.use_defaults {
+            Self::add_default_patterns(&mut builder, &mut rules)?;
+            Self::add_builtin_patterns(
+                &mut builder,
+                &mut rules,
+                PatternSource::Profile(options.profile),
+                &options.profile.extra_patterns(),
+            )?;
+        }
+        
+        let mut ignore_file_names = vec![".cpdignore"];
+        if options.use_gitignore {
+            ignore_file_names.push(".gitignore");
+        }
         
-        // Add .cpdignore if it exists
-        let cpdignore_path = project_root.join(".cpdignore");
-        if cpdignore_path.exists() {
-            builder.add(&cpdignore_path);
+        // Add the user's global ignore file if it exists
+        if let Some(global_path) = &options.global_ignore_file
+            && global_path.is_file()
+        {
+            Self::add_ignore_file(&mut builder, &mut rules, global_path)?;
         }
         
-        // Add .gitignore if it exists (as additional patterns)
-        let gitignore_path = project_root.join(".gitignore");
-        if gitignore_path.exists() {
-            builder.add(&gitignore_path);
+        // Add .cpdignore and .gitignore from the project root if they exist
+        for &name in &ignore_file_names {
+            let path = project_root.join(name);
+            if path.is_file() {
+                Self::add_ignore_file(&mut builder, &mut rules, &path)?;
+            }
         }
         
         let gitignore = builder.build().map_err(|e| CpdError::InvalidIgnorePattern {
             pattern: e.to_string(),
         })?;
         
+        let overrides = if options.extra_rules.is_empty() {
+            None
+        } else {
+            let mut builder = GitignoreBuilder::new(project_root);
+            for rule in &options.extra_rules {
+                builder.add_line(None, &rule.pattern).map_err(|e| CpdError::InvalidIgnorePattern {
+                    pattern: format!("{} pattern '{}': {}", rule.source, rule.pattern, e),
+                })?;
+            }
+            Some(builder.build().map_err(|e| CpdError::InvalidIgnorePattern {
+                pattern: e.to_string(),
+            })?)
+        };
+        
         Ok(Self {
-            gitignore,
+            internal,
+            root: gitignore,
+            nested: RefCell::new(BTreeMap::new()),
+            nested_error: RefCell::new(None),
+            overrides,
+            rules,
+            extra_rules: options.extra_rules.clone(),
+            allowlist: Self::load_allowlist(project_root)?,
+            ignore_file_names,
             project_root: project_root.to_path_buf(),
         })
     }
     
-    fn add_default_patterns(builder: &mut GitignoreBuilder) -> Result<()> {
+    /// The ignore files of a project-relative subdirectory, loading them on first use
+    fn nested_matcher(&self, relative_dir: &Path) -> Option<Rc<ScopedMatcher>> {
+        if let Some(matcher) = self.nested.borrow().get(relative_dir) {
+            return matcher.clone();
+        }
+        
+        let matcher = match self.load_nested(relative_dir) {
+            Ok(matcher) => matcher.map(Rc::new),
+            Err(e) => {
+                self.nested_error.borrow_mut().get_or_insert(e);
+                None
+            }
+        };
+        self.nested.borrow_mut().insert(relative_dir.to_path_buf(), matcher.clone());
+        matcher
+    }
+    
+    fn load_nested(&self, relative_dir: &Path) -> Result<Option<ScopedMatcher>> {
+        let dir = self.project_root.join(relative_dir);
+        let mut builder = GitignoreBuilder::new(&dir);
+        let mut rules = Vec::new();
+        
+        for &name in &self.ignore_file_names {
+            let path = dir.join(name);
+            if path.is_file() {
+                Self::add_ignore_file(&mut builder, &mut rules, &path)?;
+            }
+        }
+        
+        if rules.is_empty() {
+            return Ok(None);
+        }
+        
+        let gitignore = builder.build().map_err(|e| CpdError::InvalidIgnorePattern {
+            pattern: e.to_string(),
+        })?;
+        Ok(Some(ScopedMatcher { gitignore, rules }))
+    }
+    
+    /// Fail with the first nested ignore file that could not be loaded while
+    /// matching paths; call this after walking the project
+    pub fn check_nested(&self) -> Result<()> {
+        match self.nested_error.borrow_mut().take() {
+            Some(e) => Err(e),
+            None => Ok(()),
+        }
+    }
+    
+    fn load_allowlist(project_root: &Path) -> Result<Option<Allowlist>> {
+        let path = project_root.join(ALLOWLIST_FILE_NAME);
+        if !path.is_file() {
+            return Ok(None);
+        }
+        
+        let mut builder = GitignoreBuilder::new(project_root);
+        let mut rules = Vec::new();
+        Self::add_ignore_file(&mut builder, &mut rules, &path)?;
+        
+        let gitignore = builder.build().map_err(|e| CpdError::InvalidIgnorePattern {
+            pattern: e.to_string(),
+        })?;
+        
+        Ok(Some(Allowlist { gitignore, rules }))
+    }
+    
+    fn add_default_patterns(builder: &mut GitignoreBuilder, rules: &mut Vec<IgnoreRule>) -> Result<()> {
         // Default patterns to ignore
         let default_patterns = [
-            ".git/",
-            ".git",
-            ".gitignore",
-            ".cpdignore",
             "target/",
             "node_modules/",
             ".env",
@@ -62,33 +405,275 @@ impl IgnoreFilter {
             "*~",
         ];
         
-        for pattern in &default_patterns {
+        Self::add_builtin_patterns(builder, rules, PatternSource::Default, &default_patterns)
+    }
+    
+    fn add_builtin_patterns(
+        builder: &mut GitignoreBuilder,
+        rules: &mut Vec<IgnoreRule>,
+        source: PatternSource,
+        patterns: &[&str],
+    ) -> Result<()> {
+        for pattern in patterns {
             builder.add_line(None, pattern).map_err(|e| CpdError::InvalidIgnorePattern {
                 pattern: format!("Default pattern '{}': {}", pattern, e),
             })?;
+            rules.push(IgnoreRule {
+                source: source.clone(),
+                line: None,
+                pattern: pattern.to_string(),
+            });
         }
         
         Ok(())
     }
     
-    /// Check if a file should be included (not ignored)
-    pub fn should_include(&self, path: &Path) -> bool {
-        // Convert absolute path to relative path from project root
-        let relative_path = if path.is_absolute() {
-            match path.strip_prefix(&self.project_root) {
-                Ok(rel) => rel,
-                Err(_) => return true, // If not under project root, include by default
+    /// Add every pattern from an ignore file, remembering its line number
+    fn add_ignore_file(builder: &mut GitignoreBuilder, rules: &mut Vec<IgnoreRule>, path: &Path) -> Result<()> {
+        let content = fs::read_to_string(path)?;
+        
+        for (index, line) in content.lines().enumerate() {
+            let pattern = normalize_pattern(line);
+            if pattern.is_empty() || pattern.starts_with('#') {
+                continue;
             }
+            
+            builder.add_line(Some(path.to_path_buf()), line).map_err(|e| CpdError::InvalidIgnorePattern {
+                pattern: format!("{}:{}: {}", path.display(), index + 1, e),
+            })?;
+            rules.push(IgnoreRule {
+                source: PatternSource::File(path.to_path_buf()),
+                line: Some(index + 1),
+                pattern: pattern.to_string(),
+            });
+        }
+        
+        Ok(())
+    }
+    
+    /// Convert a path to one relative to the project root, if it is under it
+    fn relative_path<'a>(&self, path: &'a Path) -> Option<&'a Path> {
+        // Walkers yield paths prefixed with the project root, which may itself be relative
+        if let Ok(relative_path) = path.strip_prefix(&self.project_root) {
+            Some(relative_path)
+        } else if path.is_absolute() {
+            None
         } else {
-            path
+            Some(path)
+        }
+    }
+    
+    /// Match a project-relative path, taking ignored parent directories into account
+    fn matched(&self, relative_path: &Path) -> Match<Glob> {
+        // Nothing inside an ignored directory can be re-included
+        let mut ancestors: Vec<&Path> = relative_path
+            .ancestors()
+            .skip(1)
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .collect();
+        ancestors.reverse();
+        
+        for ancestor in ancestors {
+            let matched = self.matched_single(ancestor, true);
+            if matched.is_ignore() {
+                return matched;
+            }
+        }
+        
+        let is_dir = self.project_root.join(relative_path).is_dir();
+        self.matched_single(relative_path, is_dir)
+    }
+    
+    /// Match a single path against the ignore files that apply to it; the
+    /// deepest file with a matching pattern decides
+    fn matched_single(&self, relative_path: &Path, is_dir: bool) -> Match<Glob> {
+        let matched = self.internal.matched(relative_path, is_dir);
+        if matched.is_ignore() {
+            return matched.map(Glob::clone);
+        }
+        
+        if let Some(overrides) = &self.overrides {
+            let matched = overrides.matched(relative_path, is_dir);
+            if !matched.is_none() {
+                return matched.map(Glob::clone);
+            }
+        }
+        
+        // Ignore files in the directories above the path, deepest first
+        for dir in relative_path.ancestors().skip(1) {
+            let scoped_path = relative_path.strip_prefix(dir).unwrap_or(relative_path);
+            let matched = if dir.as_os_str().is_empty() {
+                self.root.matched(scoped_path, is_dir).map(Glob::clone)
+            } else {
+                match self.nested_matcher(dir) {
+                    Some(matcher) => matcher.gitignore.matched(scoped_path, is_dir).map(Glob::clone),
+                    None => Match::None,
+                }
+            };
+            if !matched.is_none() {
+                return matched;
+            }
+        }
+        
+        Match::None
+    }
+    
+    /// Match a project-relative path against the allowlist, if there is one.
+    ///
+    /// Listing a directory allows everything inside it; `!pattern` lines
+    /// take paths back out.
+    fn allowlist_matched(&self, relative_path: &Path) -> Option<Match<&Glob>> {
+        let allowlist = self.allowlist.as_ref()?;
+        let is_dir = self.project_root.join(relative_path).is_dir();
+        Some(allowlist.gitignore.matched_path_or_any_parents(relative_path, is_dir))
+    }
+    
+    /// Check if a file or directory should be included (not ignored).
+    ///
+    /// Directories are only checked against ignore rules, not the allowlist,
+    /// since an allowed file may live in a directory that isn't listed itself.
+    pub fn should_include(&self, path: &Path) -> bool {
+        // Convert absolute path to relative path from project root
+        let Some(relative_path) = self.relative_path(path) else {
+            return true; // If not under project root, include by default
         };
         
-        match self.gitignore.matched(relative_path, path.is_dir()) {
+        let is_dir = self.project_root.join(relative_path).is_dir();
+        if !is_dir
+            && let Some(allowed) = self.allowlist_matched(relative_path)
+            && !allowed.is_ignore()
+        {
+            return false;
+        }
+        
+        match self.matched(relative_path) {
             Match::None | Match::Whitelist(_) => true,
             Match::Ignore(_) => false,
         }
     }
     
+    /// Explain which rule includes or excludes a path
+    pub fn explain(&self, path: &Path) -> Explanation {
+        let Some(relative_path) = self.relative_path(path) else {
+            return Explanation {
+                path: path.to_path_buf(),
+                included: true,
+                rule: None,
+                outside_allowlist: false,
+            };
+        };
+        
+        let allowlist_rule = match self.allowlist_matched(relative_path) {
+            Some(Match::Ignore(glob)) => self.allowlist_rule_for(glob),
+            Some(Match::Whitelist(glob)) => {
+                return Explanation {
+                    path: relative_path.to_path_buf(),
+                    included: false,
+                    rule: self.allowlist_rule_for(glob),
+                    outside_allowlist: true,
+                };
+            }
+            Some(Match::None) => {
+                return Explanation {
+                    path: relative_path.to_path_buf(),
+                    included: false,
+                    rule: None,
+                    outside_allowlist: true,
+                };
+            }
+            None => None,
+        };
+        
+        let (included, rule) = match self.matched(relative_path) {
+            Match::None => (true, allowlist_rule),
+            Match::Whitelist(glob) => (true, self.rule_for(&glob)),
+            Match::Ignore(glob) => (false, self.rule_for(&glob)),
+        };
+        
+        Explanation {
+            path: relative_path.to_path_buf(),
+            included,
+            rule,
+            outside_allowlist: false,
+        }
+    }
+    
+    fn allowlist_rule_for(&self, glob: &Glob) -> Option<IgnoreRule> {
+        let allowlist = self.allowlist.as_ref()?;
+        allowlist
+            .rules
+            .iter()
+            .rev()
+            .find(|rule| rule.pattern == glob.original())
+            .cloned()
+    }
+    
+    /// Rules from the `.cpdinclude` allowlist, if one is in use
+    pub fn allowlist_rules(&self) -> Option<&[IgnoreRule]> {
+        self.allowlist.as_ref().map(|allowlist| allowlist.rules.as_slice())
+    }
+    
+    /// Find the rule a matched glob was built from.
+    ///
+    /// When the same pattern appears more than once in a file the last one
+    /// wins, just as it does when matching.
+    fn rule_for(&self, glob: &Glob) -> Option<IgnoreRule> {
+        let source = glob.from().map(|path| PatternSource::File(path.to_path_buf()));
+        
+        self.loaded_rules()
+            .into_iter()
+            .rev()
+            .find(|rule| match &source {
+                Some(source) => &rule.source == source && rule.pattern == glob.original(),
+                None => {
+                    matches!(
+                        rule.source,
+                        PatternSource::Internal
+                            | PatternSource::Default
+                            | PatternSource::Profile(_)
+                            | PatternSource::DeployProfile(_)
+                    ) && rule.pattern == glob.original()
+                }
+            })
+    }
+    
+    /// All rules, in the order they are applied (later rules win).
+    ///
+    /// This walks the project to load the ignore files of every directory
+    /// that isn't ignored.
+    pub fn rules(&self) -> Result<Vec<IgnoreRule>> {
+        let walker = WalkDir::new(&self.project_root).min_depth(1).sort_by_file_name();
+        let dirs = walker.into_iter().filter_entry(|entry| {
+            entry.file_type().is_dir()
+                && entry
+                    .path()
+                    .strip_prefix(&self.project_root)
+                    .is_ok_and(|relative_path| !self.matched(relative_path).is_ignore())
+        });
+        for entry in dirs.filter_map(|entry| entry.ok()) {
+            if let Ok(relative_dir) = entry.path().strip_prefix(&self.project_root) {
+                self.nested_matcher(relative_dir);
+            }
+        }
+        self.check_nested()?;
+        
+        Ok(self.loaded_rules())
+    }
+    
+    /// Rules of the ignore files loaded so far, in the order they are applied
+    fn loaded_rules(&self) -> Vec<IgnoreRule> {
+        let nested = self.nested.borrow();
+        // Parents sort before their subdirectories, so deeper files come later
+        let nested_rules = nested.values().flatten().flat_map(|matcher| matcher.rules.iter());
+        
+        self.rules
+            .iter()
+            .chain(nested_rules)
+            .chain(&self.extra_rules)
+            .cloned()
+            .collect()
+    }
+    
     /// Get a closure that can be used for filtering
     pub fn filter_fn(&self) -> impl Fn(&Path) -> bool + '_ {
         move |path: &Path| self.should_include(path)
@@ -96,16 +681,17 @@ impl IgnoreFilter {
     
     /// List all patterns that would be applied
     #[allow(dead_code)]
-    pub fn list_patterns(&self) -> Vec<String> {
-        // This is a simplified version - the actual gitignore crate doesn't expose patterns directly
-        // In a real implementation, you might want to store the patterns separately
-        vec![
-            ".git/".to_string(),
-            "target/".to_string(),
-            "node_modules/".to_string(),
-            "*.pyc".to_string(),
-            "__pycache__/".to_string(),
-        ]
+    pub fn list_patterns(&self) -> Result<Vec<String>> {
+        Ok(self.rules()?.into_iter().map(|rule| rule.pattern).collect())
+    }
+}
+
+/// Trim a pattern line the same way the gitignore parser does
+fn normalize_pattern(line: &str) -> &str {
+    if line.ends_with("\\ ") {
+        line
+    } else {
+        line.trim_end()
     }
 }
 
@@ -141,6 +727,8 @@ mod tests {
     #[test]
     fn test_default_ignores() {
         let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("target")).unwrap();
+        fs::create_dir(temp_dir.path().join("__pycache__")).unwrap();
         let filter = IgnoreFilter::new(temp_dir.path()).unwrap();
         
         // Should ignore .git directory
@@ -167,6 +755,7 @@ mod tests {
         
         // Create a .cpdignore file
         fs::write(&cpdignore_path, "custom_ignore/\n*.log\ntemp_*").unwrap();
+        fs::create_dir(temp_dir.path().join("custom_ignore")).unwrap();
         
         let filter = IgnoreFilter::new(temp_dir.path()).unwrap();
         
@@ -179,12 +768,291 @@ mod tests {
         assert!(filter.should_include(&PathBuf::from("main.py")));
     }
     
+    #[test]
+    fn test_explain_and_list() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join(".cpdignore"), "# comment\n*.log\n\n!keep.log\n").unwrap();
+        
+        let filter = IgnoreFilter::new(temp_dir.path()).unwrap();
+        let cpdignore = PatternSource::File(temp_dir.path().join(".cpdignore"));
+        
+        let explanation = filter.explain(Path::new("debug.log"));
+        assert!(!explanation.included);
+        let rule = explanation.rule.unwrap();
+        assert_eq!(rule.source, cpdignore);
+        assert_eq!(rule.line, Some(2));
+        assert_eq!(rule.pattern, "*.log");
+        
+        let explanation = filter.explain(Path::new("keep.log"));
+        assert!(explanation.included);
+        assert_eq!(explanation.rule.unwrap().line, Some(4));
+        
+        let explanation = filter.explain(Path::new("__pycache__/mod.pyc"));
+        assert!(!explanation.included);
+        assert_eq!(explanation.rule.unwrap().source, PatternSource::Default);
+        
+        let explanation = filter.explain(Path::new("code.py"));
+        assert!(explanation.included);
+        assert!(explanation.rule.is_none());
+        
+        let patterns = filter.list_patterns().unwrap();
+        assert!(patterns.contains(&"*.log".to_string()));
+        assert!(patterns.contains(&"!keep.log".to_string()));
+        assert!(!patterns.contains(&"# comment".to_string()));
+    }
+    
+    #[test]
+    fn test_nested_ignore_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("lib/vendor/tests")).unwrap();
+        fs::create_dir_all(root.join("lib/vendor/docs")).unwrap();
+        fs::create_dir_all(root.join("ignored")).unwrap();
+        fs::write(root.join(".cpdignore"), "*.md\nignored/\n").unwrap();
+        fs::write(root.join("lib/vendor/.cpdignore"), "tests/\n/docs\n!README.md\n").unwrap();
+        // Never loaded, its directory is already ignored
+        fs::write(root.join("ignored/.cpdignore"), "!*\n").unwrap();
+        
+        let options = IgnoreOptions {
+            global_ignore_file: None,
+            ..IgnoreOptions::default()
+        };
+        let filter = IgnoreFilter::with_options(root, &options).unwrap();
+        
+        // Scoped to lib/vendor
+        assert!(!filter.should_include(&root.join("lib/vendor/tests/test_a.py")));
+        assert!(!filter.should_include(&root.join("lib/vendor/docs/index.rst")));
+        assert!(filter.should_include(&root.join("lib/vendor/driver.py")));
+        assert!(filter.should_include(&root.join("tests/test_main.py")));
+        
+        // Deeper files override the root
+        assert!(!filter.should_include(&root.join("NOTES.md")));
+        assert!(filter.should_include(&root.join("lib/vendor/README.md")));
+        
+        assert!(!filter.should_include(&root.join("ignored/anything.py")));
+        assert_eq!(filter.rules().unwrap().iter().filter(|r| r.pattern == "!*").count(), 0);
+    }
+    
+    #[test]
+    fn test_nested_ignore_files_load_lazily() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("lib")).unwrap();
+        fs::create_dir_all(root.join("broken")).unwrap();
+        fs::write(root.join("lib/.cpdignore"), "*.txt\n").unwrap();
+        fs::write(root.join("broken/.cpdignore"), "[\n").unwrap();
+        
+        let filter = IgnoreFilter::new(root).unwrap();
+        assert!(filter.should_include(&root.join("code.py")));
+        assert!(filter.nested.borrow().is_empty());
+        
+        assert!(!filter.should_include(&root.join("lib/notes.txt")));
+        assert!(filter.nested.borrow().contains_key(Path::new("lib")));
+        filter.check_nested().unwrap();
+        
+        // Invalid patterns are reported once the directory is reached
+        filter.should_include(&root.join("broken/code.py"));
+        assert!(matches!(filter.check_nested(), Err(CpdError::InvalidIgnorePattern { .. })));
+        
+        // Listing the rules loads every directory
+        let filter = IgnoreFilter::new(root).unwrap();
+        assert!(filter.rules().is_err());
+    }
+    
+    #[test]
+    fn test_global_ignore_file() {
+        let project = TempDir::new().unwrap();
+        let config = TempDir::new().unwrap();
+        let global_path = config.path().join("ignore");
+        fs::write(&global_path, "*.secret\n").unwrap();
+        
+        let options = IgnoreOptions {
+            global_ignore_file: Some(global_path.clone()),
+            ..IgnoreOptions::default()
+        };
+        let filter = IgnoreFilter::with_options(project.path(), &options).unwrap();
+        
+        assert!(!filter.should_include(&project.path().join("wifi.secret")));
+        assert_eq!(filter.explain(Path::new("wifi.secret")).rule.unwrap().source, PatternSource::File(global_path));
+    }
+    
+    #[test]
+    fn test_opt_out_of_gitignore_and_defaults() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join(".gitignore"), "data.json\n").unwrap();
+        
+        let options = IgnoreOptions {
+            global_ignore_file: None,
+            use_gitignore: false,
+            use_defaults: false,
+            profile: IgnoreProfile::CircuitPython,
+            extra_rules: Vec::new(),
+        };
+        let filter = IgnoreFilter::with_options(root, &options).unwrap();
+        
+        assert!(filter.should_include(&root.join("data.json")));
+        assert!(filter.should_include(&root.join(".env")));
+        
+        // VCS and cpd files are never deployed
+        assert!(!filter.should_include(&root.join(".git/config")));
+        assert!(!filter.should_include(&root.join(".gitignore")));
+        assert!(!filter.should_include(&root.join("cpd.toml")));
+        assert!(!filter.should_include(&root.join("cpd.secrets.toml")));
+    }
+    
+    #[test]
+    fn test_ignore_profiles() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        
+        let options = |profile| IgnoreOptions {
+            global_ignore_file: None,
+            profile,
+            ..IgnoreOptions::default()
+        };
+        
+        let default = IgnoreFilter::with_options(root, &options(IgnoreProfile::Default)).unwrap();
+        assert!(default.should_include(&root.join("README.md")));
+        assert!(default.should_include(&root.join("lib/adafruit_foo/examples/simpletest.py")));
+        
+        let circuitpython = IgnoreFilter::with_options(root, &options(IgnoreProfile::CircuitPython)).unwrap();
+        assert!(!circuitpython.should_include(&root.join("README.md")));
+        assert!(!circuitpython.should_include(&root.join("requirements-dev.txt")));
+        assert!(!circuitpython.should_include(&root.join("lib/adafruit_foo/examples/simpletest.py")));
+        assert!(!circuitpython.should_include(&root.join("lib/adafruit_foo/__init__.pyi")));
+        assert!(!circuitpython.should_include(&root.join(".vscode/settings.json")));
+        assert!(circuitpython.should_include(&root.join("examples/demo.py")));
+        assert!(circuitpython.should_include(&root.join("lib/adafruit_foo/__init__.py")));
+        
+        let explanation = circuitpython.explain(Path::new("docs/index.rst"));
+        assert_eq!(explanation.rule.unwrap().source, PatternSource::Profile(IgnoreProfile::CircuitPython));
+        
+        let minimal = IgnoreFilter::with_options(root, &options(IgnoreProfile::Minimal)).unwrap();
+        assert!(!minimal.should_include(&root.join("examples/demo.py")));
+        assert!(!minimal.should_include(&root.join("lib/adafruit_foo/LICENSE")));
+        assert!(minimal.should_include(&root.join("code.py")));
+        
+        // Profile patterns are skipped along with the rest of the defaults
+        let disabled = IgnoreOptions {
+            use_defaults: false,
+            ..options(IgnoreProfile::Minimal)
+        };
+        let filter = IgnoreFilter::with_options(root, &disabled).unwrap();
+        assert!(filter.should_include(&root.join("README.md")));
+    }
+    
+    #[test]
+    fn test_extra_rules_take_precedence() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::create_dir_all(root.join("lib/feather")).unwrap();
+        fs::write(root.join("lib/.cpdignore"), "feather/\n").unwrap();
+        
+        let source = PatternSource::DeployProfile("feather".to_string());
+        let options = IgnoreOptions {
+            global_ignore_file: None,
+            extra_rules: ["config.*.py", "!lib/feather/"]
+                .iter()
+                .map(|pattern| IgnoreRule {
+                    source: source.clone(),
+                    line: None,
+                    pattern: pattern.to_string(),
+                })
+                .collect(),
+            ..IgnoreOptions::default()
+        };
+        let filter = IgnoreFilter::with_options(root, &options).unwrap();
+        
+        assert!(filter.should_include(&root.join("lib/feather/pins.py")));
+        assert!(!filter.should_include(&root.join("config.qtpy.py")));
+        assert_eq!(filter.explain(Path::new("config.qtpy.py")).rule.unwrap().source, source);
+        assert_eq!(filter.rules().unwrap().last().unwrap().pattern, "!lib/feather/");
+    }
+    
+    #[test]
+    fn test_internal_patterns_cannot_be_reincluded() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join(".cpdignore"), "!cpd.toml\n").unwrap();
+        
+        let options = IgnoreOptions {
+            global_ignore_file: None,
+            extra_rules: vec![IgnoreRule {
+                source: PatternSource::DeployProfile("all".to_string()),
+                line: None,
+                pattern: "!*".to_string(),
+            }],
+            ..IgnoreOptions::default()
+        };
+        let filter = IgnoreFilter::with_options(root, &options).unwrap();
+        
+        assert!(!filter.should_include(&root.join("cpd.toml")));
+        assert!(!filter.should_include(&root.join(SECRETS_FILE_NAME)));
+        assert!(filter.should_include(&root.join("notes.tmp")));
+        assert_eq!(
+            filter.explain(Path::new(SECRETS_FILE_NAME)).rule.unwrap().source,
+            PatternSource::Internal
+        );
+        
+        assert!(is_internal(Path::new(SECRETS_FILE_NAME)));
+        assert!(is_internal(Path::new(".git/config")));
+        assert!(!is_internal(Path::new("settings.toml")));
+    }
+    
+    #[test]
+    fn test_allowlist() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        fs::write(root.join(".cpdinclude"), "code.py\nlib/\n!lib/tests/\n").unwrap();
+        
+        let filter = IgnoreFilter::new(root).unwrap();
+        
+        // Directories aren't subject to the allowlist, so walks can reach listed files
+        fs::create_dir_all(root.join("assets/fonts")).unwrap();
+        assert!(filter.should_include(&root.join("assets")));
+        
+        assert!(filter.should_include(&root.join("code.py")));
+        assert!(filter.should_include(&root.join("lib/helper.py")));
+        assert!(!filter.should_include(&root.join("lib/tests/test_helper.py")));
+        assert!(!filter.should_include(&root.join("tools/flash.sh")));
+        
+        // Ignore rules still apply inside allowed paths
+        assert!(!filter.should_include(&root.join("lib/__pycache__/helper.pyc")));
+        
+        let explanation = filter.explain(Path::new("tools/flash.sh"));
+        assert!(explanation.outside_allowlist);
+        assert!(explanation.rule.is_none());
+        
+        let explanation = filter.explain(Path::new("lib/helper.py"));
+        assert!(explanation.included);
+        assert_eq!(explanation.rule.unwrap().pattern, "lib/");
+    }
+    
     #[test]
     fn test_simple_filter() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::create_dir(temp_dir.path().join("temp")).unwrap();
         let filter = create_simple_filter(&["*.txt", "temp/"]).unwrap();
         
         assert!(!filter(&PathBuf::from("readme.txt")));
-        assert!(!filter(&PathBuf::from("temp")));
+        assert!(!filter(&temp_dir.path().join("temp")));
         assert!(filter(&PathBuf::from("main.py")));
     }
+    
+    #[test]
+    fn test_directory_patterns_skip_missing_files() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join(".cpdignore"), "build/\n").unwrap();
+        fs::create_dir(temp_dir.path().join("build")).unwrap();
+        let filter = IgnoreFilter::new(temp_dir.path()).unwrap();
+        
+        assert!(!filter.should_include(&temp_dir.path().join("build")));
+        assert!(!filter.should_include(&temp_dir.path().join("build/app.py")));
+        
+        // A file that is no longer on disk, like one being cleaned up from
+        // the board, is matched as a file
+        assert!(filter.should_include(&temp_dir.path().join("lib/build")));
+        assert!(filter.explain(Path::new("lib/build")).included);
+    }
 }
@@ -0,0 +1,299 @@
+use crate::error::{CpdError, Result};
+use crate::file_ops::{hash_bytes, hash_file, DeployedFile};
+use crate::manifest::board_path_string;
+use crate::storage::BoardStorage;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Directory inside the project where cpd keeps local state
+pub const STATE_DIR_NAME: &str = ".cpd";
+
+/// Persistent record of a deployment in progress.
+///
+/// The plan is written before any file is copied and every completed file
+/// is recorded as it finishes, so an interrupted deployment can be resumed
+/// with `cpd deploy --resume`. Completed files are appended to a log next
+/// to the journal, so recording one doesn't rewrite the whole plan. The
+/// journal is removed once a deployment completes without failures.
+#[derive(Debug)]
+pub struct DeployJournal {
+    path: PathBuf,
+    state: JournalState,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalState {
+    board: PathBuf,
+    started_at: String,
+    files: Vec<PlannedFile>,
+    completed: BTreeSet<String>,
+}
+
+/// A file in the deploy plan, with the source hash at planning time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlannedFile {
+    /// Project-relative path using `/` separators
+    pub path: String,
+    /// Board path, when it differs from the project path
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl PlannedFile {
+    /// Board-relative path the file is written to
+    pub fn target(&self) -> &str {
+        self.target.as_deref().unwrap_or(&self.path)
+    }
+}
+
+impl DeployJournal {
+    /// Location of the journal for a project
+    pub fn path_for(project_dir: &Path) -> PathBuf {
+        project_dir.join(STATE_DIR_NAME).join("state")
+    }
+
+    /// Start a new journal for deploying `files` from `project_dir` to `board`.
+    ///
+    /// `target_path` maps a project-relative path to its path on the board.
+    pub fn create(
+        project_dir: &Path,
+        board: &Path,
+        files: &[PathBuf],
+        target_path: &dyn Fn(&Path) -> PathBuf,
+    ) -> Result<Self> {
+        let mut planned = Vec::with_capacity(files.len());
+        for file in files {
+            let relative_path = file.strip_prefix(project_dir).unwrap_or(file);
+            let path = board_path_string(relative_path);
+            let target = board_path_string(&target_path(relative_path));
+            planned.push(PlannedFile {
+                target: (target != path).then_some(target),
+                path,
+                size: fs::metadata(file)?.len(),
+                sha256: hash_file(file)?,
+            });
+        }
+
+        let journal = Self {
+            path: Self::path_for(project_dir),
+            state: JournalState {
+                board: board.to_path_buf(),
+                started_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+                files: planned,
+                completed: BTreeSet::new(),
+            },
+        };
+        journal.save()?;
+
+        Ok(journal)
+    }
+
+    /// Load the journal of an interrupted deployment, if there is one
+    pub fn load(project_dir: &Path) -> Result<Option<Self>> {
+        let path = Self::path_for(project_dir);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let content = fs::read_to_string(&path)?;
+        let state = serde_json::from_str(&content).map_err(|e| CpdError::Configuration {
+            message: format!("Invalid deployment journal {}: {}", path.display(), e),
+        })?;
+
+        let mut journal = Self { path, state };
+        match fs::read_to_string(journal.log_path()) {
+            // A line cut short by the interruption just means that file is copied again
+            Ok(log) => journal
+                .state
+                .completed
+                .extend(log.lines().filter_map(|line| serde_json::from_str::<String>(line).ok())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e.into()),
+        }
+
+        Ok(Some(journal))
+    }
+
+    /// Board the interrupted deployment was targeting
+    pub fn board(&self) -> &Path {
+        &self.state.board
+    }
+
+    pub fn started_at(&self) -> &str {
+        &self.state.started_at
+    }
+
+    pub fn planned_files(&self) -> &[PlannedFile] {
+        &self.state.files
+    }
+
+    #[allow(dead_code)]
+    pub fn completed_count(&self) -> usize {
+        self.state.completed.len()
+    }
+
+    /// Check the files recorded as completed against the board.
+    ///
+    /// Returns the files that are confirmed written, ready to be recorded in
+    /// the deployment manifest. Completed files whose board copy is missing
+    /// or doesn't match the planned hash are returned to the remaining set.
+    pub fn verify_completed(&mut self, board: &dyn BoardStorage) -> Result<Vec<DeployedFile>> {
+        let mut verified = Vec::new();
+        let mut invalid = Vec::new();
+
+        for file in &self.state.files {
+            if !self.state.completed.contains(&file.path) {
+                continue;
+            }
+
+            match board.read(Path::new(file.target())) {
+                Ok(content) if hash_bytes(&content) == file.sha256 => verified.push(DeployedFile {
+                    relative_path: PathBuf::from(file.target()),
+                    size: file.size,
+                    sha256: file.sha256.clone(),
+                }),
+                _ => invalid.push(file.path.clone()),
+            }
+        }
+
+        if !invalid.is_empty() {
+            for path in &invalid {
+                self.state.completed.remove(path);
+            }
+            self.save()?;
+        }
+
+        Ok(verified)
+    }
+
+    /// Source paths of the planned files that haven't been completed yet
+    pub fn remaining_files(&self, project_dir: &Path) -> Vec<PathBuf> {
+        self.state
+            .files
+            .iter()
+            .filter(|file| !self.state.completed.contains(&file.path))
+            .map(|file| project_dir.join(&file.path))
+            .collect()
+    }
+
+    /// Record a file as written to the board
+    pub fn mark_completed(&mut self, relative_path: &Path) -> Result<()> {
+        let path = board_path_string(relative_path);
+
+        let mut log = OpenOptions::new().create(true).append(true).open(self.log_path())?;
+        // One JSON string per line, so any path round-trips
+        writeln!(log, "{}", serde_json::Value::String(path.clone()))?;
+
+        self.state.completed.insert(path);
+        Ok(())
+    }
+
+    /// Remove the journal after a deployment completed successfully
+    pub fn finish(self) -> Result<()> {
+        remove_if_exists(&self.path)?;
+        remove_if_exists(&self.log_path())
+    }
+
+    /// Log of files completed since the journal was last saved
+    fn log_path(&self) -> PathBuf {
+        self.path.with_extension("completed")
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let content = serde_json::to_string_pretty(&self.state).map_err(|e| CpdError::Configuration {
+            message: format!("Failed to serialize deployment journal: {}", e),
+        })?;
+
+        // Write to a temporary file first so an interruption never leaves a truncated journal
+        let temp_path = self.path.with_extension("tmp");
+        fs::write(&temp_path, content)?;
+        fs::rename(&temp_path, &self.path)?;
+
+        // The journal now lists every completed file itself
+        remove_if_exists(&self.log_path())
+    }
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Ok(()) => Ok(()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalBoard;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_journal_resume_flow() {
+        let project = TempDir::new().unwrap();
+        let board = TempDir::new().unwrap();
+
+        let files: Vec<PathBuf> = ["code.py", "lib/helper.py", "boot.py", "src/main.py"]
+            .iter()
+            .map(|name| {
+                let path = project.path().join(name);
+                fs::create_dir_all(path.parent().unwrap()).unwrap();
+                fs::write(&path, format!("# {}", name)).unwrap();
+                path
+            })
+            .collect();
+
+        // src/ is installed at the board root
+        let target_path = |path: &Path| path.strip_prefix("src").unwrap_or(path).to_path_buf();
+        let mut journal = DeployJournal::create(project.path(), board.path(), &files, &target_path).unwrap();
+
+        // code.py and src/main.py were copied correctly, lib/helper.py was recorded but is corrupt on the board
+        fs::write(board.path().join("code.py"), "# code.py").unwrap();
+        fs::write(board.path().join("main.py"), "# src/main.py").unwrap();
+        fs::create_dir_all(board.path().join("lib")).unwrap();
+        fs::write(board.path().join("lib/helper.py"), "garbage").unwrap();
+        journal.mark_completed(Path::new("code.py")).unwrap();
+        journal.mark_completed(Path::new("lib/helper.py")).unwrap();
+        journal.mark_completed(Path::new("src/main.py")).unwrap();
+        drop(journal);
+
+        // Completed files are only appended to the log
+        let log = fs::read_to_string(project.path().join(".cpd/state.completed")).unwrap();
+        assert_eq!(log, "\"code.py\"\n\"lib/helper.py\"\n\"src/main.py\"\n");
+        let state: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(DeployJournal::path_for(project.path())).unwrap()).unwrap();
+        assert_eq!(state["completed"], serde_json::json!([]));
+
+        let mut journal = DeployJournal::load(project.path()).unwrap().unwrap();
+        assert_eq!(journal.board(), board.path());
+        assert_eq!(journal.completed_count(), 3);
+
+        let verified = journal.verify_completed(&LocalBoard::new(board.path())).unwrap();
+        assert_eq!(verified.len(), 2);
+        assert_eq!(verified[0].relative_path, PathBuf::from("code.py"));
+        assert_eq!(verified[1].relative_path, PathBuf::from("main.py"));
+
+        let remaining = journal.remaining_files(project.path());
+        assert_eq!(remaining, vec![project.path().join("lib/helper.py"), project.path().join("boot.py")]);
+
+        // Checking the board saved the journal and folded in the log
+        assert!(!project.path().join(".cpd/state.completed").exists());
+        let mut journal = DeployJournal::load(project.path()).unwrap().unwrap();
+        assert_eq!(journal.completed_count(), 2);
+
+        journal.mark_completed(Path::new("lib/helper.py")).unwrap();
+        journal.finish().unwrap();
+        assert!(DeployJournal::load(project.path()).unwrap().is_none());
+        assert!(!project.path().join(".cpd/state.completed").exists());
+    }
+}
@@ -0,0 +1,130 @@
+use crate::config::LayoutConfig;
+use crate::error::{CpdError, Result};
+use std::path::{Component, Path, PathBuf};
+
+/// Maps project-relative paths to board-relative paths.
+///
+/// With no configuration every file keeps its project path. A source root
+/// (`src/ -> /`) and mapping rules (`assets/fonts -> /fonts`) move whole
+/// directories; the longest matching rule wins. When a source root is set,
+/// files outside it and outside every mapping aren't deployed at all.
+#[derive(Debug, Clone, Default)]
+pub struct PathMapper {
+    /// `(project dir, board dir)` pairs, longest project dir first
+    mappings: Vec<(PathBuf, PathBuf)>,
+    has_source_root: bool,
+}
+
+impl PathMapper {
+    pub fn new(config: &LayoutConfig) -> Result<Self> {
+        let mut mappings = Vec::new();
+
+        if let Some(source_root) = &config.source_root {
+            mappings.push((project_dir(source_root)?, PathBuf::new()));
+        }
+        for (from, to) in &config.map {
+            mappings.push((project_dir(from)?, board_dir(to)?));
+        }
+
+        mappings.sort_by_key(|(from, _)| std::cmp::Reverse(from.components().count()));
+
+        Ok(Self {
+            mappings,
+            has_source_root: config.source_root.is_some(),
+        })
+    }
+
+    /// Whether a project file is part of the board layout
+    pub fn covers(&self, relative_path: &Path) -> bool {
+        !self.has_source_root || self.rule_for(relative_path).is_some()
+    }
+
+    /// Board path for a project-relative path
+    pub fn map(&self, relative_path: &Path) -> PathBuf {
+        match self.rule_for(relative_path) {
+            Some((from, to)) => to.join(relative_path.strip_prefix(from).unwrap()),
+            None => relative_path.to_path_buf(),
+        }
+    }
+
+    fn rule_for(&self, relative_path: &Path) -> Option<&(PathBuf, PathBuf)> {
+        self.mappings.iter().find(|(from, _)| relative_path.starts_with(from))
+    }
+}
+
+/// Validate the project side of a mapping, e.g. `src/`
+fn project_dir(path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() || path.components().any(|c| matches!(c, Component::ParentDir)) {
+        return Err(CpdError::Configuration {
+            message: format!("Layout source must be a directory inside the project: {}", path.display()),
+        });
+    }
+
+    Ok(path.components().collect())
+}
+
+/// Normalize the board side of a mapping; `/fonts` and `fonts` are the same place
+fn board_dir(path: &Path) -> Result<PathBuf> {
+    if path.components().any(|c| matches!(c, Component::ParentDir | Component::Prefix(_))) {
+        return Err(CpdError::Configuration {
+            message: format!("Layout target must be a directory on the board: {}", path.display()),
+        });
+    }
+
+    Ok(path.components().filter(|c| matches!(c, Component::Normal(_))).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layout(source_root: Option<&str>, map: &[(&str, &str)]) -> PathMapper {
+        PathMapper::new(&LayoutConfig {
+            source_root: source_root.map(PathBuf::from),
+            map: map.iter().map(|(from, to)| (PathBuf::from(from), PathBuf::from(to))).collect(),
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_identity_by_default() {
+        let mapper = PathMapper::default();
+
+        assert!(mapper.covers(Path::new("tools/flash.sh")));
+        assert_eq!(mapper.map(Path::new("lib/helper.py")), PathBuf::from("lib/helper.py"));
+    }
+
+    #[test]
+    fn test_source_root_and_mappings() {
+        let mapper = layout(Some("src/"), &[("assets/fonts", "/fonts"), ("src/vendor", "/lib")]);
+
+        assert_eq!(mapper.map(Path::new("src/code.py")), PathBuf::from("code.py"));
+        assert_eq!(mapper.map(Path::new("assets/fonts/big.bdf")), PathBuf::from("fonts/big.bdf"));
+        // The more specific rule wins
+        assert_eq!(mapper.map(Path::new("src/vendor/neopixel.mpy")), PathBuf::from("lib/neopixel.mpy"));
+
+        assert!(mapper.covers(Path::new("src/code.py")));
+        assert!(!mapper.covers(Path::new("tools/flash.sh")));
+        assert!(!mapper.covers(Path::new("assets/images/logo.bmp")));
+    }
+
+    #[test]
+    fn test_mappings_without_source_root() {
+        let mapper = layout(None, &[("assets/fonts", "fonts")]);
+
+        assert!(mapper.covers(Path::new("code.py")));
+        assert_eq!(mapper.map(Path::new("code.py")), PathBuf::from("code.py"));
+        assert_eq!(mapper.map(Path::new("assets/fonts/big.bdf")), PathBuf::from("fonts/big.bdf"));
+    }
+
+    #[test]
+    fn test_invalid_mappings() {
+        let config = |from: &str, to: &str| LayoutConfig {
+            source_root: None,
+            map: [(PathBuf::from(from), PathBuf::from(to))].into(),
+        };
+
+        assert!(PathMapper::new(&config("../shared", "/")).is_err());
+        assert!(PathMapper::new(&config("assets", "/../fonts")).is_err());
+    }
+}
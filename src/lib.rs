@@ -3,3 +3,19 @@ pub mod error;
 pub mod file_ops;
 pub mod ignore;
 pub mod board;
+pub mod config;
+pub mod deploy;
+pub mod git;
+pub mod journal;
+pub mod layout;
+pub mod manifest;
+pub mod mdns;
+pub mod output;
+pub mod profile;
+pub mod progress;
+pub mod protect;
+pub mod serial;
+pub mod storage;
+pub mod template;
+pub mod version_file;
+pub mod web;
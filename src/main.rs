@@ -3,140 +3,244 @@ mod error;
 mod file_ops;
 mod ignore;
 mod board;
+mod config;
+mod deploy;
+mod git;
+mod journal;
+mod layout;
+mod manifest;
+mod mdns;
+mod output;
+mod profile;
+mod progress;
+mod protect;
+mod serial;
+mod storage;
+mod template;
+mod version_file;
+mod web;
 
-use cli::Cli;
+use cli::{AliasCommand, Cli, Command, IgnoreCommand};
 use error::{CpdError, Result};
-use file_ops::FileOperations;
+use file_ops::RetryPolicy;
 use ignore::IgnoreFilter;
-use board::BoardDetector;
+use mdns::Discovery;
+use board::{BoardDetector, CircuitPythonBoard};
+use config::{is_valid_alias, BoardIdentity, ProjectConfig, UserConfig};
+use deploy::{BoardSelector, DeployMode, DeployPlan, DeployReport, Deployer};
+use output::{Event, JsonObserver};
+use profile::DeployProfile;
+use progress::ProgressBarObserver;
+use std::path::Path;
 
 fn main() {
-    if let Err(e) = run() {
-        eprintln!("Error: {}", e);
-        std::process::exit(1);
+    let cli = Cli::parse_args();
+    
+    if let Err(e) = run(&cli) {
+        if cli.is_human() {
+            eprintln!("Error: {}", e);
+        } else {
+            output::emit(&Event::Error { error: &e });
+        }
+        std::process::exit(e.exit_code());
     }
 }
 
-fn run() -> Result<()> {
-    let cli = Cli::parse_args();
-    
+fn run(cli: &Cli) -> Result<()> {
     // Validate CLI arguments
     cli.validate()?;
     
+    // JSON output keeps stdout to events only
+    let human = cli.is_human();
+    let verbose = cli.verbose && human;
+    
     // Handle list-boards command
     if cli.list_boards {
-        let detector = BoardDetector::new(cli.verbose);
+        let detector = BoardDetector::new(verbose)
+            .with_discovery(Some(Discovery::default()))
+            .with_aliases(UserConfig::load()?.aliases);
+        if !human {
+            let boards = detector.detect_boards()?;
+            output::emit(&Event::Boards { boards: &boards });
+            return Ok(());
+        }
         return detector.list_boards();
     }
     
+    if let Some(Command::Alias { action }) = &cli.command {
+        return run_alias_command(action, cli);
+    }
+    
     let project_dir = cli.project_dir();
     
-    if cli.verbose {
+    if verbose {
         println!("Project directory: {}", project_dir.display());
     }
     
-    // Set up ignore filter
-    let ignore_filter = IgnoreFilter::new(&project_dir)?;
+    // Handle ignore inspection commands
+    if let Some(Command::Ignore { action }) = &cli.command {
+        let config = ProjectConfig::load(&project_dir)?;
+        let profile = DeployProfile::select(&config, cli.profile.as_deref(), None)?;
+        let ignore_filter = deploy::project_ignore_filter(
+            &project_dir,
+            &config,
+            profile.as_ref(),
+            !cli.no_gitignore,
+            !cli.no_default_ignores,
+        )?;
+        return run_ignore_command(action, &ignore_filter, &project_dir, human);
+    }
     
-    // Detect or validate board
-    let detector = BoardDetector::new(cli.verbose);
-    let board = if let Some(board_path) = &cli.board_path {
-        // Validate manually specified board
-        if !detector.is_circuitpython_board(board_path) {
-            if cli.force {
-                if cli.verbose {
-                    println!("Warning: {} doesn't appear to be a CircuitPython board, but --force was specified", board_path.display());
-                }
-            } else {
-                return Err(CpdError::InvalidBoardPath {
-                    path: format!("{} doesn't appear to be a CircuitPython board", board_path.display()),
-                });
-            }
-        }
-        
-        board::CircuitPythonBoard::new(
-            board_path.clone(),
-            "Manual".to_string(),
-            None,
-            0,
-            0,
-        )
+    let mode = if cli.resume() {
+        DeployMode::Resume
+    } else if cli.dry_run {
+        DeployMode::DryRun
     } else {
-        // Auto-detect boards
-        let boards = detector.detect_boards()?;
-        
-        if boards.is_empty() {
-            return Err(CpdError::BoardNotFound);
-        }
-        
-        if boards.len() == 1 {
-            boards.into_iter().next().unwrap()
-        } else if cli.assume_yes {
-            return Err(CpdError::MultipleBoardsFound);
-        } else {
-            detector.select_board(&boards)?.clone()
-        }
+        DeployMode::Deploy
     };
     
-    if cli.verbose {
-        println!("Target board: {} at {}", board.display_name(), board.path.display());
+    let mut deployer = Deployer::builder(&project_dir)
+        .mode(mode)
+        .gitignore(!cli.no_gitignore)
+        .default_ignores(!cli.no_default_ignores)
+        .force(cli.force)
+        .overwrite_protected(cli.overwrite_protected)
+        .network(cli.network)
+        .retry_policy(RetryPolicy {
+            max_retries: cli.retries,
+            ..RetryPolicy::default()
+        })
+        .verbose(verbose);
+    if let Some(board_path) = &cli.board_path {
+        deployer = deployer.board(BoardSelector::from_location(board_path, &project_dir)?);
+    }
+    if let Some(profile) = &cli.profile {
+        deployer = deployer.profile(profile);
     }
-    
-    // Create backup if requested
     if let Some(backup_dir) = &cli.backup_dir {
-        if cli.verbose {
-            println!("Creating backup at: {}", backup_dir.display());
+        deployer = deployer.backup(backup_dir);
+    }
+    if cli.verify {
+        deployer = deployer.verify(cli.verify_retries);
+    }
+    if let Some(version_file) = &cli.version_file {
+        deployer = deployer.version_file(version_file);
+    }
+    
+    if human {
+        if !cli.assume_yes {
+            deployer = deployer.select_board(|boards| {
+                let selected = BoardDetector::new(false).select_board(boards)?;
+                Ok(boards.iter().position(|board| board.path == selected.path).unwrap_or_default())
+            });
+        }
+        deployer = deployer.observer(ProgressBarObserver::new(verbose));
+        let assume_yes = cli.assume_yes;
+        let profile_requested = cli.profile.is_some();
+        deployer = deployer.confirm(move |plan| {
+            show_plan(plan, verbose, profile_requested);
+            assume_yes || plan.dry_run || confirm(plan)
+        });
+    } else {
+        deployer = deployer
+            .confirm(|plan| {
+                output::emit(&Event::Plan(plan));
+                true
+            })
+            .observer(JsonObserver::default());
+    }
+    
+    let report = deployer.build().run()?;
+    
+    // Display results
+    if human {
+        show_report(&report, verbose);
+    } else {
+        output::emit(&Event::Result {
+            dry_run: report.dry_run,
+            result: &report.result,
+        });
+    }
+    
+    // A deployment where any file failed is an error, even though the rest were written
+    if report.is_success() {
+        Ok(())
+    } else {
+        Err(CpdError::PartialCopyFailure {
+            failed: report.result.files_failed,
+            total: report.result.files_copied + report.result.files_failed,
+        })
+    }
+}
+
+/// Print what is about to happen
+fn show_plan(plan: &DeployPlan, verbose: bool, profile_requested: bool) {
+    if verbose {
+        println!("Target board: {} at {}", plan.board.display_name(), plan.board.path.display());
+    }
+    
+    if let Some(profile) = &plan.profile {
+        match &plan.board.board_id {
+            Some(board_id) if !profile_requested => println!("Using profile '{}' for board {}", profile, board_id),
+            _ => println!("Using profile '{}'", profile),
         }
-        
-        let file_ops = FileOperations::new(cli.verbose);
-        file_ops.create_backup(&board.path, backup_dir)?;
+    }
+    
+    if plan.dry_run && plan.board.read_only {
+        println!("Warning: {} is mounted read-only, deploying would fail", plan.board.path.display());
+    }
+    
+    if let Some(resume) = &plan.resume {
+        println!(
+            "Resuming deployment started at {}: {} of {} files already on the board",
+            resume.started_at, resume.completed, resume.planned
+        );
     }
     
     // Show deployment plan
-    if cli.verbose || cli.dry_run {
+    if verbose || plan.dry_run {
         println!("\nDeployment plan:");
-        println!("  Source: {}", project_dir.display());
-        println!("  Target: {}", board.path.display());
+        println!("  Source: {}", plan.project_dir.display());
+        println!("  Target: {}", plan.board.path.display());
         
-        if cli.dry_run {
+        if plan.dry_run {
             println!("  Mode: DRY RUN (no files will be copied)");
         }
         
+        if verbose && !plan.protected.is_empty() {
+            println!("  Protected: {}", plan.protected.join(", "));
+        }
+        
         println!();
     }
+}
+
+/// Ask before writing to the board
+fn confirm(plan: &DeployPlan) -> bool {
+    use std::io::{self, Write};
     
-    // Confirm deployment unless --yes is specified
-    if !cli.assume_yes && !cli.dry_run {
-        println!("Deploy to {}? [y/N]", board.display_name());
-        
-        use std::io::{self, Write};
-        print!("> ");
-        io::stdout().flush().unwrap();
-        
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
-        
-        if !input.trim().to_lowercase().starts_with('y') {
-            println!("Deployment cancelled.");
-            return Ok(());
-        }
-    }
+    println!("Deploy to {}? [y/N]", plan.board.display_name());
+    print!("> ");
+    io::stdout().flush().unwrap();
     
-    // Perform deployment
-    let file_ops = FileOperations::new(cli.verbose);
-    let filter_fn = ignore_filter.filter_fn();
+    let mut input = String::new();
+    io::stdin().read_line(&mut input).unwrap();
     
-    let result = file_ops.copy_directory_contents(
-        &project_dir,
-        &board.path,
-        &filter_fn,
-        cli.dry_run,
-    )?;
+    input.trim().to_lowercase().starts_with('y')
+}
+
+fn show_report(report: &DeployReport, verbose: bool) {
+    let result = &report.result;
+    
+    if let Some(version_file) = &report.version_file
+        && report.dry_run
+    {
+        println!("Would write version file: {}", report.board.path.join(version_file).display());
+    }
     
-    // Display results
     println!("\n{}", result.summary());
     
-    if result.files_copied == 0 && result.files_failed == 0 {
+    if result.files_copied == 0 && result.files_failed == 0 && result.protected_files.is_empty() {
         println!("\n💡 No files to deploy. This could happen if:");
         println!("   • All files are excluded by .cpdignore patterns");
         println!("   • The project directory is empty");
@@ -144,6 +248,20 @@ fn run() -> Result<()> {
         println!("\nTip: Use --verbose --dry-run to see what files would be included.");
     }
     
+    if !result.protected_files.is_empty() {
+        println!("\n🔒 Protected files left untouched (use --overwrite-protected to overwrite):");
+        for file in &result.protected_files {
+            println!("  {}", file.display());
+        }
+    }
+    
+    if !result.retried_files.is_empty() {
+        println!("\n🔁 Files that needed retries:");
+        for (file, retries) in &result.retried_files {
+            println!("  {}: {} {}", file.display(), retries, if *retries == 1 { "retry" } else { "retries" });
+        }
+    }
+    
     if !result.failed_files.is_empty() {
         println!("\n❌ Failed files:");
         for (file, error) in &result.failed_files {
@@ -151,21 +269,21 @@ fn run() -> Result<()> {
         }
     }
     
-    if !cli.dry_run {
-        if result.files_copied > 0 {
+    if !report.dry_run {
+        if result.files_copied > 0 && result.is_success() {
             println!("\n✅ Deployment completed successfully!");
             
-            if cli.verbose {
+            if verbose {
                 println!("📁 Files deployed:");
                 // We could track and show which files were deployed here
                 // For now, show general info
             }
             
             // Show board space after deployment
-            if let Ok(boards) = detector.detect_boards() {
-                if let Some(updated_board) = boards.iter().find(|b| b.path == board.path) {
-                    println!("💾 Board space: {}", updated_board.format_space());
-                }
+            if let Ok(boards) = BoardDetector::new(false).detect_boards()
+                && let Some(updated_board) = boards.iter().find(|b| b.path == report.board.path)
+            {
+                println!("💾 Board space: {}", updated_board.format_space());
             }
             
             println!("\n🚀 Your CircuitPython project is ready to run!");
@@ -173,6 +291,148 @@ fn run() -> Result<()> {
     } else {
         println!("\n🔍 Dry run completed. Use the command without --dry-run to deploy.");
     }
+}
+
+fn run_alias_command(action: &AliasCommand, cli: &Cli) -> Result<()> {
+    let human = cli.is_human();
+    let path = UserConfig::default_path().ok_or_else(|| CpdError::Configuration {
+        message: "Could not find the user configuration directory".to_string(),
+    })?;
+    let mut config = UserConfig::load_from(&path)?;
+    
+    match action {
+        AliasCommand::List if !human => {
+            output::emit(&Event::Aliases { aliases: &config.aliases });
+        }
+        AliasCommand::List => {
+            if config.aliases.is_empty() {
+                println!("No board aliases registered. Add one with `cpd alias add NAME`.");
+            } else {
+                println!("Board aliases in {}:", path.display());
+                for (name, identity) in &config.aliases {
+                    println!("  {}: {}", name, identity);
+                }
+            }
+        }
+        AliasCommand::Add { name, serial, board_id, marker } => {
+            if !is_valid_alias(name) {
+                return Err(CpdError::Configuration {
+                    message: format!("Alias '{}' may only contain letters, digits, '-' and '_'", name),
+                });
+            }
+            
+            let identity = match (serial, board_id, marker) {
+                (Some(serial), _, _) => BoardIdentity::Serial { serial: serial.clone() },
+                (None, Some(board_id), Some(marker)) => BoardIdentity::Marker {
+                    board_id: board_id.clone(),
+                    marker: marker.clone(),
+                },
+                _ => alias_target(cli)?.register_identity(name)?,
+            };
+            
+            if human {
+                println!("Registered '{}' as {}", name, identity);
+            }
+            config.aliases.insert(name.clone(), identity);
+            config.save_to(&path)?;
+        }
+        AliasCommand::Remove { name } => {
+            if config.aliases.remove(name).is_none() {
+                return Err(CpdError::UnknownBoardAlias { name: name.clone() });
+            }
+            config.save_to(&path)?;
+            
+            if human {
+                println!("Removed alias '{}'", name);
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+/// The board `cpd alias add` names: the one given with --board, or a connected drive
+fn alias_target(cli: &Cli) -> Result<CircuitPythonBoard> {
+    let detector = BoardDetector::new(cli.verbose && cli.is_human());
+    
+    let Some(board_path) = &cli.board_path else {
+        let boards = detector.detect_boards()?;
+        if boards.len() > 1 && (cli.assume_yes || !cli.is_human()) {
+            return Err(CpdError::MultipleBoardsFound);
+        }
+        return detector.select_board(&boards).cloned();
+    };
+    
+    if BoardSelector::is_remote(board_path) || !detector.is_circuitpython_board(board_path) {
+        return Err(CpdError::InvalidBoardPath {
+            path: format!("{} is not a CircuitPython drive", board_path.display()),
+        });
+    }
+    Ok(CircuitPythonBoard::new(board_path.clone(), "Manual".to_string(), None, 0, 0))
+}
+
+fn run_ignore_command(action: &IgnoreCommand, ignore_filter: &IgnoreFilter, project_dir: &Path, human: bool) -> Result<()> {
+    match action {
+        IgnoreCommand::List if !human => {
+            output::emit(&Event::IgnoreRules {
+                rules: &ignore_filter.rules()?,
+                allowlist: ignore_filter.allowlist_rules(),
+            });
+        }
+        IgnoreCommand::List => {
+            println!("Ignore rules for {} (later rules take precedence):", project_dir.display());
+            
+            let mut current_source = None;
+            let rules = ignore_filter.rules()?;
+            for rule in &rules {
+                if current_source != Some(&rule.source) {
+                    println!("\n  {}", rule.source);
+                    current_source = Some(&rule.source);
+                }
+                
+                match rule.line {
+                    Some(line) => println!("    {:>4}: {}", line, rule.pattern),
+                    None => println!("          {}", rule.pattern),
+                }
+            }
+            
+            if let Some(allowlist) = ignore_filter.allowlist_rules() {
+                println!("\nAllowlist (only these paths are deployed):");
+                for rule in allowlist {
+                    println!("    {:>4}: {}", rule.line.unwrap_or_default(), rule.pattern);
+                }
+            }
+        }
+        IgnoreCommand::Explain { path } => {
+            // Accept paths relative to the current directory as well as to the project
+            let relative_path = match (path.canonicalize(), project_dir.canonicalize()) {
+                (Ok(path), Ok(root)) if path.starts_with(&root) => path.strip_prefix(&root).unwrap().to_path_buf(),
+                _ => path.clone(),
+            };
+            
+            let explanation = ignore_filter.explain(&relative_path);
+            ignore_filter.check_nested()?;
+            if !human {
+                output::emit(&Event::Explain(&explanation));
+                return Ok(());
+            }
+            
+            let status = if explanation.included { "✓ included" } else { "✗ excluded" };
+            println!("{}: {}", explanation.path.display(), status);
+            
+            match &explanation.rule {
+                Some(rule) if explanation.outside_allowlist => {
+                    println!("  removed from the allowlist by pattern '{}' at {}", rule.pattern, rule.location());
+                }
+                None if explanation.outside_allowlist => println!("  not listed in .cpdinclude"),
+                Some(rule) if rule.is_whitelist() => {
+                    println!("  re-included by pattern '{}' at {}", rule.pattern, rule.location());
+                }
+                Some(rule) => println!("  matched pattern '{}' at {}", rule.pattern, rule.location()),
+                None => println!("  no ignore pattern matches this path"),
+            }
+        }
+    }
     
     Ok(())
 }
@@ -180,6 +440,7 @@ fn run() -> Result<()> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use file_ops::FileOperations;
     use tempfile::TempDir;
     use std::fs;
     
@@ -200,13 +461,11 @@ mod tests {
         assert!(ignore_filter.is_ok());
         
         // Test file operations creation
-        let file_ops = FileOperations::new(false);
+        let _file_ops = FileOperations::new();
         
         // Test board detector creation
-        let detector = BoardDetector::new(false);
+        let _detector = BoardDetector::new(false);
         
         // These should not panic and should create valid instances
-        drop(file_ops);
-        drop(detector);
     }
 }
@@ -0,0 +1,197 @@
+use crate::error::{CpdError, Result};
+use crate::file_ops::DeployedFile;
+use crate::storage::{temp_path, BoardStorage};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Name of the manifest file written to the board root after a deploy
+pub const MANIFEST_FILE_NAME: &str = ".cpd_manifest.json";
+
+/// Record of every file cpd has deployed to a board.
+///
+/// Files listed here are owned by cpd; anything else on the board was
+/// created by the user or the device and is never touched by cleanup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeployManifest {
+    pub cpd_version: String,
+    pub git_commit: Option<String>,
+    pub deployed_at: String,
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Board-relative path using `/` separators
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+impl DeployManifest {
+    pub fn new(git_commit: Option<String>) -> Self {
+        Self {
+            cpd_version: env!("CARGO_PKG_VERSION").to_string(),
+            git_commit,
+            deployed_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            files: Vec::new(),
+        }
+    }
+
+    /// Path of the manifest file for a board mounted at `board_root`
+    pub fn path_for(board_root: &Path) -> PathBuf {
+        board_root.join(MANIFEST_FILE_NAME)
+    }
+
+    /// Load the manifest from a board, if one has been written before.
+    ///
+    /// A manifest that can't be parsed, e.g. one cut short when the board
+    /// was unplugged, is treated as missing so it never blocks a deploy;
+    /// `warn` is told that it was ignored.
+    pub fn load(board: &dyn BoardStorage, warn: &dyn Fn(&str)) -> Result<Option<Self>> {
+        let relative_path = Path::new(MANIFEST_FILE_NAME);
+        if !board.exists(relative_path) {
+            return Ok(None);
+        }
+
+        let content = board.read(relative_path)?;
+        match serde_json::from_slice(&content) {
+            Ok(manifest) => Ok(Some(manifest)),
+            Err(e) => {
+                let path = Self::path_for(&board.root());
+                warn(&format!("Ignoring invalid deployment manifest {}: {}", path.display(), e));
+                Ok(None)
+            }
+        }
+    }
+
+    /// Write the manifest to the board root
+    pub fn write(&self, board: &dyn BoardStorage) -> Result<()> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| CpdError::Configuration {
+            message: format!("Failed to serialize deployment manifest: {}", e),
+        })?;
+
+        // Write to a temporary file first so an interruption never leaves a truncated manifest
+        let path = Path::new(MANIFEST_FILE_NAME);
+        let temp_path = temp_path(path);
+        board.write(&temp_path, content.as_bytes())?;
+        board.rename(&temp_path, path)?;
+        Ok(())
+    }
+
+    /// Build the manifest for a deploy, carrying over entries from the
+    /// previous manifest that were not redeployed this time.
+    ///
+    /// Those files are still on the board and still owned by cpd, so they
+    /// stay listed until a clean removes them.
+    pub fn merged(previous: Option<&DeployManifest>, deployed: &[DeployedFile], git_commit: Option<String>) -> Self {
+        let mut entries: BTreeMap<String, ManifestEntry> = previous
+            .map(|manifest| {
+                manifest
+                    .files
+                    .iter()
+                    .map(|entry| (entry.path.clone(), entry.clone()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        for file in deployed {
+            let path = board_path_string(&file.relative_path);
+            entries.insert(
+                path.clone(),
+                ManifestEntry {
+                    path,
+                    size: file.size,
+                    sha256: file.sha256.clone(),
+                },
+            );
+        }
+
+        let mut manifest = Self::new(git_commit);
+        manifest.files = entries.into_values().collect();
+        manifest
+    }
+
+    /// Look up the entry for a board-relative path
+    #[allow(dead_code)]
+    pub fn entry(&self, relative_path: &Path) -> Option<&ManifestEntry> {
+        let path = board_path_string(relative_path);
+        self.files.iter().find(|entry| entry.path == path)
+    }
+
+    /// Check whether cpd owns the given board-relative path
+    #[allow(dead_code)]
+    pub fn contains(&self, relative_path: &Path) -> bool {
+        self.entry(relative_path).is_some()
+    }
+}
+
+/// Convert a relative path to the `/`-separated form stored in the manifest
+pub fn board_path_string(relative_path: &Path) -> String {
+    relative_path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::LocalBoard;
+    use tempfile::TempDir;
+
+    fn deployed(path: &str, sha256: &str) -> DeployedFile {
+        DeployedFile {
+            relative_path: PathBuf::from(path),
+            size: 4,
+            sha256: sha256.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_manifest_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let board = LocalBoard::new(temp_dir.path());
+        assert!(DeployManifest::load(&board, &|_| panic!()).unwrap().is_none());
+
+        let manifest = DeployManifest::merged(
+            None,
+            &[deployed("code.py", "aaaa"), deployed("lib/helper.py", "bbbb")],
+            Some("abc123".to_string()),
+        );
+        manifest.write(&board).unwrap();
+        assert!(!temp_dir.path().join(".cpd_manifest.json.cpdtmp").exists());
+
+        let loaded = DeployManifest::load(&board, &|_| panic!()).unwrap().unwrap();
+        assert_eq!(loaded.git_commit.as_deref(), Some("abc123"));
+        assert_eq!(loaded.cpd_version, env!("CARGO_PKG_VERSION"));
+        assert_eq!(loaded.files.len(), 2);
+        assert!(loaded.contains(Path::new("lib/helper.py")));
+        assert!(!loaded.contains(Path::new("settings.toml")));
+    }
+
+    #[test]
+    fn test_truncated_manifest_is_ignored() {
+        let temp_dir = TempDir::new().unwrap();
+        let board = LocalBoard::new(temp_dir.path());
+        std::fs::write(temp_dir.path().join(MANIFEST_FILE_NAME), "{\"cpd_version\": \"0.1").unwrap();
+
+        let warnings = std::cell::RefCell::new(Vec::new());
+        let loaded = DeployManifest::load(&board, &|message| warnings.borrow_mut().push(message.to_string())).unwrap();
+        assert!(loaded.is_none());
+        assert_eq!(warnings.borrow().len(), 1);
+        assert!(warnings.borrow()[0].starts_with("Ignoring invalid deployment manifest"));
+    }
+
+    #[test]
+    fn test_merge_keeps_previous_entries() {
+        let previous = DeployManifest::merged(None, &[deployed("code.py", "old"), deployed("old.py", "cccc")], None);
+        let manifest = DeployManifest::merged(Some(&previous), &[deployed("code.py", "new")], None);
+
+        assert_eq!(manifest.files.len(), 2);
+        assert_eq!(manifest.entry(Path::new("code.py")).unwrap().sha256, "new");
+        assert!(manifest.contains(Path::new("old.py")));
+    }
+}
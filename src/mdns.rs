@@ -0,0 +1,454 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::time::{Duration, Instant};
+
+/// mDNS service type advertised by boards running the Web Workflow
+pub const SERVICE_TYPE: &str = "_circuitpython._tcp.local";
+
+/// The mDNS multicast group
+const MDNS_ADDR: SocketAddr = SocketAddr::new(IpAddr::V4(Ipv4Addr::new(224, 0, 0, 251)), 5353);
+
+const TYPE_A: u16 = 1;
+const TYPE_PTR: u16 = 12;
+const TYPE_AAAA: u16 = 28;
+const TYPE_SRV: u16 = 33;
+const CLASS_IN: u16 = 1;
+/// Question class bit asking responders to answer the querying socket directly
+const UNICAST_RESPONSE: u16 = 0x8000;
+
+/// Where to send the mDNS query and how long to collect answers
+#[derive(Debug, Clone)]
+pub struct Discovery {
+    pub target: SocketAddr,
+    pub timeout: Duration,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Self {
+            target: MDNS_ADDR,
+            timeout: Duration::from_secs(1),
+        }
+    }
+}
+
+/// A board that answered the `_circuitpython._tcp` query
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveredBoard {
+    /// Service instance name, e.g. `Adafruit Feather ESP32-S3`
+    pub instance: String,
+    /// e.g. `cpy-1a2b3c.local`
+    pub hostname: String,
+    pub ip: IpAddr,
+    pub port: u16,
+}
+
+impl DiscoveredBoard {
+    /// Base URL of the board's Web Workflow, by IP so no mDNS resolver is needed
+    pub fn url(&self) -> String {
+        let host = match self.ip {
+            IpAddr::V4(ip) => ip.to_string(),
+            IpAddr::V6(ip) => format!("[{}]", ip),
+        };
+        if self.port == 80 {
+            format!("http://{}", host)
+        } else {
+            format!("http://{}:{}", host, self.port)
+        }
+    }
+}
+
+impl Discovery {
+    /// Ask for `_circuitpython._tcp` services and collect the boards that
+    /// answer before the timeout
+    pub fn discover(&self) -> io::Result<Vec<DiscoveredBoard>> {
+        let bind_ip = if self.target.ip().is_loopback() {
+            IpAddr::V4(Ipv4Addr::LOCALHOST)
+        } else {
+            IpAddr::V4(Ipv4Addr::UNSPECIFIED)
+        };
+        let socket = UdpSocket::bind((bind_ip, 0))?;
+        socket.set_multicast_ttl_v4(255)?;
+        socket.send_to(&query_packet(), self.target)?;
+
+        let deadline = Instant::now() + self.timeout;
+        let mut answers = Answers::default();
+        let mut buffer = [0u8; 9000];
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            socket.set_read_timeout(Some(remaining))?;
+
+            match socket.recv_from(&mut buffer) {
+                Ok((len, from)) => {
+                    // Anything that isn't a well-formed response is someone else's traffic
+                    if let Some(records) = parse_response(&buffer[..len]) {
+                        answers.add(records, from.ip());
+                    }
+                }
+                Err(e) if matches!(e.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(answers.boards())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum RecordData {
+    Ptr(String),
+    Srv { port: u16, target: String },
+    Address(IpAddr),
+    Other,
+}
+
+#[derive(Debug)]
+struct Record {
+    name: String,
+    data: RecordData,
+}
+
+/// Records gathered from every response, keyed by lowercased name
+#[derive(Default)]
+struct Answers {
+    instances: Vec<String>,
+    services: HashMap<String, (String, u16)>,
+    addresses: HashMap<String, IpAddr>,
+    senders: HashMap<String, IpAddr>,
+}
+
+impl Answers {
+    fn add(&mut self, records: Vec<Record>, sender: IpAddr) {
+        for record in records {
+            let name = record.name.to_ascii_lowercase();
+            match record.data {
+                RecordData::Ptr(instance) if name == SERVICE_TYPE => {
+                    self.senders.insert(instance.to_ascii_lowercase(), sender);
+                    if !self.instances.iter().any(|known| known.eq_ignore_ascii_case(&instance)) {
+                        self.instances.push(instance);
+                    }
+                }
+                RecordData::Srv { port, target } => {
+                    self.services.insert(name, (target, port));
+                }
+                // Prefer IPv4, which every Web Workflow board has
+                RecordData::Address(ip) if ip.is_ipv4() || !self.addresses.contains_key(&name) => {
+                    self.addresses.insert(name, ip);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn boards(self) -> Vec<DiscoveredBoard> {
+        self.instances
+            .iter()
+            .filter_map(|instance| {
+                let key = instance.to_ascii_lowercase();
+                let (hostname, port) = self.services.get(&key)?;
+                // Without an address record, the board is where the answer came from
+                let ip = self
+                    .addresses
+                    .get(&hostname.to_ascii_lowercase())
+                    .or_else(|| self.senders.get(&key))?;
+
+                Some(DiscoveredBoard {
+                    instance: instance
+                        .strip_suffix(&format!(".{}", SERVICE_TYPE))
+                        .unwrap_or(instance)
+                        .to_string(),
+                    hostname: hostname.clone(),
+                    ip: *ip,
+                    port: *port,
+                })
+            })
+            .collect()
+    }
+}
+
+/// A query for PTR records of `SERVICE_TYPE`
+fn query_packet() -> Vec<u8> {
+    // ID 0, standard query, one question
+    let mut packet = vec![0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0];
+    write_name(&mut packet, SERVICE_TYPE);
+    packet.extend_from_slice(&TYPE_PTR.to_be_bytes());
+    packet.extend_from_slice(&(CLASS_IN | UNICAST_RESPONSE).to_be_bytes());
+    packet
+}
+
+fn write_name(packet: &mut Vec<u8>, name: &str) {
+    for label in name.split('.').filter(|label| !label.is_empty()) {
+        packet.push(label.len() as u8);
+        packet.extend_from_slice(label.as_bytes());
+    }
+    packet.push(0);
+}
+
+/// The answer, authority and additional records of a DNS response
+fn parse_response(packet: &[u8]) -> Option<Vec<Record>> {
+    let header = packet.get(..12)?;
+    let count = |index: usize| u16::from_be_bytes([header[index], header[index + 1]]) as usize;
+    // Only responses have the QR bit set
+    if header[2] & 0x80 == 0 {
+        return None;
+    }
+
+    let mut pos = 12;
+    for _ in 0..count(4) {
+        let (_, next) = read_name(packet, pos)?;
+        pos = next + 4;
+    }
+
+    let mut records = Vec::new();
+    for _ in 0..count(6) + count(8) + count(10) {
+        let (name, next) = read_name(packet, pos)?;
+        let fixed = packet.get(next..next + 10)?;
+        let record_type = u16::from_be_bytes([fixed[0], fixed[1]]);
+        let length = u16::from_be_bytes([fixed[8], fixed[9]]) as usize;
+        let start = next + 10;
+        let rdata = packet.get(start..start + length)?;
+
+        let data = match record_type {
+            TYPE_PTR => RecordData::Ptr(read_name(packet, start)?.0),
+            TYPE_SRV if length >= 6 => RecordData::Srv {
+                port: u16::from_be_bytes([rdata[4], rdata[5]]),
+                target: read_name(packet, start + 6)?.0,
+            },
+            TYPE_A if length == 4 => RecordData::Address(IpAddr::V4(Ipv4Addr::new(rdata[0], rdata[1], rdata[2], rdata[3]))),
+            TYPE_AAAA if length == 16 => {
+                let octets: [u8; 16] = rdata.try_into().ok()?;
+                RecordData::Address(IpAddr::V6(Ipv6Addr::from(octets)))
+            }
+            _ => RecordData::Other,
+        };
+        records.push(Record { name, data });
+        pos = start + length;
+    }
+
+    Some(records)
+}
+
+/// Read a possibly compressed name at `pos`, returning it with the position
+/// just after it
+fn read_name(packet: &[u8], mut pos: usize) -> Option<(String, usize)> {
+    let mut labels = Vec::new();
+    let mut end = None;
+    // Bounds compression pointer loops in malformed packets
+    let mut jumps = 0;
+
+    loop {
+        let length = *packet.get(pos)? as usize;
+        match length {
+            0 => {
+                end.get_or_insert(pos + 1);
+                break;
+            }
+            _ if length & 0xC0 == 0xC0 => {
+                let offset = ((length & 0x3F) << 8) | *packet.get(pos + 1)? as usize;
+                end.get_or_insert(pos + 2);
+                jumps += 1;
+                if jumps > 32 {
+                    return None;
+                }
+                pos = offset;
+            }
+            _ => {
+                let label = packet.get(pos + 1..pos + 1 + length)?;
+                labels.push(String::from_utf8_lossy(label).into_owned());
+                pos += 1 + length;
+            }
+        }
+    }
+
+    Some((labels.join("."), end?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::BoardDetector;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::path::PathBuf;
+    use std::thread::{self, JoinHandle};
+
+    const INSTANCE: &str = "Adafruit Feather ESP32-S3";
+    const HOSTNAME: &str = "cpy-1a2b3c.local";
+
+    fn write_record(packet: &mut Vec<u8>, name: &str, record_type: u16, rdata: &[u8]) {
+        write_name(packet, name);
+        packet.extend_from_slice(&record_type.to_be_bytes());
+        packet.extend_from_slice(&CLASS_IN.to_be_bytes());
+        packet.extend_from_slice(&120u32.to_be_bytes());
+        packet.extend_from_slice(&(rdata.len() as u16).to_be_bytes());
+        packet.extend_from_slice(rdata);
+    }
+
+    /// The response a Web Workflow board sends: PTR and SRV answers, with
+    /// the address as an additional record
+    fn response_packet(port: u16) -> Vec<u8> {
+        let instance = format!("{}.{}", INSTANCE, SERVICE_TYPE);
+        let mut packet = vec![0, 0, 0x84, 0, 0, 0, 0, 2, 0, 0, 0, 1];
+
+        // Points back at the service type inside the PTR record's name
+        let mut ptr = Vec::new();
+        ptr.push(INSTANCE.len() as u8);
+        ptr.extend_from_slice(INSTANCE.as_bytes());
+        ptr.extend_from_slice(&[0xC0, 12]);
+        write_record(&mut packet, SERVICE_TYPE, TYPE_PTR, &ptr);
+
+        let mut srv = vec![0, 0, 0, 0];
+        srv.extend_from_slice(&port.to_be_bytes());
+        write_name(&mut srv, HOSTNAME);
+        write_record(&mut packet, &instance, TYPE_SRV, &srv);
+
+        write_record(&mut packet, HOSTNAME, TYPE_A, &[127, 0, 0, 1]);
+        packet
+    }
+
+    /// A loopback mDNS responder that answers one `_circuitpython._tcp` query
+    fn start_responder(port: u16) -> (SocketAddr, JoinHandle<()>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = socket.local_addr().unwrap();
+
+        let handle = thread::spawn(move || {
+            let mut buffer = [0u8; 512];
+            let (len, from) = socket.recv_from(&mut buffer).unwrap();
+            let (name, next) = read_name(&buffer[..len], 12).unwrap();
+            assert_eq!(name, SERVICE_TYPE);
+            assert_eq!(&buffer[next..next + 2], &TYPE_PTR.to_be_bytes());
+            socket.send_to(&response_packet(port), from).unwrap();
+        });
+
+        (address, handle)
+    }
+
+    /// A loopback server answering one request for `/cp/version.json`
+    fn start_version_server() -> (u16, JoinHandle<()>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            assert!(request_line.starts_with("GET /cp/version.json "));
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).unwrap();
+                if header.trim_end().is_empty() {
+                    break;
+                }
+            }
+
+            let body = serde_json::json!({
+                "web_api_version": 4,
+                "version": "9.0.0",
+                "build_date": "2024-03-19",
+                "board_name": "Adafruit Feather ESP32-S3 No PSRAM",
+                "mcu_name": "ESP32S3",
+                "board_id": "adafruit_feather_esp32s3_nopsram",
+                "creator_id": 9114,
+                "creation_id": 1,
+                "hostname": "cpy-1a2b3c",
+                "port": port,
+                "ip": "127.0.0.1",
+            })
+            .to_string();
+            write!(
+                stream,
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+        });
+
+        (port, handle)
+    }
+
+    fn loopback_discovery(target: SocketAddr) -> Discovery {
+        Discovery {
+            target,
+            timeout: Duration::from_millis(300),
+        }
+    }
+
+    #[test]
+    fn test_read_name() {
+        let mut packet = Vec::new();
+        write_name(&mut packet, SERVICE_TYPE);
+        packet.extend_from_slice(&[3, b'c', b'p', b'y', 0xC0, 0]);
+
+        assert_eq!(read_name(&packet, 0), Some((SERVICE_TYPE.to_string(), 27)));
+        assert_eq!(read_name(&packet, 27), Some((format!("cpy.{}", SERVICE_TYPE), 33)));
+        // A pointer to itself
+        assert_eq!(read_name(&[0xC0, 0], 0), None);
+        assert_eq!(read_name(&[5, b'a'], 0), None);
+    }
+
+    #[test]
+    fn test_parse_response() {
+        assert!(parse_response(&query_packet()).is_none());
+        assert!(parse_response(&[0x84]).is_none());
+
+        let records = parse_response(&response_packet(8080)).unwrap();
+        let data: Vec<&RecordData> = records.iter().map(|record| &record.data).collect();
+        assert_eq!(
+            data,
+            vec![
+                &RecordData::Ptr(format!("{}.{}", INSTANCE, SERVICE_TYPE)),
+                &RecordData::Srv {
+                    port: 8080,
+                    target: HOSTNAME.to_string()
+                },
+                &RecordData::Address(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_discover() {
+        let (address, responder) = start_responder(8080);
+        let boards = loopback_discovery(address).discover().unwrap();
+        responder.join().unwrap();
+
+        assert_eq!(
+            boards,
+            vec![DiscoveredBoard {
+                instance: INSTANCE.to_string(),
+                hostname: HOSTNAME.to_string(),
+                ip: IpAddr::V4(Ipv4Addr::LOCALHOST),
+                port: 8080,
+            }]
+        );
+        assert_eq!(boards[0].url(), "http://127.0.0.1:8080");
+    }
+
+    #[test]
+    fn test_detect_network_boards() {
+        let (port, version_server) = start_version_server();
+        let (address, responder) = start_responder(port);
+
+        let boards = BoardDetector::new(false)
+            .with_discovery(Some(loopback_discovery(address)))
+            .detect_boards()
+            .unwrap();
+        responder.join().unwrap();
+        version_server.join().unwrap();
+
+        let board = boards.iter().find(|board| board.network.is_some()).unwrap();
+        let network = board.network.as_ref().unwrap();
+        assert_eq!(board.path, PathBuf::from(format!("http://127.0.0.1:{}", port)));
+        assert_eq!(board.board_id.as_deref(), Some("adafruit_feather_esp32s3_nopsram"));
+        assert_eq!(network.hostname, HOSTNAME);
+        assert_eq!(network.ip, IpAddr::V4(Ipv4Addr::LOCALHOST));
+        assert_eq!(network.version.as_deref(), Some("9.0.0"));
+        assert!(!board.read_only);
+    }
+}
@@ -0,0 +1,175 @@
+use crate::board::CircuitPythonBoard;
+use crate::config::BoardIdentity;
+use crate::deploy::DeployPlan;
+use crate::error::CpdError;
+use crate::file_ops::CopyResult;
+use crate::ignore::{Explanation, IgnoreRule};
+use crate::progress::{Batch, DeployObserver, Operation, SkipReason};
+use clap::ValueEnum;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// How results are written to stdout
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Progress bars and messages for people
+    #[default]
+    Human,
+    /// One JSON object per line (NDJSON), for scripts and editor integrations
+    Json,
+}
+
+/// A line of `--output json` output.
+///
+/// Every event has an `event` field naming its type; the other field names
+/// are stable so scripts can rely on them.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// Result of `--list-boards`
+    Boards { boards: &'a [CircuitPythonBoard] },
+    /// Files about to be deployed, sent before any of them is written
+    Plan(&'a DeployPlan),
+    /// Outcome of one file, sent as the deployment progresses
+    File(&'a FileEvent<'a>),
+    /// Totals at the end of a deployment
+    Result {
+        dry_run: bool,
+        #[serde(flatten)]
+        result: &'a CopyResult,
+    },
+    /// Result of `cpd ignore list`
+    IgnoreRules {
+        rules: &'a [IgnoreRule],
+        allowlist: Option<&'a [IgnoreRule]>,
+    },
+    /// Result of `cpd ignore explain`
+    Explain(&'a Explanation),
+    /// Result of `cpd alias list`
+    Aliases {
+        aliases: &'a BTreeMap<String, BoardIdentity>,
+    },
+    /// The command failed
+    Error { error: &'a CpdError },
+}
+
+/// Outcome of a single deployed file, with project- and board-relative paths
+#[derive(Debug, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum FileEvent<'a> {
+    Copied {
+        path: &'a Path,
+        target: &'a Path,
+        bytes: u64,
+        retries: u32,
+    },
+    WouldCopy {
+        path: &'a Path,
+        target: &'a Path,
+    },
+    Protected {
+        path: &'a Path,
+        target: &'a Path,
+    },
+    Failed {
+        path: &'a Path,
+        target: &'a Path,
+        error: &'a CpdError,
+    },
+}
+
+/// Streams a `file` event for every file of a deployment. Backups and
+/// cleanup aren't reported.
+#[derive(Debug, Default)]
+pub struct JsonObserver {
+    deploying: AtomicBool,
+}
+
+impl DeployObserver for JsonObserver {
+    fn planned(&self, batch: &Batch) {
+        self.deploying.store(batch.operation == Operation::Deploy, Ordering::Relaxed);
+    }
+
+    fn copied(&self, path: &Path, target: &Path, bytes: u64, retries: u32) {
+        self.emit_file(&FileEvent::Copied {
+            path,
+            target,
+            bytes,
+            retries,
+        });
+    }
+
+    fn skipped(&self, path: &Path, target: &Path, reason: SkipReason) {
+        self.emit_file(&match reason {
+            SkipReason::DryRun { .. } => FileEvent::WouldCopy { path, target },
+            SkipReason::Protected => FileEvent::Protected { path, target },
+        });
+    }
+
+    fn failed(&self, path: &Path, target: &Path, error: &CpdError) {
+        self.emit_file(&FileEvent::Failed { path, target, error });
+    }
+}
+
+impl JsonObserver {
+    fn emit_file(&self, event: &FileEvent) {
+        if self.deploying.load(Ordering::Relaxed) {
+            emit(&Event::File(event));
+        }
+    }
+}
+
+/// Write `event` to stdout as a single line of JSON
+pub fn emit(event: &Event) {
+    let mut stdout = io::stdout().lock();
+    // Events only hold serializable data, and a closed stdout isn't worth failing over
+    if serde_json::to_writer(&mut stdout, event).is_ok() {
+        let _ = writeln!(stdout);
+        let _ = stdout.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_file_event_json() {
+        let event = FileEvent::Copied {
+            path: Path::new("src/code.py"),
+            target: Path::new("code.py"),
+            bytes: 42,
+            retries: 0,
+        };
+        let value = serde_json::to_value(Event::File(&event)).unwrap();
+
+        assert_eq!(value["event"], "file");
+        assert_eq!(value["status"], "copied");
+        assert_eq!(value["path"], "src/code.py");
+        assert_eq!(value["target"], "code.py");
+        assert_eq!(value["bytes"], 42);
+    }
+
+    #[test]
+    fn test_result_json() {
+        let result = CopyResult {
+            files_copied: 1,
+            files_failed: 1,
+            bytes_copied: 10,
+            failed_files: vec![(PathBuf::from("lib/big.mpy"), "Disk full or insufficient space".to_string())],
+            deployed_files: Vec::new(),
+            retried_files: vec![(PathBuf::from("code.py"), 2)],
+            protected_files: Vec::new(),
+        };
+        let value = serde_json::to_value(Event::Result { dry_run: false, result: &result }).unwrap();
+
+        assert_eq!(value["event"], "result");
+        assert_eq!(value["files_copied"], 1);
+        assert_eq!(value["failed_files"][0]["path"], "lib/big.mpy");
+        assert_eq!(value["retried_files"][0]["retries"], 2);
+    }
+}
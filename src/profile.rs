@@ -0,0 +1,260 @@
+use crate::config::{ProfileConfig, ProjectConfig};
+use crate::error::{CpdError, Result};
+use crate::ignore::{self, IgnoreRule, PatternSource};
+use crate::layout::PathMapper;
+use std::collections::BTreeMap;
+use std::path::{Component, Path, PathBuf};
+
+/// File CircuitPython runs on startup
+pub const ENTRY_FILE_NAME: &str = "code.py";
+
+/// A deploy profile from `cpd.toml`, tailoring the project to one kind of board
+#[derive(Debug, Clone)]
+pub struct DeployProfile {
+    pub name: String,
+    config: ProfileConfig,
+}
+
+impl DeployProfile {
+    /// Pick the profile to deploy with.
+    ///
+    /// A profile named with `--profile` always wins; otherwise the first
+    /// profile listing the board's ID is used. Returns `None` when neither
+    /// applies, in which case the project is deployed as is.
+    pub fn select(config: &ProjectConfig, requested: Option<&str>, board_id: Option<&str>) -> Result<Option<Self>> {
+        if let Some(name) = requested {
+            let Some(profile) = config.profiles.get(name) else {
+                let available: Vec<&str> = config.profiles.keys().map(String::as_str).collect();
+                return Err(CpdError::Configuration {
+                    message: format!(
+                        "Unknown profile '{}'. Profiles in cpd.toml: {}",
+                        name,
+                        if available.is_empty() { "none".to_string() } else { available.join(", ") }
+                    ),
+                });
+            };
+            return Self::new(name, profile).map(Some);
+        }
+
+        let Some(board_id) = board_id else {
+            return Ok(None);
+        };
+
+        config
+            .profiles
+            .iter()
+            .find(|(_, profile)| profile.board_ids.iter().any(|id| id == board_id))
+            .map(|(name, profile)| Self::new(name, profile))
+            .transpose()
+    }
+
+    /// Validate and normalize the paths of the profile's `entry` and `files`
+    fn new(name: &str, config: &ProfileConfig) -> Result<Self> {
+        let mut config = config.clone();
+        config.entry = config.entry.map(|entry| project_file(name, &entry)).transpose()?;
+        config.files = config
+            .files
+            .iter()
+            .map(|(from, to)| Ok((project_file(name, from)?, board_file(name, to)?)))
+            .collect::<Result<_>>()?;
+
+        Ok(Self {
+            name: name.to_string(),
+            config,
+        })
+    }
+
+    /// The profile's exclude patterns followed by its includes as `!pattern` rules
+    pub fn ignore_rules(&self) -> Vec<IgnoreRule> {
+        let excludes = self.config.exclude.iter().cloned();
+        let includes = self.config.include.iter().map(|pattern| format!("!{}", pattern));
+
+        excludes
+            .chain(includes)
+            .map(|pattern| IgnoreRule {
+                source: PatternSource::DeployProfile(self.name.clone()),
+                line: None,
+                pattern,
+            })
+            .collect()
+    }
+
+    /// Project files installed under another name, keyed by project-relative source path
+    pub fn renames(&self) -> BTreeMap<PathBuf, PathBuf> {
+        let mut renames = self.config.files.clone();
+        if let Some(entry) = &self.config.entry {
+            renames.insert(entry.clone(), PathBuf::from(ENTRY_FILE_NAME));
+        }
+        renames
+    }
+
+    /// Adjust a list of files to deploy for this profile.
+    ///
+    /// Renamed files are always deployed, even if ignore rules exclude
+    /// them, and project files that `layout` would put on the same board
+    /// path are dropped so the profile's version wins.
+    pub fn plan_files(&self, project_dir: &Path, files: Vec<PathBuf>, layout: &PathMapper) -> Result<Vec<PathBuf>> {
+        let renames = self.renames();
+
+        for source in renames.keys() {
+            if !project_dir.join(source).is_file() {
+                return Err(CpdError::Configuration {
+                    message: format!(
+                        "Profile '{}' installs {}, which doesn't exist in {}",
+                        self.name,
+                        source.display(),
+                        project_dir.display()
+                    ),
+                });
+            }
+        }
+
+        let mut planned: Vec<PathBuf> = files
+            .into_iter()
+            .filter(|file| {
+                let relative_path = file.strip_prefix(project_dir).unwrap_or(file);
+                renames.contains_key(relative_path) || !renames.values().any(|target| *target == layout.map(relative_path))
+            })
+            .collect();
+
+        for source in renames.keys() {
+            let path = project_dir.join(source);
+            if !planned.contains(&path) {
+                planned.push(path);
+            }
+        }
+
+        Ok(planned)
+    }
+}
+
+/// Validate a file the profile installs, which must be inside the project
+/// and not one of the files cpd never deploys
+fn project_file(profile: &str, path: &Path) -> Result<PathBuf> {
+    if path.is_absolute() || path.components().any(|c| matches!(c, Component::ParentDir)) {
+        return Err(CpdError::Configuration {
+            message: format!("Profile '{}' installs {}, which is outside the project", profile, path.display()),
+        });
+    }
+
+    let path: PathBuf = path.components().filter(|c| matches!(c, Component::Normal(_))).collect();
+    if ignore::is_internal(&path) {
+        return Err(CpdError::Configuration {
+            message: format!("Profile '{}' installs {}, which is never deployed", profile, path.display()),
+        });
+    }
+
+    Ok(path)
+}
+
+/// Normalize the board path a file is installed as; `/config.py` and `config.py` are the same place
+fn board_file(profile: &str, path: &Path) -> Result<PathBuf> {
+    let normalized: PathBuf = path.components().filter(|c| matches!(c, Component::Normal(_))).collect();
+    if normalized.as_os_str().is_empty()
+        || path.components().any(|c| matches!(c, Component::ParentDir | Component::Prefix(_)))
+    {
+        return Err(CpdError::Configuration {
+            message: format!("Profile '{}' installs a file as {}, which is not a file on the board", profile, path.display()),
+        });
+    }
+
+    Ok(normalized)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn config() -> ProjectConfig {
+        let mut config = ProjectConfig::default();
+        config.profiles.insert(
+            "feather".to_string(),
+            ProfileConfig {
+                board_ids: vec!["adafruit_feather_esp32s3_nopsram".to_string()],
+                entry: Some(PathBuf::from("code_feather.py")),
+                include: vec!["lib/feather/".to_string()],
+                exclude: vec!["config.*.py".to_string()],
+                files: [(PathBuf::from("config.feather.py"), PathBuf::from("config.py"))].into(),
+            },
+        );
+        config.profiles.insert("qtpy".to_string(), ProfileConfig::default());
+        config
+    }
+
+    #[test]
+    fn test_select_profile() {
+        let config = config();
+
+        let selected = DeployProfile::select(&config, None, Some("adafruit_feather_esp32s3_nopsram")).unwrap();
+        assert_eq!(selected.unwrap().name, "feather");
+
+        let selected = DeployProfile::select(&config, Some("qtpy"), Some("adafruit_feather_esp32s3_nopsram")).unwrap();
+        assert_eq!(selected.unwrap().name, "qtpy");
+
+        assert!(DeployProfile::select(&config, None, Some("raspberry_pi_pico")).unwrap().is_none());
+        assert!(DeployProfile::select(&config, None, None).unwrap().is_none());
+        assert!(DeployProfile::select(&config, Some("pico"), None).is_err());
+    }
+
+    #[test]
+    fn test_plan_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let root = temp_dir.path();
+        for name in ["code.py", "code_feather.py", "config.py", "config.feather.py", "lib/helper.py"] {
+            fs::create_dir_all(root.join(name).parent().unwrap()).unwrap();
+            fs::write(root.join(name), name).unwrap();
+        }
+
+        let profile = DeployProfile::select(&config(), Some("feather"), None).unwrap().unwrap();
+        // config.feather.py was excluded by the profile's own pattern
+        let files = vec![
+            root.join("code.py"),
+            root.join("code_feather.py"),
+            root.join("config.py"),
+            root.join("lib/helper.py"),
+        ];
+
+        let planned = profile.plan_files(root, files, &PathMapper::default()).unwrap();
+
+        assert_eq!(
+            planned,
+            vec![root.join("code_feather.py"), root.join("lib/helper.py"), root.join("config.feather.py")]
+        );
+        assert_eq!(profile.renames()[Path::new("code_feather.py")], PathBuf::from("code.py"));
+        assert_eq!(profile.ignore_rules()[1].pattern, "!lib/feather/");
+    }
+
+    #[test]
+    fn test_profile_paths_are_validated() {
+        let profile = |entry: &str, files: &[(&str, &str)]| ProfileConfig {
+            entry: Some(PathBuf::from(entry)),
+            files: files.iter().map(|(from, to)| (PathBuf::from(from), PathBuf::from(to))).collect(),
+            ..ProfileConfig::default()
+        };
+        let select = |config: ProfileConfig| {
+            let mut project = ProjectConfig::default();
+            project.profiles.insert("test".to_string(), config);
+            DeployProfile::select(&project, Some("test"), None)
+        };
+
+        let selected = select(profile("./code_feather.py", &[("config.feather.py", "/lib/config.py")]))
+            .unwrap()
+            .unwrap();
+        let renames = selected.renames();
+        assert_eq!(renames[Path::new("code_feather.py")], PathBuf::from("code.py"));
+        assert_eq!(renames[Path::new("config.feather.py")], PathBuf::from("lib/config.py"));
+
+        assert!(select(profile("../code.py", &[])).is_err());
+        assert!(select(profile("/home/user/code.py", &[])).is_err());
+        assert!(select(profile("code.py", &[("lib/../../secrets.py", "secrets.py")])).is_err());
+        assert!(select(profile("code.py", &[("config.py", "../config.py")])).is_err());
+        assert!(select(profile("code.py", &[("config.py", "/")])).is_err());
+
+        // cpd's own files stay off the board even when renamed
+        assert!(select(profile("code.py", &[("cpd.secrets.toml", "s.toml")])).is_err());
+        assert!(select(profile("cpd.toml", &[])).is_err());
+        assert!(select(profile("code.py", &[(".git/config", "config.txt")])).is_err());
+    }
+}
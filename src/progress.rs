@@ -0,0 +1,308 @@
+use crate::error::CpdError;
+use crate::file_ops::format_bytes;
+use crate::template::RenderedTemplate;
+use indicatif::{ProgressBar, ProgressStyle};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+/// What a batch of files is being processed for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    /// Copying the project to the board
+    Deploy,
+    /// Copying the board to a backup directory
+    Backup,
+    /// Removing files that are no longer part of the project from the board
+    Clean,
+}
+
+/// A batch of files an operation is about to work through
+#[derive(Debug)]
+pub struct Batch<'a> {
+    pub operation: Operation,
+    pub from: &'a Path,
+    pub to: &'a Path,
+    pub total: usize,
+    pub dry_run: bool,
+}
+
+/// Why a file wasn't written or removed
+#[derive(Debug)]
+pub enum SkipReason<'a> {
+    /// Dry run; the rendered template is given for templates
+    DryRun { rendered: Option<&'a RenderedTemplate> },
+    /// The file exists on the board and is protected
+    Protected,
+}
+
+/// Why a written file didn't pass verification
+#[derive(Debug)]
+pub enum VerifyFailure<'a> {
+    /// The file on the board differs from the source
+    Mismatch,
+    /// The file couldn't be read back, or the source couldn't be read
+    Unreadable(&'a io::Error),
+}
+
+/// Receives progress from `FileOperations` as it copies, backs up and cleans.
+///
+/// Paths are relative to the batch's `from` directory, targets to its `to`
+/// directory. Every method has an empty default, so implementations only
+/// need the events they care about.
+pub trait DeployObserver {
+    fn planned(&self, _batch: &Batch) {}
+    fn started(&self, _path: &Path) {}
+    fn copied(&self, _path: &Path, _target: &Path, _bytes: u64, _retries: u32) {}
+    fn removed(&self, _target: &Path) {}
+    fn skipped(&self, _path: &Path, _target: &Path, _reason: SkipReason) {}
+    fn failed(&self, _path: &Path, _target: &Path, _error: &CpdError) {}
+    fn finished(&self, _operation: Operation) {}
+
+    /// Writing `target` failed with a transient error; retry number `retry`
+    /// follows after `delay`
+    fn retrying(&self, _target: &Path, _error: &io::Error, _retry: u32, _delay: Duration) {}
+    /// The board at `board` went away; the next write waits for it to come back
+    fn waiting_for_board(&self, _board: &Path) {}
+    /// `target` was read back and matches the source
+    fn verified(&self, _target: &Path) {}
+    /// Verification attempt `attempt` (1-based) of `target` failed; the file
+    /// is rewritten while attempts remain
+    fn verify_failed(&self, _target: &Path, _attempt: u32, _failure: VerifyFailure) {}
+    /// cpd wrote one of its own files, such as the deployment manifest or the
+    /// version file; `path` includes the board location
+    fn generated(&self, _path: &Path) {}
+    /// Something looks wrong, but the deployment carries on
+    fn warning(&self, _message: &str) {}
+}
+
+/// Ignores every event, for tests and embedding
+#[derive(Debug, Default)]
+pub struct SilentObserver;
+
+impl DeployObserver for SilentObserver {}
+
+/// Terminal output for the CLI: a progress bar while copying, the file list
+/// of a dry run, and per-file messages in verbose mode
+#[derive(Debug, Default)]
+pub struct ProgressBarObserver {
+    verbose: bool,
+    state: Mutex<BatchState>,
+}
+
+#[derive(Debug, Default)]
+struct BatchState {
+    operation: Option<Operation>,
+    from: PathBuf,
+    to: PathBuf,
+    dry_run: bool,
+    progress: Option<ProgressBar>,
+    files_copied: usize,
+    bytes_copied: u64,
+}
+
+impl ProgressBarObserver {
+    pub fn new(verbose: bool) -> Self {
+        Self {
+            verbose,
+            state: Mutex::default(),
+        }
+    }
+
+    fn state(&self) -> MutexGuard<'_, BatchState> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn advance(&self) {
+        if let Some(pb) = &self.state().progress {
+            pb.inc(1);
+        }
+    }
+
+    /// Print a line to stdout above the progress bar, if one is showing
+    fn println(&self, line: &str) {
+        match &self.state().progress {
+            Some(pb) => pb.println(line),
+            None => println!("{}", line),
+        }
+    }
+
+    /// Print a line to stderr without tearing the progress bar
+    fn eprintln(&self, line: &str) {
+        match &self.state().progress {
+            Some(pb) => pb.suspend(|| eprintln!("{}", line)),
+            None => eprintln!("{}", line),
+        }
+    }
+
+    /// Where a batch target is, for messages
+    fn destination(&self, target: &Path) -> PathBuf {
+        self.state().to.join(target)
+    }
+}
+
+impl DeployObserver for ProgressBarObserver {
+    fn planned(&self, batch: &Batch) {
+        let progress = (!batch.dry_run && batch.operation != Operation::Clean && batch.total > 0).then(|| {
+            let pb = ProgressBar::new(batch.total as u64);
+            pb.set_style(
+                ProgressStyle::default_bar()
+                    .template("[{elapsed_precise}] {bar:40.cyan/blue} {pos:>7}/{len:7} {msg}")
+                    .unwrap()
+                    .progress_chars("##-"),
+            );
+            pb
+        });
+
+        *self.state() = BatchState {
+            operation: Some(batch.operation),
+            from: batch.from.to_path_buf(),
+            to: batch.to.to_path_buf(),
+            dry_run: batch.dry_run,
+            progress,
+            ..BatchState::default()
+        };
+
+        if self.verbose {
+            match batch.operation {
+                Operation::Backup => self.println(&format!("Creating backup at: {}", batch.to.display())),
+                Operation::Clean if batch.total == 0 => self.println("Nothing to clean"),
+                _ => {}
+            }
+        }
+    }
+
+    fn started(&self, path: &Path) {
+        if let Some(pb) = &self.state().progress {
+            pb.set_message(format!("Copying {}", path.display()));
+        }
+    }
+
+    fn copied(&self, path: &Path, target: &Path, bytes: u64, _retries: u32) {
+        let line = {
+            let mut state = self.state();
+            state.files_copied += 1;
+            state.bytes_copied += bytes;
+            format!("Copied: {} -> {}", state.from.join(path).display(), state.to.join(target).display())
+        };
+        if self.verbose {
+            self.println(&line);
+        }
+        self.advance();
+    }
+
+    fn removed(&self, target: &Path) {
+        if self.verbose {
+            println!("Removed: {}", self.destination(target).display());
+        }
+    }
+
+    fn skipped(&self, path: &Path, target: &Path, reason: SkipReason) {
+        let state = self.state();
+        let dest_path = state.to.join(target);
+
+        match reason {
+            SkipReason::DryRun { rendered: Some(rendered) } => {
+                println!("Would render: {} -> {}", state.from.join(path).display(), dest_path.display());
+                // Files without placeholders may hold plain-text credentials, don't echo them
+                if rendered.substitutions > 0 {
+                    for line in rendered.redacted.lines() {
+                        println!("    {}", line);
+                    }
+                }
+            }
+            SkipReason::DryRun { rendered: None } => {
+                println!("Would copy: {} -> {}", state.from.join(path).display(), dest_path.display());
+            }
+            SkipReason::Protected if state.operation == Some(Operation::Clean) => {
+                if self.verbose {
+                    println!("Kept protected: {}", dest_path.display());
+                }
+            }
+            SkipReason::Protected if state.dry_run => {
+                println!("Would skip protected: {}", dest_path.display());
+            }
+            SkipReason::Protected => {
+                if self.verbose {
+                    println!("Skipped protected: {}", dest_path.display());
+                }
+            }
+        }
+
+        drop(state);
+        self.advance();
+    }
+
+    fn failed(&self, _path: &Path, _target: &Path, _error: &CpdError) {
+        self.advance();
+    }
+
+    fn finished(&self, operation: Operation) {
+        let state = std::mem::take(&mut *self.state());
+        if let Some(pb) = state.progress {
+            pb.finish_with_message(match operation {
+                Operation::Deploy => "Deployment completed",
+                Operation::Backup => "Backup completed",
+                Operation::Clean => "Clean completed",
+            });
+        }
+
+        if self.verbose && operation == Operation::Backup {
+            println!(
+                "Backup completed: {} files, {}",
+                state.files_copied,
+                format_bytes(state.bytes_copied)
+            );
+        }
+    }
+
+    fn retrying(&self, target: &Path, error: &io::Error, retry: u32, delay: Duration) {
+        if self.verbose {
+            let to = self.destination(target);
+            self.eprintln(&format!(
+                "Transient error writing {}: {} (retry {} in {:?})",
+                to.display(),
+                error,
+                retry,
+                delay
+            ));
+        }
+    }
+
+    fn waiting_for_board(&self, board: &Path) {
+        if self.verbose {
+            self.println(&format!("Waiting for board to remount at {}...", board.display()));
+        }
+    }
+
+    fn verified(&self, target: &Path) {
+        if self.verbose {
+            let to = self.destination(target);
+            self.println(&format!("Verified: {}", to.display()));
+        }
+    }
+
+    fn verify_failed(&self, target: &Path, attempt: u32, failure: VerifyFailure) {
+        if self.verbose {
+            let to = self.destination(target);
+            let reason = match failure {
+                VerifyFailure::Mismatch => "content differs from source".to_string(),
+                VerifyFailure::Unreadable(error) => error.to_string(),
+            };
+            self.eprintln(&format!("Verification failed for {} (attempt {}): {}", to.display(), attempt, reason));
+        }
+    }
+
+    fn generated(&self, path: &Path) {
+        if self.verbose {
+            self.println(&format!("Wrote: {}", path.display()));
+        }
+    }
+
+    fn warning(&self, message: &str) {
+        if self.verbose {
+            self.eprintln(&format!("Warning: {}", message));
+        }
+    }
+}
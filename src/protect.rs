@@ -0,0 +1,138 @@
+use crate::error::{CpdError, Result};
+use crate::storage::BoardStorage;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+
+/// Board files that belong to the device or the user rather than the project
+pub const DEFAULT_PROTECTED_PATTERNS: [&str; 3] = ["/boot_out.txt", "/settings.toml", "/sd/"];
+
+/// Paths on the board that deployments must leave alone.
+///
+/// Patterns use `.gitignore` syntax and are relative to the board root. A
+/// protected file that already exists on the board is never overwritten or
+/// deleted; one that doesn't exist yet can still be created.
+pub struct ProtectedPaths {
+    matcher: Gitignore,
+    /// Only the project's own patterns, without the defaults
+    custom: Gitignore,
+    patterns: Vec<String>,
+}
+
+impl ProtectedPaths {
+    /// Build the protect list from the defaults (if enabled) followed by `patterns`
+    pub fn new(patterns: &[String], use_defaults: bool) -> Result<Self> {
+        let mut all_patterns = Vec::new();
+
+        if use_defaults {
+            all_patterns.extend(DEFAULT_PROTECTED_PATTERNS.iter().map(|p| p.to_string()));
+        }
+        all_patterns.extend(patterns.iter().cloned());
+
+        Ok(Self {
+            matcher: build_matcher(&all_patterns)?,
+            custom: build_matcher(patterns)?,
+            patterns: all_patterns,
+        })
+    }
+
+    /// A protect list that matches nothing, used with `--force` and `--overwrite-protected`
+    pub fn none() -> Self {
+        Self {
+            matcher: Gitignore::empty(),
+            custom: Gitignore::empty(),
+            patterns: Vec::new(),
+        }
+    }
+
+    /// Whether a board-relative path is covered by a protect pattern
+    pub fn is_protected(&self, relative_path: &Path) -> bool {
+        self.matcher
+            .matched_path_or_any_parents(relative_path, false)
+            .is_ignore()
+    }
+
+    /// Whether writing or deleting `relative_path` on the board must be skipped
+    pub fn blocks(&self, board: &dyn BoardStorage, relative_path: &Path) -> bool {
+        self.is_protected(relative_path) && board.exists(relative_path)
+    }
+
+    /// Whether writing a file rendered from a project template to `relative_path` must be skipped.
+    ///
+    /// The project's template is the intended source of files like
+    /// `settings.toml`, so only the project's own patterns apply to it.
+    pub fn blocks_rendered(&self, board: &dyn BoardStorage, relative_path: &Path) -> bool {
+        self.custom
+            .matched_path_or_any_parents(relative_path, false)
+            .is_ignore()
+            && board.exists(relative_path)
+    }
+
+    /// Patterns in effect, defaults first
+    pub fn patterns(&self) -> &[String] {
+        &self.patterns
+    }
+}
+
+fn build_matcher(patterns: &[String]) -> Result<Gitignore> {
+    let mut builder = GitignoreBuilder::new("");
+    for pattern in patterns {
+        builder.add_line(None, pattern).map_err(|e| CpdError::Configuration {
+            message: format!("Invalid protect pattern '{}': {}", pattern, e),
+        })?;
+    }
+
+    builder.build().map_err(|e| CpdError::Configuration {
+        message: format!("Invalid protect patterns: {}", e),
+    })
+}
+
+impl Default for ProtectedPaths {
+    fn default() -> Self {
+        Self::new(&[], true).expect("default protect patterns are valid")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryBoard;
+
+    #[test]
+    fn test_default_patterns() {
+        let protected = ProtectedPaths::default();
+
+        assert!(protected.is_protected(Path::new("settings.toml")));
+        assert!(protected.is_protected(Path::new("boot_out.txt")));
+        assert!(protected.is_protected(Path::new("sd/data.csv")));
+        assert!(!protected.is_protected(Path::new("code.py")));
+        assert!(!protected.is_protected(Path::new("lib/settings.toml")));
+    }
+
+    #[test]
+    fn test_custom_patterns_and_negation() {
+        let patterns = vec!["logs/".to_string(), "!/settings.toml".to_string()];
+        let protected = ProtectedPaths::new(&patterns, true).unwrap();
+
+        assert!(protected.is_protected(Path::new("logs/2024-01-01.csv")));
+        assert!(!protected.is_protected(Path::new("settings.toml")));
+
+        assert!(!ProtectedPaths::none().is_protected(Path::new("boot_out.txt")));
+        assert!(ProtectedPaths::new(&["logs/[".to_string()], false).is_err());
+    }
+
+    #[test]
+    fn test_blocks_only_existing_files() {
+        let board = MemoryBoard::new();
+        let protected = ProtectedPaths::default();
+
+        assert!(!protected.blocks(&board, Path::new("settings.toml")));
+
+        board.write(Path::new("settings.toml"), b"CIRCUITPY_WIFI_SSID = \"home\"").unwrap();
+        assert!(protected.blocks(&board, Path::new("settings.toml")));
+
+        // Rendered templates only honour the project's own patterns
+        assert!(!protected.blocks_rendered(&board, Path::new("settings.toml")));
+        let protected = ProtectedPaths::new(&["/settings.toml".to_string()], true).unwrap();
+        assert!(protected.blocks_rendered(&board, Path::new("settings.toml")));
+    }
+}
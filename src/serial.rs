@@ -0,0 +1,661 @@
+use crate::error::{CpdError, Result};
+use crate::manifest::board_path_string;
+use crate::storage::{temp_path, BoardEntry, BoardStorage};
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Prefix of `--board` values that name a serial port, e.g. `serial:/dev/ttyUSB0`
+pub const SERIAL_PREFIX: &str = "serial:";
+
+const BAUD_RATE: u32 = 115_200;
+
+/// Bytes of file content sent per `write()` call
+const WRITE_CHUNK_SIZE: usize = 256;
+
+/// Longest the board may take to answer a command
+const COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
+
+const RAW_REPL_BANNER: &[u8] = b"raw REPL; CTRL-B to exit\r\n>";
+
+/// Helpers defined on the board when the raw REPL is entered, so each file
+/// operation is a single short call
+const PRELUDE: &str = "\
+import os
+def _cpd_ls(p):
+ for n in os.listdir(p):
+  s=os.stat(p.rstrip('/')+'/'+n)
+  print(1 if s[0]&0x4000 else 0,s[6],n)
+def _cpd_st(p):
+ try:
+  s=os.stat(p)
+ except OSError as e:
+  if e.args[0]==2:
+   print('-')
+   return
+  raise
+ print(1 if s[0]&0x4000 else 0,s[6])
+def _cpd_rd(p):
+ with open(p,'rb') as f:
+  while True:
+   b=f.read(64)
+   if not b:
+    break
+   print(''.join('%02x'%x for x in b))
+def _cpd_open(p):
+ global _cpd_f
+ _cpd_f=open(p,'wb')
+def _cpd_w(b):
+ _cpd_f.write(b)
+def _cpd_close(p):
+ _cpd_f.close()
+ print(os.stat(p)[6])
+def _cpd_discard(p):
+ try:
+  _cpd_f.close()
+ except OSError:
+  pass
+ os.remove(p)
+def _cpd_mv(a,b):
+ try:
+  os.remove(b)
+ except OSError:
+  pass
+ os.rename(a,b)
+def _cpd_md(p):
+ try:
+  os.mkdir(p)
+ except OSError as e:
+  if e.args[0]!=17:
+   raise
+def _cpd_rm(p):
+ os.remove(p)
+def _cpd_df():
+ s=os.statvfs('/')
+ print(s[0]*s[3])
+";
+
+/// A connection to CircuitPython's raw REPL, the mode ampy and mpremote use
+/// to run code on a board: Ctrl-A enters it, each command is sent followed
+/// by Ctrl-D, and the board answers `OK`, the output, `\x04`, any error
+/// text, `\x04` and a `>` prompt.
+pub struct RawRepl<P> {
+    port: P,
+    timeout: Duration,
+}
+
+impl<P: Read + Write> RawRepl<P> {
+    /// Interrupt whatever the board is running and enter the raw REPL
+    pub fn enter(port: P) -> io::Result<Self> {
+        let mut repl = Self {
+            port,
+            timeout: COMMAND_TIMEOUT,
+        };
+
+        // Ctrl-C twice stops code.py, Ctrl-A switches to raw mode
+        repl.port.write_all(b"\r\x03\x03")?;
+        thread::sleep(Duration::from_millis(100));
+        repl.port.write_all(b"\r\x01")?;
+        repl.port.flush()?;
+        repl.read_until(RAW_REPL_BANNER)?;
+
+        Ok(repl)
+    }
+
+    /// Run `code` on the board and return what it printed.
+    ///
+    /// An exception on the board becomes an error of the matching kind,
+    /// e.g. `OSError: [Errno 28]` is `StorageFull`.
+    pub fn exec(&mut self, code: &str) -> io::Result<String> {
+        self.port.write_all(code.as_bytes())?;
+        self.port.write_all(b"\x04")?;
+        self.port.flush()?;
+
+        let response = self.read_until(b"\x04")?;
+        let Some(output) = response.strip_prefix(b"OK") else {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unexpected raw REPL response: {}", String::from_utf8_lossy(&response)),
+            ));
+        };
+        // read_until only returns once the terminator has arrived
+        let output = output.strip_suffix(b"\x04").unwrap_or(output);
+        let output = String::from_utf8_lossy(output).into_owned();
+
+        let error = self.read_until(b"\x04")?;
+        let error = error.strip_suffix(b"\x04").unwrap_or(&error);
+        let error = String::from_utf8_lossy(error).into_owned();
+        self.read_until(b">")?;
+
+        if error.trim().is_empty() {
+            Ok(output)
+        } else {
+            Err(traceback_error(&error))
+        }
+    }
+
+    /// Leave the raw REPL for the normal one
+    pub fn exit(&mut self) -> io::Result<()> {
+        self.port.write_all(b"\r\x02")?;
+        self.port.flush()
+    }
+
+    /// Read until the input ends with `ending`, returning everything read
+    fn read_until(&mut self, ending: &[u8]) -> io::Result<Vec<u8>> {
+        let deadline = Instant::now() + self.timeout;
+        let mut data = Vec::new();
+        let mut byte = [0u8; 1];
+
+        while !data.ends_with(ending) {
+            match self.port.read(&mut byte) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(_) => data.push(byte[0]),
+                Err(e) if matches!(e.kind(), io::ErrorKind::TimedOut | io::ErrorKind::WouldBlock) => {
+                    if Instant::now() >= deadline {
+                        return Err(io::Error::new(io::ErrorKind::TimedOut, "the board stopped responding"));
+                    }
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(data)
+    }
+}
+
+/// Turn the traceback of a failed command into an I/O error, keeping the
+/// errno of an `OSError` as the error kind
+fn traceback_error(traceback: &str) -> io::Error {
+    let message = traceback.trim().lines().last().unwrap_or_default().to_string();
+
+    let errno = message.strip_prefix("OSError: ").and_then(|rest| {
+        let rest = rest.strip_prefix("[Errno ").unwrap_or(rest);
+        let digits: String = rest.chars().take_while(char::is_ascii_digit).collect();
+        digits.parse::<i32>().ok()
+    });
+    let kind = match errno {
+        Some(2) => io::ErrorKind::NotFound,
+        Some(13) => io::ErrorKind::PermissionDenied,
+        Some(17) => io::ErrorKind::AlreadyExists,
+        Some(21) => io::ErrorKind::IsADirectory,
+        Some(28) => io::ErrorKind::StorageFull,
+        Some(30) => io::ErrorKind::ReadOnlyFilesystem,
+        _ => io::ErrorKind::Other,
+    };
+
+    io::Error::new(kind, message)
+}
+
+/// Anything a raw REPL can run over: a serial port, or a fake in tests
+pub trait Port: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Port for T {}
+
+/// A board without a `CIRCUITPY` drive, such as an ESP32-C3, written to
+/// through the raw REPL on its serial port.
+///
+/// Files are written in chunks with `open().write()` to a temporary file,
+/// which replaces the real one only once its size has been checked, so a
+/// failed write never leaves a truncated file behind.
+pub struct SerialBoard {
+    port_name: String,
+    repl: Mutex<RawRepl<Box<dyn Port>>>,
+}
+
+impl SerialBoard {
+    /// Open a serial port, e.g. `/dev/ttyUSB0` or `COM3`
+    pub fn open(port_name: &str) -> Result<Self> {
+        let connection_failed = |source: io::Error| CpdError::BoardConnectionFailed {
+            board: format!("{}{}", SERIAL_PREFIX, port_name),
+            source,
+        };
+
+        let port = serialport::new(port_name, BAUD_RATE)
+            .timeout(Duration::from_millis(100))
+            .open()
+            .map_err(|e| connection_failed(e.into()))?;
+
+        Self::connect(port_name, Box::new(port)).map_err(connection_failed)
+    }
+
+    /// Talk to a board over an already open connection
+    pub fn connect(port_name: &str, port: Box<dyn Port>) -> io::Result<Self> {
+        let mut repl = RawRepl::enter(port)?;
+        repl.exec(PRELUDE)?;
+
+        Ok(Self {
+            port_name: port_name.to_string(),
+            repl: Mutex::new(repl),
+        })
+    }
+
+    fn exec(&self, code: &str) -> io::Result<String> {
+        self.repl.lock().unwrap().exec(code)
+    }
+
+    /// Send `content` to the file opened at `path` (a Python literal), and
+    /// check the whole file arrived
+    fn write_temp_file(&self, path: &str, content: &[u8]) -> io::Result<()> {
+        for chunk in content.chunks(WRITE_CHUNK_SIZE) {
+            self.exec(&format!("_cpd_w({})", python_bytes(chunk)))?;
+        }
+
+        let output = self.exec(&format!("_cpd_close({})", path))?;
+        let size: usize = output.trim().parse().map_err(|_| invalid_output(&output))?;
+        if size != content.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("wrote {} bytes but the board has {}", content.len(), size),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl fmt::Debug for SerialBoard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SerialBoard").field("port_name", &self.port_name).finish()
+    }
+}
+
+impl Drop for SerialBoard {
+    fn drop(&mut self) {
+        if let Ok(repl) = self.repl.get_mut() {
+            let _ = repl.exit();
+        }
+    }
+}
+
+impl BoardStorage for SerialBoard {
+    fn root(&self) -> PathBuf {
+        PathBuf::from(format!("{}{}", SERIAL_PREFIX, self.port_name))
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<BoardEntry>> {
+        let output = self.exec(&format!("_cpd_ls({})", python_path(dir)))?;
+
+        let mut entries = Vec::new();
+        for line in output.lines().filter(|line| !line.is_empty()) {
+            let mut fields = line.splitn(3, ' ');
+            let (Some(is_dir), Some(size), Some(name)) = (fields.next(), fields.next(), fields.next()) else {
+                return Err(invalid_output(line));
+            };
+            entries.push(BoardEntry {
+                path: dir.join(name),
+                is_dir: is_dir == "1",
+                size: size.parse().map_err(|_| invalid_output(line))?,
+            });
+        }
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let output = self.exec(&format!("_cpd_rd({})", python_path(path)))?;
+
+        let hex: String = output.split_whitespace().collect();
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| {
+                hex.get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or_else(|| invalid_output(&hex))
+            })
+            .collect()
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        let temp_path = temp_path(path);
+        let temp = python_path(&temp_path);
+        self.exec(&format!("_cpd_open({})", temp))?;
+
+        if let Err(e) = self.write_temp_file(&temp, content) {
+            let _ = self.exec(&format!("_cpd_discard({})", temp));
+            return Err(e);
+        }
+
+        self.rename(&temp_path, path)
+    }
+
+    fn delete(&self, path: &Path) -> io::Result<()> {
+        self.exec(&format!("_cpd_rm({})", python_path(path))).map(|_| ())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        self.exec(&format!("_cpd_mv({},{})", python_path(from), python_path(to)))
+            .map(|_| ())
+    }
+
+    fn mkdir(&self, path: &Path) -> io::Result<()> {
+        let mut ancestors: Vec<&Path> = path
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .collect();
+        if ancestors.is_empty() {
+            return Ok(());
+        }
+        ancestors.reverse();
+
+        let calls: Vec<String> = ancestors
+            .iter()
+            .map(|ancestor| format!("_cpd_md({})", python_path(ancestor)))
+            .collect();
+        self.exec(&calls.join("\n")).map(|_| ())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Option<BoardEntry>> {
+        let output = self.exec(&format!("_cpd_st({})", python_path(path)))?;
+        let line = output.trim();
+        if line == "-" {
+            return Ok(None);
+        }
+
+        let Some((is_dir, size)) = line.split_once(' ') else {
+            return Err(invalid_output(line));
+        };
+        Ok(Some(BoardEntry {
+            path: path.to_path_buf(),
+            is_dir: is_dir == "1",
+            size: size.parse().map_err(|_| invalid_output(line))?,
+        }))
+    }
+
+    fn free_space(&self) -> Option<u64> {
+        self.exec("_cpd_df()").ok()?.trim().parse().ok()
+    }
+}
+
+/// Absolute board path as a Python string literal
+fn python_path(path: &Path) -> String {
+    let path = format!("/{}", board_path_string(path));
+    // JSON string literals are also valid Python string literals
+    serde_json::to_string(&path).unwrap_or_else(|_| "'/'".to_string())
+}
+
+/// Bytes as a Python bytes literal
+fn python_bytes(bytes: &[u8]) -> String {
+    let mut literal = String::from("b'");
+    for &byte in bytes {
+        match byte {
+            b'\\' => literal.push_str("\\\\"),
+            b'\'' => literal.push_str("\\'"),
+            0x20..=0x7e => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\x{:02x}", byte)),
+        }
+    }
+    literal.push('\'');
+    literal
+}
+
+fn invalid_output(output: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("unexpected output from the board: {}", output.trim()),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deploy::{BoardSelector, Deployer};
+    use crate::storage::MemoryBoard;
+    use std::collections::VecDeque;
+    use std::fs;
+    use std::sync::Arc;
+    use tempfile::TempDir;
+
+    /// Pretends to be a board's serial port: answers raw REPL commands by
+    /// running the `_cpd_*` helper calls against an in-memory filesystem
+    struct FakeRepl {
+        board: Arc<MemoryBoard>,
+        input: Vec<u8>,
+        output: VecDeque<u8>,
+        raw: bool,
+        writing: Option<(PathBuf, Vec<u8>)>,
+    }
+
+    impl FakeRepl {
+        fn new(board: Arc<MemoryBoard>) -> Self {
+            Self {
+                board,
+                input: Vec::new(),
+                output: VecDeque::new(),
+                raw: false,
+                writing: None,
+            }
+        }
+
+        fn run(&mut self, code: &str) -> std::result::Result<String, String> {
+            if code.starts_with("import os") {
+                return Ok(String::new());
+            }
+
+            let mut output = String::new();
+            for line in code.lines() {
+                let (name, arg) = line.trim_end_matches(')').split_once('(').ok_or("SyntaxError")?;
+                let paths = || -> Vec<PathBuf> {
+                    let paths: Vec<String> = serde_json::from_str(&format!("[{}]", arg)).unwrap();
+                    paths.iter().map(|path| PathBuf::from(path.trim_start_matches('/'))).collect()
+                };
+                let path = || paths().remove(0);
+                let result = match name {
+                    "_cpd_ls" => self.board.list(&path()).map(|entries| {
+                        for entry in entries {
+                            let name = entry.path.file_name().unwrap().to_string_lossy().into_owned();
+                            output += &format!("{} {} {}\r\n", entry.is_dir as u8, entry.size, name);
+                        }
+                    }),
+                    "_cpd_st" => self.board.stat(&path()).map(|entry| match entry {
+                        Some(entry) => output += &format!("{} {}\r\n", entry.is_dir as u8, entry.size),
+                        None => output += "-\r\n",
+                    }),
+                    "_cpd_rd" => self.board.read(&path()).map(|content| {
+                        for chunk in content.chunks(64) {
+                            output += &chunk.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+                            output += "\r\n";
+                        }
+                    }),
+                    "_cpd_open" => {
+                        self.writing = Some((path(), Vec::new()));
+                        self.board.write(&path(), b"")
+                    }
+                    "_cpd_w" => {
+                        let (_, content) = self.writing.as_mut().ok_or("NameError")?;
+                        content.extend(parse_bytes(arg));
+                        Ok(())
+                    }
+                    "_cpd_close" => {
+                        let (path, content) = self.writing.take().ok_or("NameError")?;
+                        self.board
+                            .write(&path, &content)
+                            .map(|()| output += &format!("{}\r\n", content.len()))
+                    }
+                    "_cpd_discard" => {
+                        self.writing = None;
+                        self.board.delete(&path())
+                    }
+                    "_cpd_mv" => {
+                        let paths = paths();
+                        self.board.rename(&paths[0], &paths[1])
+                    }
+                    "_cpd_md" => self.board.mkdir(&path()),
+                    "_cpd_rm" => self.board.delete(&path()),
+                    "_cpd_df" => {
+                        output += "1048576\r\n";
+                        Ok(())
+                    }
+                    _ => return Err(format!("NameError: name '{}' isn't defined", name)),
+                };
+
+                if let Err(e) = result {
+                    let errno = match e.kind() {
+                        io::ErrorKind::NotFound => 2,
+                        io::ErrorKind::StorageFull => 28,
+                        _ => 5,
+                    };
+                    return Err(format!("Traceback (most recent call last):\r\nOSError: [Errno {}] {}\r\n", errno, e));
+                }
+            }
+
+            Ok(output)
+        }
+    }
+
+    /// Decode the `b'...'` literals written by `python_bytes`
+    fn parse_bytes(literal: &str) -> Vec<u8> {
+        let inner = &literal.as_bytes()[2..literal.len() - 1];
+        let mut bytes = Vec::new();
+        let mut i = 0;
+        while i < inner.len() {
+            if inner[i] == b'\\' {
+                if inner[i + 1] == b'x' {
+                    let hex = std::str::from_utf8(&inner[i + 2..i + 4]).unwrap();
+                    bytes.push(u8::from_str_radix(hex, 16).unwrap());
+                    i += 4;
+                } else {
+                    bytes.push(inner[i + 1]);
+                    i += 2;
+                }
+            } else {
+                bytes.push(inner[i]);
+                i += 1;
+            }
+        }
+        bytes
+    }
+
+    impl Write for FakeRepl {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            for &byte in buf {
+                match byte {
+                    0x01 => {
+                        self.raw = true;
+                        self.input.clear();
+                        self.output.extend(RAW_REPL_BANNER);
+                    }
+                    0x02 => self.raw = false,
+                    0x03 => self.input.clear(),
+                    0x04 if self.raw => {
+                        let code = String::from_utf8(std::mem::take(&mut self.input)).unwrap();
+                        let (stdout, stderr) = match self.run(code.trim()) {
+                            Ok(stdout) => (stdout, String::new()),
+                            Err(stderr) => (String::new(), stderr),
+                        };
+                        self.output.extend(format!("OK{}\x04{}\x04>", stdout, stderr).bytes());
+                    }
+                    b'\r' if self.input.is_empty() => {}
+                    _ => self.input.push(byte),
+                }
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Read for FakeRepl {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.output.is_empty() {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            let count = buf.len().min(self.output.len());
+            for (slot, byte) in buf.iter_mut().zip(self.output.drain(..count)) {
+                *slot = byte;
+            }
+            Ok(count)
+        }
+    }
+
+    fn connect(board: &Arc<MemoryBoard>) -> SerialBoard {
+        SerialBoard::connect("/dev/ttyFAKE", Box::new(FakeRepl::new(Arc::clone(board)))).unwrap()
+    }
+
+    #[test]
+    fn test_python_literals() {
+        assert_eq!(python_path(Path::new("lib/it's.py")), "\"/lib/it's.py\"");
+        assert_eq!(python_path(Path::new("")), "\"/\"");
+        assert_eq!(python_bytes(b"a'\\\n\xff"), "b'a\\'\\\\\\x0a\\xff'");
+        assert_eq!(parse_bytes(&python_bytes(b"a'\\\n\xff")), b"a'\\\n\xff");
+    }
+
+    #[test]
+    fn test_traceback_error() {
+        let error = traceback_error("Traceback (most recent call last):\r\n  File \"<stdin>\", line 1\r\nOSError: [Errno 28] No space left on device\r\n");
+        assert_eq!(error.kind(), io::ErrorKind::StorageFull);
+        assert_eq!(error.to_string(), "OSError: [Errno 28] No space left on device");
+
+        assert_eq!(traceback_error("OSError: 30").kind(), io::ErrorKind::ReadOnlyFilesystem);
+        assert_eq!(traceback_error("NameError: name 'x' isn't defined").kind(), io::ErrorKind::Other);
+    }
+
+    #[test]
+    fn test_serial_board_storage() {
+        let memory = Arc::new(MemoryBoard::new().with_file("boot_out.txt", "Board ID:adafruit_qtpy_esp32c3\n"));
+        let board = connect(&memory);
+
+        // Larger than one chunk, with bytes that need escaping
+        let content: Vec<u8> = (0..=255u8).cycle().take(WRITE_CHUNK_SIZE * 2 + 10).collect();
+        board.mkdir(Path::new("lib/sensors")).unwrap();
+        board.write(Path::new("lib/sensors/data.bin"), &content).unwrap();
+
+        assert_eq!(memory.file("lib/sensors/data.bin").unwrap(), content);
+        assert_eq!(board.read(Path::new("lib/sensors/data.bin")).unwrap(), content);
+        assert!(board.stat(Path::new("lib")).unwrap().unwrap().is_dir);
+        assert!(board.stat(Path::new("missing.py")).unwrap().is_none());
+        assert_eq!(board.free_space(), Some(1048576));
+
+        let files: Vec<PathBuf> = board.walk().unwrap().into_iter().map(|entry| entry.path).collect();
+        assert_eq!(files, memory.files());
+
+        board.delete(Path::new("boot_out.txt")).unwrap();
+        let error = board.read(Path::new("boot_out.txt")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_failed_write_keeps_old_file() {
+        let memory = Arc::new(MemoryBoard::new().with_file("code.py", "print('old')").with_capacity(30));
+        let board = connect(&memory);
+
+        let error = board.write(Path::new("code.py"), b"print('a much longer new version')").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::StorageFull);
+        assert_eq!(memory.file("code.py").unwrap(), b"print('old')");
+        assert_eq!(memory.files(), vec![PathBuf::from("code.py")]);
+
+        board.write(Path::new("code.py"), b"print('new')").unwrap();
+        assert_eq!(memory.file("code.py").unwrap(), b"print('new')");
+        assert_eq!(memory.files(), vec![PathBuf::from("code.py")]);
+    }
+
+    #[test]
+    fn test_deploy_over_serial() {
+        let project = TempDir::new().unwrap();
+        fs::write(project.path().join("code.py"), "print('hello')").unwrap();
+        fs::write(project.path().join("notes.txt"), "not for the board").unwrap();
+        fs::write(project.path().join(".cpdignore"), "*.txt\n").unwrap();
+
+        let memory = Arc::new(MemoryBoard::new().with_file("boot_out.txt", "Board ID:adafruit_qtpy_esp32c3\n"));
+        let report = Deployer::builder(project.path())
+            .board(BoardSelector::Storage(Arc::new(connect(&memory))))
+            .verify(1)
+            .build()
+            .run()
+            .unwrap();
+
+        assert!(report.is_success());
+        assert_eq!(report.result.files_copied, 1);
+        assert_eq!(report.board.path, PathBuf::from("serial:/dev/ttyFAKE"));
+        assert_eq!(report.board.board_id.as_deref(), Some("adafruit_qtpy_esp32c3"));
+        assert_eq!(memory.file("code.py").unwrap(), b"print('hello')");
+        assert!(memory.file("notes.txt").is_none());
+        assert!(memory.file(".cpd_manifest.json").is_some());
+    }
+}
@@ -0,0 +1,495 @@
+use crate::board::BoardDetector;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+/// A file or directory on the board
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BoardEntry {
+    /// Board-relative path
+    pub path: PathBuf,
+    pub is_dir: bool,
+    /// Size in bytes, zero for directories
+    pub size: u64,
+}
+
+/// Access to a board's filesystem.
+///
+/// Deploying, backing up, cleaning and verifying all go through this trait,
+/// so the same code works on a mounted `CIRCUITPY` drive ([`LocalBoard`])
+/// and on boards reached some other way. Paths are relative to the board
+/// root; the empty path is the root itself.
+pub trait BoardStorage: fmt::Debug {
+    /// Where the board is, used to show board paths in messages
+    fn root(&self) -> PathBuf;
+
+    /// Entries directly inside `dir`, sorted by path
+    fn list(&self, dir: &Path) -> io::Result<Vec<BoardEntry>>;
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+
+    /// Create or replace a file. The parent directory must already exist.
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()>;
+
+    /// Remove a file
+    fn delete(&self, path: &Path) -> io::Result<()>;
+
+    /// Move a file to `to`, replacing any file already there
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()>;
+
+    /// Create a directory and any missing parents; an existing directory is fine
+    fn mkdir(&self, path: &Path) -> io::Result<()>;
+
+    /// The entry at `path`, or `None` if nothing is there
+    fn stat(&self, path: &Path) -> io::Result<Option<BoardEntry>>;
+
+    /// Bytes that can still be written, if the board reports it
+    fn free_space(&self) -> Option<u64>;
+
+    fn exists(&self, path: &Path) -> bool {
+        matches!(self.stat(path), Ok(Some(_)))
+    }
+
+    /// Read a file that was just written, from the device rather than a cache
+    fn read_back(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.read(path)
+    }
+
+    /// Set a file's modification time, where the board keeps one
+    fn set_modified(&self, _path: &Path, _modified: SystemTime) {}
+
+    /// Whether the board is currently reachable
+    fn is_connected(&self) -> bool {
+        true
+    }
+
+    /// Wait up to `timeout` for a board that went away (e.g. remounting
+    /// after auto-reload) to come back. Returns true once it has.
+    fn wait_for_reconnect(&self, _timeout: Duration) -> bool {
+        false
+    }
+
+    /// Every file on the board, depth first in path order
+    fn walk(&self) -> io::Result<Vec<BoardEntry>> {
+        let mut files = Vec::new();
+        let mut pending = vec![PathBuf::new()];
+
+        while let Some(dir) = pending.pop() {
+            let entries = self.list(&dir)?;
+            // Push directories in reverse so they are visited in order
+            for entry in entries.iter().rev().filter(|entry| entry.is_dir) {
+                pending.push(entry.path.clone());
+            }
+            files.extend(entries.into_iter().filter(|entry| !entry.is_dir));
+        }
+
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(files)
+    }
+}
+
+/// Where a file is written before being renamed into place, so an
+/// interrupted write never leaves a truncated file under the real name
+pub fn temp_path(path: &Path) -> PathBuf {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".cpdtmp");
+    PathBuf::from(temp)
+}
+
+/// A board mounted as a local drive, e.g. `/media/user/CIRCUITPY` or `E:\`
+#[derive(Debug, Clone)]
+pub struct LocalBoard {
+    root: PathBuf,
+}
+
+impl LocalBoard {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+}
+
+impl BoardStorage for LocalBoard {
+    fn root(&self) -> PathBuf {
+        self.root.clone()
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<BoardEntry>> {
+        let mut entries = Vec::new();
+        for entry in fs::read_dir(self.root.join(dir))? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            entries.push(BoardEntry {
+                path: dir.join(entry.file_name()),
+                is_dir: metadata.is_dir(),
+                size: if metadata.is_dir() { 0 } else { metadata.len() },
+            });
+        }
+
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(self.root.join(path))
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        fs::write(self.root.join(path), content)
+    }
+
+    fn delete(&self, path: &Path) -> io::Result<()> {
+        fs::remove_file(self.root.join(path))
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        fs::rename(self.root.join(from), self.root.join(to))
+    }
+
+    fn mkdir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir_all(self.root.join(path))
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Option<BoardEntry>> {
+        match fs::metadata(self.root.join(path)) {
+            Ok(metadata) => Ok(Some(BoardEntry {
+                path: path.to_path_buf(),
+                is_dir: metadata.is_dir(),
+                size: if metadata.is_dir() { 0 } else { metadata.len() },
+            })),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn free_space(&self) -> Option<u64> {
+        available_space(&self.root)
+    }
+
+    fn read_back(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let path = self.root.join(path);
+        drop_cached_pages(&path);
+        fs::read(path)
+    }
+
+    fn set_modified(&self, path: &Path, modified: SystemTime) {
+        let _ = filetime::set_file_mtime(self.root.join(path), filetime::FileTime::from_system_time(modified));
+    }
+
+    fn is_connected(&self) -> bool {
+        self.root.exists()
+    }
+
+    fn wait_for_reconnect(&self, timeout: Duration) -> bool {
+        BoardDetector::new(false).wait_for_board(&self.root, timeout)
+    }
+}
+
+/// Free bytes on the filesystem holding `path`
+fn available_space(path: &Path) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::ffi::CString;
+        use std::os::unix::ffi::OsStrExt;
+
+        let c_path = CString::new(path.as_os_str().as_bytes()).ok()?;
+        let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+        // SAFETY: c_path is NUL-terminated and stat is a valid, writable statvfs
+        if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+            return None;
+        }
+        #[allow(clippy::unnecessary_cast)]
+        Some(stat.f_bavail as u64 * stat.f_frsize as u64)
+    }
+
+    #[cfg(not(unix))]
+    {
+        // The disk with the longest mount point containing `path` holds it
+        let disks = sysinfo::Disks::new_with_refreshed_list();
+        disks
+            .iter()
+            .filter(|disk| path.starts_with(disk.mount_point()))
+            .max_by_key(|disk| disk.mount_point().as_os_str().len())
+            .map(|disk| disk.available_space())
+    }
+}
+
+/// Flush a file and evict it from the host page cache where possible, so the
+/// next read comes from the device
+#[cfg(target_os = "linux")]
+fn drop_cached_pages(path: &Path) {
+    use std::os::unix::io::AsRawFd;
+
+    if let Ok(file) = fs::File::open(path) {
+        let _ = file.sync_all();
+        // SAFETY: the descriptor is valid for the lifetime of `file`
+        unsafe {
+            libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED);
+        }
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn drop_cached_pages(path: &Path) {
+    if let Ok(file) = fs::File::open(path) {
+        let _ = file.sync_all();
+    }
+}
+
+/// A board that only exists in memory, for tests and for trying out
+/// deployments without hardware
+#[allow(dead_code)]
+#[derive(Debug, Default)]
+pub struct MemoryBoard {
+    files: Mutex<BTreeMap<PathBuf, Vec<u8>>>,
+    dirs: Mutex<BTreeSet<PathBuf>>,
+    capacity: Option<u64>,
+    read_only: bool,
+}
+
+#[allow(dead_code)]
+impl MemoryBoard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a file, creating its parent directories
+    pub fn with_file(self, path: impl Into<PathBuf>, content: impl Into<Vec<u8>>) -> Self {
+        let path = path.into();
+        if let Some(parent) = path.parent() {
+            self.add_dirs(parent);
+        }
+        self.files.lock().unwrap().insert(path, content.into());
+        self
+    }
+
+    /// Fail writes that would take the board past `bytes` of file data
+    pub fn with_capacity(mut self, bytes: u64) -> Self {
+        self.capacity = Some(bytes);
+        self
+    }
+
+    /// Fail every change to the board, like a drive mounted read-only
+    pub fn with_read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Content of a file on the board
+    pub fn file(&self, path: impl AsRef<Path>) -> Option<Vec<u8>> {
+        self.files.lock().unwrap().get(path.as_ref()).cloned()
+    }
+
+    /// Paths of every file on the board, in order
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.files.lock().unwrap().keys().cloned().collect()
+    }
+
+    fn add_dirs(&self, path: &Path) {
+        let mut dirs = self.dirs.lock().unwrap();
+        for ancestor in path.ancestors().filter(|ancestor| !ancestor.as_os_str().is_empty()) {
+            dirs.insert(ancestor.to_path_buf());
+        }
+    }
+
+    fn is_dir(&self, path: &Path) -> bool {
+        path.as_os_str().is_empty() || self.dirs.lock().unwrap().contains(path)
+    }
+
+    fn used_space(&self) -> u64 {
+        self.files.lock().unwrap().values().map(|content| content.len() as u64).sum()
+    }
+}
+
+impl BoardStorage for MemoryBoard {
+    fn root(&self) -> PathBuf {
+        PathBuf::from("memory:")
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<BoardEntry>> {
+        if !self.is_dir(dir) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+
+        let dirs = self.dirs.lock().unwrap();
+        let files = self.files.lock().unwrap();
+        let subdirs = dirs
+            .iter()
+            .filter(|path| path.parent() == Some(dir))
+            .map(|path| BoardEntry {
+                path: path.clone(),
+                is_dir: true,
+                size: 0,
+            });
+        let files = files
+            .iter()
+            .filter(|(path, _)| path.parent() == Some(dir))
+            .map(|(path, content)| BoardEntry {
+                path: path.clone(),
+                is_dir: false,
+                size: content.len() as u64,
+            });
+
+        let mut entries: Vec<BoardEntry> = subdirs.chain(files).collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.file(path).ok_or_else(|| io::ErrorKind::NotFound.into())
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::ErrorKind::ReadOnlyFilesystem.into());
+        }
+        if self.is_dir(path) {
+            return Err(io::ErrorKind::IsADirectory.into());
+        }
+        if !path.parent().is_some_and(|parent| self.is_dir(parent)) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+
+        if let Some(capacity) = self.capacity {
+            let replaced = self.file(path).map(|old| old.len() as u64).unwrap_or_default();
+            if self.used_space() - replaced + content.len() as u64 > capacity {
+                return Err(io::ErrorKind::StorageFull.into());
+            }
+        }
+
+        self.files.lock().unwrap().insert(path.to_path_buf(), content.to_vec());
+        Ok(())
+    }
+
+    fn delete(&self, path: &Path) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::ErrorKind::ReadOnlyFilesystem.into());
+        }
+        match self.files.lock().unwrap().remove(path) {
+            Some(_) => Ok(()),
+            None => Err(io::ErrorKind::NotFound.into()),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::ErrorKind::ReadOnlyFilesystem.into());
+        }
+        if !to.parent().is_some_and(|parent| self.is_dir(parent)) {
+            return Err(io::ErrorKind::NotFound.into());
+        }
+
+        let mut files = self.files.lock().unwrap();
+        let content = files.remove(from).ok_or(io::ErrorKind::NotFound)?;
+        files.insert(to.to_path_buf(), content);
+        Ok(())
+    }
+
+    fn mkdir(&self, path: &Path) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::ErrorKind::ReadOnlyFilesystem.into());
+        }
+        if self.files.lock().unwrap().contains_key(path) {
+            return Err(io::ErrorKind::AlreadyExists.into());
+        }
+        self.add_dirs(path);
+        Ok(())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Option<BoardEntry>> {
+        if let Some(content) = self.files.lock().unwrap().get(path) {
+            return Ok(Some(BoardEntry {
+                path: path.to_path_buf(),
+                is_dir: false,
+                size: content.len() as u64,
+            }));
+        }
+
+        Ok(self.is_dir(path).then(|| BoardEntry {
+            path: path.to_path_buf(),
+            is_dir: true,
+            size: 0,
+        }))
+    }
+
+    fn free_space(&self) -> Option<u64> {
+        self.capacity.map(|capacity| capacity.saturating_sub(self.used_space()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_local_board() {
+        let temp_dir = TempDir::new().unwrap();
+        let board = LocalBoard::new(temp_dir.path());
+
+        board.mkdir(Path::new("lib/sensors")).unwrap();
+        board.write(Path::new("lib/sensors/bme.py"), b"import bme").unwrap();
+        board.write(Path::new("code.py"), b"print(1)").unwrap();
+
+        assert_eq!(board.read(Path::new("code.py")).unwrap(), b"print(1)");
+        assert_eq!(board.read_back(Path::new("code.py")).unwrap(), b"print(1)");
+        assert_eq!(board.stat(Path::new("lib/sensors/bme.py")).unwrap().unwrap().size, 10);
+        assert!(board.stat(Path::new("lib")).unwrap().unwrap().is_dir);
+        assert!(board.stat(Path::new("missing.py")).unwrap().is_none());
+        assert!(board.free_space().is_some());
+
+        let files: Vec<PathBuf> = board.walk().unwrap().into_iter().map(|entry| entry.path).collect();
+        assert_eq!(files, vec![PathBuf::from("code.py"), PathBuf::from("lib/sensors/bme.py")]);
+
+        board.rename(Path::new("lib/sensors/bme.py"), Path::new("code.py")).unwrap();
+        assert_eq!(board.read(Path::new("code.py")).unwrap(), b"import bme");
+        assert!(!board.exists(Path::new("lib/sensors/bme.py")));
+
+        board.delete(Path::new("code.py")).unwrap();
+        assert!(!board.exists(Path::new("code.py")));
+        assert!(board.delete(Path::new("code.py")).is_err());
+    }
+
+    #[test]
+    fn test_memory_board() {
+        let board = MemoryBoard::new().with_file("boot_out.txt", "Board ID:pico");
+
+        assert!(board.write(Path::new("lib/helper.py"), b"x").is_err());
+        board.mkdir(Path::new("lib")).unwrap();
+        board.write(Path::new("lib/helper.py"), b"def help(): pass").unwrap();
+
+        let entries = board.list(Path::new("")).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].path, PathBuf::from("boot_out.txt"));
+        assert!(entries[1].is_dir);
+
+        let files: Vec<PathBuf> = board.walk().unwrap().into_iter().map(|entry| entry.path).collect();
+        assert_eq!(files, board.files());
+        assert!(board.stat(Path::new("lib")).unwrap().unwrap().is_dir);
+
+        board.rename(Path::new("lib/helper.py"), Path::new("boot_out.txt")).unwrap();
+        assert_eq!(board.file("boot_out.txt").unwrap(), b"def help(): pass");
+        assert!(board.rename(Path::new("lib/helper.py"), Path::new("code.py")).is_err());
+
+        board.delete(Path::new("boot_out.txt")).unwrap();
+        assert_eq!(board.file("boot_out.txt"), None);
+    }
+
+    #[test]
+    fn test_memory_board_capacity() {
+        let board = MemoryBoard::new().with_capacity(10);
+
+        board.write(Path::new("a.py"), b"12345678").unwrap();
+        assert_eq!(board.free_space(), Some(2));
+
+        let error = board.write(Path::new("b.py"), b"123").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::StorageFull);
+
+        // Replacing a file only needs room for the difference
+        board.write(Path::new("a.py"), b"1234567890").unwrap();
+        assert_eq!(board.free_space(), Some(0));
+    }
+}
@@ -0,0 +1,186 @@
+use crate::error::{CpdError, Result};
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Local, git-ignored file holding secret values for templates
+pub const SECRETS_FILE_NAME: &str = "cpd.secrets.toml";
+
+/// Extension of template files; it is stripped from the name on the board
+pub const TEMPLATE_EXTENSION: &str = "tmpl";
+
+/// Shown in place of secret values in dry-run output
+const REDACTED: &str = "********";
+
+/// Values substituted into templates.
+///
+/// Environment variables take precedence over the secrets file, so CI can
+/// provide values without writing them to disk.
+#[derive(Debug, Default)]
+pub struct Secrets {
+    values: BTreeMap<String, String>,
+}
+
+/// A rendered template, with a copy safe to print
+#[derive(Debug)]
+pub struct RenderedTemplate {
+    pub content: String,
+    pub redacted: String,
+    /// Number of placeholders that were replaced
+    pub substitutions: usize,
+}
+
+impl Secrets {
+    /// Load the project's secrets file, if there is one
+    pub fn load(project_dir: &Path) -> Result<Self> {
+        let path = project_dir.join(SECRETS_FILE_NAME);
+        if !path.is_file() {
+            return Ok(Self::default());
+        }
+
+        let content = fs::read_to_string(&path)?;
+        let table: toml::Table = toml::from_str(&content).map_err(|e| CpdError::Configuration {
+            message: format!("{}: {}", path.display(), e),
+        })?;
+
+        let values = table
+            .into_iter()
+            .map(|(name, value)| {
+                let value = match value {
+                    toml::Value::String(s) => s,
+                    other => other.to_string(),
+                };
+                (name, value)
+            })
+            .collect();
+
+        Ok(Self { values })
+    }
+
+    /// Value of a secret, from the environment or the secrets file
+    pub fn get(&self, name: &str) -> Option<String> {
+        env::var(name).ok().or_else(|| self.values.get(name).cloned())
+    }
+}
+
+/// Whether a project file is rendered before being written to the board
+pub fn is_template(relative_path: &Path) -> bool {
+    relative_path.extension().is_some_and(|ext| ext == TEMPLATE_EXTENSION)
+        || relative_path == Path::new("settings.toml")
+}
+
+/// Where a project file ends up on the board, e.g. `wifi.json.tmpl` -> `wifi.json`
+pub fn target_path(relative_path: &Path) -> PathBuf {
+    if relative_path.extension().is_some_and(|ext| ext == TEMPLATE_EXTENSION) {
+        relative_path.with_extension("")
+    } else {
+        relative_path.to_path_buf()
+    }
+}
+
+/// Replace `${NAME}` placeholders in `content` with secret values.
+///
+/// `$${` produces a literal `${`. `file` is only used in error messages.
+pub fn render(content: &str, secrets: &Secrets, file: &Path) -> Result<RenderedTemplate> {
+    let mut rendered = String::with_capacity(content.len());
+    let mut redacted = String::with_capacity(content.len());
+    let mut substitutions = 0;
+    let mut rest = content;
+
+    while let Some(start) = rest.find('$') {
+        let (before, after) = rest.split_at(start);
+        rendered.push_str(before);
+        redacted.push_str(before);
+
+        if let Some(after) = after.strip_prefix("$${") {
+            rendered.push_str("${");
+            redacted.push_str("${");
+            rest = after;
+        } else if let Some(after) = after.strip_prefix("${")
+            && let Some(end) = after.find('}')
+            && is_valid_name(&after[..end])
+        {
+            let name = &after[..end];
+            let value = secrets.get(name).ok_or_else(|| CpdError::MissingSecret {
+                name: name.to_string(),
+                file: file.display().to_string(),
+            })?;
+            rendered.push_str(&value);
+            redacted.push_str(REDACTED);
+            substitutions += 1;
+            rest = &after[end + 1..];
+        } else {
+            rendered.push('$');
+            redacted.push('$');
+            rest = &after[1..];
+        }
+    }
+
+    rendered.push_str(rest);
+    redacted.push_str(rest);
+
+    Ok(RenderedTemplate {
+        content: rendered,
+        redacted,
+        substitutions,
+    })
+}
+
+fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn secrets(values: &[(&str, &str)]) -> Secrets {
+        Secrets {
+            values: values.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect(),
+        }
+    }
+
+    #[test]
+    fn test_render_and_redact() {
+        let secrets = secrets(&[("CPD_TEST_SSID", "home"), ("CPD_TEST_PASSWORD", "hunter2")]);
+        let template = "SSID = \"${CPD_TEST_SSID}\"\nPASSWORD = \"${CPD_TEST_PASSWORD}\"\nPRICE = \"$5 $${literal}\"\n";
+
+        let rendered = render(template, &secrets, Path::new("settings.toml")).unwrap();
+
+        assert_eq!(rendered.content, "SSID = \"home\"\nPASSWORD = \"hunter2\"\nPRICE = \"$5 ${literal}\"\n");
+        assert!(!rendered.redacted.contains("hunter2"));
+        assert!(rendered.redacted.contains("PASSWORD = \"********\""));
+        assert_eq!(rendered.substitutions, 2);
+    }
+
+    #[test]
+    fn test_missing_secret() {
+        let error = render("${CPD_TEST_UNSET_SECRET}", &Secrets::default(), Path::new("settings.toml")).unwrap_err();
+
+        assert!(matches!(error, CpdError::MissingSecret { ref name, .. } if name == "CPD_TEST_UNSET_SECRET"));
+    }
+
+    #[test]
+    fn test_load_secrets_file() {
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join(SECRETS_FILE_NAME), "CPD_TEST_FILE_SECRET = \"s3cret\"\nCPD_TEST_PORT = 8080\n").unwrap();
+
+        let secrets = Secrets::load(temp_dir.path()).unwrap();
+
+        assert_eq!(secrets.get("CPD_TEST_FILE_SECRET").as_deref(), Some("s3cret"));
+        assert_eq!(secrets.get("CPD_TEST_PORT").as_deref(), Some("8080"));
+    }
+
+    #[test]
+    fn test_template_paths() {
+        assert!(is_template(Path::new("settings.toml")));
+        assert!(is_template(Path::new("lib/wifi.json.tmpl")));
+        assert!(!is_template(Path::new("lib/settings.toml")));
+        assert_eq!(target_path(Path::new("lib/wifi.json.tmpl")), PathBuf::from("lib/wifi.json"));
+        assert_eq!(target_path(Path::new("settings.toml")), PathBuf::from("settings.toml"));
+    }
+}
@@ -0,0 +1,160 @@
+use crate::error::{CpdError, Result};
+use crate::file_ops::{hash_bytes, DeployedFile};
+use crate::git::GitInfo;
+use crate::storage::BoardStorage;
+use serde::Serialize;
+use std::path::{Component, Path};
+use std::time::SystemTime;
+
+/// Check that a version file path stays inside the board root and names a
+/// format [`VersionInfo::render`] can write
+pub fn validate_path(path: &Path) -> Result<()> {
+    if path.as_os_str().is_empty() || path.components().any(|c| !matches!(c, Component::Normal(_) | Component::CurDir)) {
+        return Err(CpdError::Configuration {
+            message: format!("Version file must be a path relative to the board root: {}", path.display()),
+        });
+    }
+
+    let extension = path.extension().and_then(|ext| ext.to_str());
+    if !matches!(extension, Some("py") | Some("json")) {
+        return Err(CpdError::Configuration {
+            message: format!("Version file must end in .py or .json: {}", path.display()),
+        });
+    }
+
+    Ok(())
+}
+
+/// Version information written to the board so `code.py` can report what it is running
+#[derive(Debug, Clone, Serialize)]
+pub struct VersionInfo {
+    pub git_commit: Option<String>,
+    pub git_branch: Option<String>,
+    pub git_dirty: bool,
+    pub deployed_at: String,
+    pub cpd_version: String,
+}
+
+impl VersionInfo {
+    /// Collect version information for the project being deployed
+    pub fn collect(project_dir: &Path) -> Self {
+        let git = GitInfo::read(project_dir);
+
+        Self {
+            git_commit: git.as_ref().and_then(|g| g.commit.clone()),
+            git_branch: git.as_ref().and_then(|g| g.branch.clone()),
+            git_dirty: git.map(|g| g.dirty).unwrap_or(false),
+            deployed_at: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+            cpd_version: env!("CARGO_PKG_VERSION").to_string(),
+        }
+    }
+
+    /// Render the version file, choosing the format from the file extension
+    pub fn render(&self, path: &Path) -> Result<String> {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("py") => Ok(self.render_python()),
+            Some("json") => serde_json::to_string_pretty(self).map_err(|e| CpdError::Configuration {
+                message: format!("Failed to serialize version file: {}", e),
+            }),
+            _ => Err(CpdError::Configuration {
+                message: format!("Version file must end in .py or .json: {}", path.display()),
+            }),
+        }
+    }
+
+    fn render_python(&self) -> String {
+        // JSON string literals are also valid Python string literals
+        let string = |value: &Option<String>| match value {
+            Some(s) => serde_json::to_string(s).unwrap_or_else(|_| "None".to_string()),
+            None => "None".to_string(),
+        };
+
+        format!(
+            "# Generated by cpd at deploy time. Do not edit.\n\
+             GIT_COMMIT = {}\n\
+             GIT_BRANCH = {}\n\
+             GIT_DIRTY = {}\n\
+             DEPLOYED_AT = {}\n\
+             CPD_VERSION = {}\n",
+            string(&self.git_commit),
+            string(&self.git_branch),
+            if self.git_dirty { "True" } else { "False" },
+            string(&Some(self.deployed_at.clone())),
+            string(&Some(self.cpd_version.clone())),
+        )
+    }
+
+    /// Write the version file to `relative_path` on the board
+    pub fn write_to_board(&self, board: &dyn BoardStorage, relative_path: &Path) -> Result<DeployedFile> {
+        let content = self.render(relative_path)?;
+
+        if let Some(parent) = relative_path.parent() {
+            board.mkdir(parent)?;
+        }
+        board.write(relative_path, content.as_bytes())?;
+
+        Ok(DeployedFile {
+            relative_path: relative_path.to_path_buf(),
+            size: content.len() as u64,
+            sha256: hash_bytes(content.as_bytes()),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryBoard;
+    use std::path::PathBuf;
+
+    fn sample() -> VersionInfo {
+        VersionInfo {
+            git_commit: Some("abc123".to_string()),
+            git_branch: None,
+            git_dirty: true,
+            deployed_at: "2024-01-01T00:00:00Z".to_string(),
+            cpd_version: "0.1.0".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_python() {
+        let content = sample().render(Path::new("lib/_cpd_version.py")).unwrap();
+
+        assert!(content.contains("GIT_COMMIT = \"abc123\"\n"));
+        assert!(content.contains("GIT_BRANCH = None\n"));
+        assert!(content.contains("GIT_DIRTY = True\n"));
+    }
+
+    #[test]
+    fn test_validate_path() {
+        assert!(validate_path(Path::new("lib/_cpd_version.py")).is_ok());
+        assert!(validate_path(Path::new("./version.json")).is_ok());
+        assert!(validate_path(Path::new("../version.py")).is_err());
+        assert!(validate_path(Path::new("lib/../../version.py")).is_err());
+        assert!(validate_path(Path::new("/version.py")).is_err());
+        assert!(validate_path(Path::new("version.txt")).is_err());
+    }
+
+    #[test]
+    fn test_render_json() {
+        let content = sample().render(Path::new("version.json")).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&content).unwrap();
+
+        assert_eq!(value["git_commit"], "abc123");
+        assert_eq!(value["git_dirty"], true);
+        assert!(sample().render(Path::new("version.txt")).is_err());
+    }
+
+    #[test]
+    fn test_write_to_board() {
+        let board = MemoryBoard::new();
+        let relative = PathBuf::from("lib/_cpd_version.py");
+
+        let deployed = sample().write_to_board(&board, &relative).unwrap();
+
+        let content = board.file(&relative).unwrap();
+        assert_eq!(deployed.relative_path, relative);
+        assert_eq!(deployed.sha256, hash_bytes(&content));
+    }
+}
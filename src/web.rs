@@ -0,0 +1,594 @@
+use crate::error::{CpdError, Result};
+use crate::manifest::board_path_string;
+use crate::storage::{BoardEntry, BoardStorage, temp_path};
+use crate::template::{self, Secrets};
+use base64::Engine;
+use serde::Deserialize;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Prefix of `--board` values that name a Web Workflow board, e.g. `http://cpy-1a2b3c.local`
+pub const HTTP_PREFIX: &str = "http://";
+
+/// `settings.toml` key holding the board's Web Workflow password
+pub const PASSWORD_SETTING: &str = "CIRCUITPY_WEB_API_PASSWORD";
+
+/// A board on the network, written to through the CircuitPython Web
+/// Workflow file API (`/fs/`, CircuitPython 8 and later)
+#[derive(Debug)]
+pub struct WebBoard {
+    url: String,
+    agent: ureq::Agent,
+    authorization: Option<String>,
+}
+
+/// Body of a directory listing: CircuitPython 9 wraps the entries with
+/// free space information, CircuitPython 8 sends the bare list
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum DirectoryListing {
+    Detailed {
+        free: Option<u64>,
+        block_size: Option<u64>,
+        files: Vec<WebEntry>,
+    },
+    Entries(Vec<WebEntry>),
+}
+
+#[derive(Debug, Deserialize)]
+struct WebEntry {
+    name: String,
+    directory: bool,
+    #[serde(default)]
+    file_size: u64,
+}
+
+/// An entry of `/cp/diskinfo.json`
+#[derive(Debug, Deserialize)]
+struct DiskInfo {
+    free: u64,
+    block_size: u64,
+}
+
+impl WebBoard {
+    /// Connect to the board at `url`, checking the password by listing the
+    /// root directory
+    pub fn connect(url: &str, password: Option<&str>) -> Result<Self> {
+        let agent = ureq::AgentBuilder::new()
+            .timeout_connect(Duration::from_secs(5))
+            .timeout_read(Duration::from_secs(30))
+            .timeout_write(Duration::from_secs(30))
+            .build();
+        // The user name is always empty
+        let authorization = password.map(|password| {
+            format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(format!(":{}", password))
+            )
+        });
+
+        let board = Self {
+            url: url.trim_end_matches('/').to_string(),
+            agent,
+            authorization,
+        };
+
+        board
+            .list(Path::new(""))
+            .map_err(|source| CpdError::BoardConnectionFailed {
+                board: board.url.clone(),
+                source,
+            })?;
+
+        Ok(board)
+    }
+
+    /// URL of a file, or of a directory when `directory` is set
+    fn fs_url(&self, path: &Path, directory: bool) -> String {
+        let mut url = format!("{}/fs/{}", self.url, encode_path(path));
+        if directory && !url.ends_with('/') {
+            url.push('/');
+        }
+        url
+    }
+
+    fn request(&self, method: &str, url: &str) -> ureq::Request {
+        let request = self.agent.request(method, url);
+        match &self.authorization {
+            Some(authorization) => request.set("Authorization", authorization),
+            None => request,
+        }
+    }
+
+    fn listing(&self, dir: &Path) -> io::Result<DirectoryListing> {
+        let response = self
+            .request("GET", &self.fs_url(dir, true))
+            .set("Accept", "application/json")
+            .call()
+            .map_err(http_error)?;
+
+        serde_json::from_reader(response.into_reader()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    fn write_temp_file(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        self.request("PUT", &self.fs_url(path, false))
+            .send_bytes(content)
+            .map_err(http_error)?;
+
+        let size = self.stat(path)?.map_or(0, |entry| entry.size);
+        if size != content.len() as u64 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("wrote {} bytes but the board has {}", content.len(), size),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl BoardStorage for WebBoard {
+    fn root(&self) -> PathBuf {
+        PathBuf::from(&self.url)
+    }
+
+    fn list(&self, dir: &Path) -> io::Result<Vec<BoardEntry>> {
+        let files = match self.listing(dir)? {
+            DirectoryListing::Detailed { files, .. } | DirectoryListing::Entries(files) => files,
+        };
+
+        let mut entries: Vec<BoardEntry> = files
+            .into_iter()
+            .map(|entry| BoardEntry {
+                path: dir.join(entry.name),
+                is_dir: entry.directory,
+                size: if entry.directory { 0 } else { entry.file_size },
+            })
+            .collect();
+        entries.sort_by(|a, b| a.path.cmp(&b.path));
+        Ok(entries)
+    }
+
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let response = self.request("GET", &self.fs_url(path, false)).call().map_err(http_error)?;
+
+        let mut content = Vec::new();
+        response.into_reader().read_to_end(&mut content)?;
+        Ok(content)
+    }
+
+    fn write(&self, path: &Path, content: &[u8]) -> io::Result<()> {
+        // A dropped connection mid-upload only ever truncates the temporary file
+        let temp_path = temp_path(path);
+        if let Err(e) = self.write_temp_file(&temp_path, content) {
+            let _ = self.delete(&temp_path);
+            return Err(e);
+        }
+
+        self.rename(&temp_path, path)
+    }
+
+    fn delete(&self, path: &Path) -> io::Result<()> {
+        self.request("DELETE", &self.fs_url(path, false))
+            .call()
+            .map(|_| ())
+            .map_err(http_error)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> io::Result<()> {
+        // The board won't move a file onto an existing one
+        match self.delete(to) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => {}
+        }
+
+        self.request("MOVE", &self.fs_url(from, false))
+            .set("X-Destination", &format!("/fs/{}", encode_path(to)))
+            .call()
+            .map(|_| ())
+            .map_err(http_error)
+    }
+
+    fn mkdir(&self, path: &Path) -> io::Result<()> {
+        // The board only creates the last directory of a path
+        let mut ancestors: Vec<&Path> = path
+            .ancestors()
+            .filter(|ancestor| !ancestor.as_os_str().is_empty())
+            .collect();
+        ancestors.reverse();
+
+        for ancestor in ancestors {
+            self.request("PUT", &self.fs_url(ancestor, true))
+                .call()
+                .map_err(http_error)?;
+        }
+        Ok(())
+    }
+
+    fn stat(&self, path: &Path) -> io::Result<Option<BoardEntry>> {
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return Ok(Some(BoardEntry {
+                path: PathBuf::new(),
+                is_dir: true,
+                size: 0,
+            }));
+        };
+
+        match self.list(parent) {
+            Ok(entries) => Ok(entries.into_iter().find(|entry| entry.path.file_name() == Some(name))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn free_space(&self) -> Option<u64> {
+        if let Ok(DirectoryListing::Detailed {
+            free: Some(free),
+            block_size: Some(block_size),
+            ..
+        }) = self.listing(Path::new(""))
+        {
+            return Some(free * block_size);
+        }
+
+        // CircuitPython 8 only reports it separately
+        let response = self
+            .request("GET", &format!("{}/cp/diskinfo.json", self.url))
+            .call()
+            .ok()?;
+        let disks: Vec<DiskInfo> = serde_json::from_reader(response.into_reader()).ok()?;
+        disks.first().map(|disk| disk.free * disk.block_size)
+    }
+}
+
+/// The Web Workflow password for deploying a project.
+///
+/// Taken from the `CIRCUITPY_WEB_API_PASSWORD` environment variable or
+/// `cpd.secrets.toml`, or else from the project's `settings.toml`, which
+/// sets the password on the board (placeholders in it are rendered).
+pub fn web_api_password(project_dir: &Path) -> Result<Option<String>> {
+    let secrets = Secrets::load(project_dir)?;
+    if let Some(password) = secrets.get(PASSWORD_SETTING) {
+        return Ok(Some(password));
+    }
+
+    let settings_path = project_dir.join("settings.toml");
+    let Ok(content) = fs::read_to_string(&settings_path) else {
+        return Ok(None);
+    };
+    let settings: toml::Table = toml::from_str(&content).map_err(|e| CpdError::Configuration {
+        message: format!("{}: {}", settings_path.display(), e),
+    })?;
+
+    match settings.get(PASSWORD_SETTING) {
+        Some(toml::Value::String(value)) => {
+            let rendered = template::render(value, &secrets, Path::new("settings.toml"))?;
+            Ok(Some(rendered.content))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// What a board says about itself at `/cp/version.json`
+#[derive(Debug, Deserialize)]
+pub struct DeviceInfo {
+    pub board_id: Option<String>,
+    pub version: Option<String>,
+}
+
+/// Fetch the board's `/cp/version.json`, which needs no password
+pub fn device_info(url: &str) -> io::Result<DeviceInfo> {
+    let response = ureq::AgentBuilder::new()
+        .timeout(Duration::from_secs(2))
+        .build()
+        .get(&format!("{}/cp/version.json", url.trim_end_matches('/')))
+        .call()
+        .map_err(http_error)?;
+
+    serde_json::from_reader(response.into_reader()).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Turn an HTTP failure into an I/O error of the matching kind
+fn http_error(error: ureq::Error) -> io::Error {
+    match error {
+        ureq::Error::Status(status, response) => {
+            let (kind, message) = match status {
+                401 => (
+                    io::ErrorKind::PermissionDenied,
+                    format!("the board rejected the password, check {}", PASSWORD_SETTING),
+                ),
+                403 => (
+                    io::ErrorKind::PermissionDenied,
+                    format!("the board has no {} in its settings.toml", PASSWORD_SETTING),
+                ),
+                404 => (io::ErrorKind::NotFound, "not found on the board".to_string()),
+                409 => (
+                    io::ErrorKind::ReadOnlyFilesystem,
+                    "the board's drive is in use over USB, so it can't be written over the network".to_string(),
+                ),
+                413 | 507 => (io::ErrorKind::StorageFull, "no space left on the board".to_string()),
+                _ => (io::ErrorKind::Other, format!("HTTP {} {}", status, response.status_text())),
+            };
+            io::Error::new(kind, message)
+        }
+        ureq::Error::Transport(transport) => {
+            let kind = match transport.kind() {
+                ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed => io::ErrorKind::NotConnected,
+                _ => std::error::Error::source(&transport)
+                    .and_then(|source| source.downcast_ref::<io::Error>())
+                    .map(io::Error::kind)
+                    .unwrap_or(io::ErrorKind::Other),
+            };
+            io::Error::new(kind, transport.to_string())
+        }
+    }
+}
+
+/// Percent-encode a board-relative path for use in a URL
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for byte in board_path_string(path).bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => encoded.push(byte as char),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::deploy::{BoardSelector, Deployer};
+    use crate::storage::MemoryBoard;
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::{TcpListener, TcpStream};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread::{self, JoinHandle};
+    use tempfile::TempDir;
+
+    /// A loopback HTTP server implementing the Web Workflow file API on top
+    /// of a `MemoryBoard`, one request per connection
+    struct StubServer {
+        url: String,
+        stop: Arc<AtomicBool>,
+        handle: JoinHandle<MemoryBoard>,
+    }
+
+    impl StubServer {
+        fn start(board: MemoryBoard, password: &'static str) -> Self {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let stop = Arc::new(AtomicBool::new(false));
+            let stopping = Arc::clone(&stop);
+
+            let handle = thread::spawn(move || {
+                for stream in listener.incoming() {
+                    if stopping.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    handle_request(&board, stream.unwrap(), password);
+                }
+                board
+            });
+
+            Self { url, stop, handle }
+        }
+
+        /// Stop serving and hand back the board
+        fn finish(self) -> MemoryBoard {
+            self.stop.store(true, Ordering::SeqCst);
+            let _ = TcpStream::connect(self.url.trim_start_matches(HTTP_PREFIX));
+            self.handle.join().unwrap()
+        }
+    }
+
+    fn handle_request(board: &MemoryBoard, mut stream: TcpStream, password: &str) {
+        let mut reader = BufReader::new(stream.try_clone().unwrap());
+        let mut request_line = String::new();
+        reader.read_line(&mut request_line).unwrap();
+        let mut parts = request_line.split_whitespace();
+        let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+
+        let mut content_length = 0;
+        let mut destination = String::new();
+        let mut authorized = false;
+        loop {
+            let mut header = String::new();
+            reader.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            let (name, value) = header.split_once(": ").unwrap();
+            match name.to_ascii_lowercase().as_str() {
+                "content-length" => content_length = value.parse().unwrap(),
+                "x-destination" => destination = value.to_string(),
+                "authorization" => {
+                    let expected = base64::engine::general_purpose::STANDARD.encode(format!(":{}", password));
+                    authorized = value == format!("Basic {}", expected);
+                }
+                _ => {}
+            }
+        }
+        let mut body = vec![0; content_length];
+        reader.read_exact(&mut body).unwrap();
+
+        let (status, response) = if !authorized {
+            (401, Vec::new())
+        } else {
+            serve(board, method, target, &destination, &body)
+        };
+        write!(
+            stream,
+            "HTTP/1.1 {} Stub\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            status,
+            response.len()
+        )
+        .unwrap();
+        stream.write_all(&response).unwrap();
+    }
+
+    fn serve(board: &MemoryBoard, method: &str, target: &str, destination: &str, body: &[u8]) -> (u16, Vec<u8>) {
+        let Some(path) = target.strip_prefix("/fs/") else {
+            return (404, Vec::new());
+        };
+        let directory = path.is_empty() || path.ends_with('/');
+        let path = PathBuf::from(decode_path(path.trim_end_matches('/')));
+
+        let status = |result: io::Result<()>, success| match result {
+            Ok(()) => (success, Vec::new()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => (404, Vec::new()),
+            Err(e) if e.kind() == io::ErrorKind::StorageFull => (413, Vec::new()),
+            Err(_) => (500, Vec::new()),
+        };
+
+        match (method, directory) {
+            ("GET", true) => match board.list(&path) {
+                Ok(entries) => {
+                    let files: Vec<serde_json::Value> = entries
+                        .iter()
+                        .map(|entry| {
+                            serde_json::json!({
+                                "name": entry.path.file_name().unwrap().to_string_lossy(),
+                                "directory": entry.is_dir,
+                                "modified_ns": 0,
+                                "file_size": entry.size,
+                            })
+                        })
+                        .collect();
+                    let listing = serde_json::json!({"free": 100, "block_size": 512, "writable": true, "files": files});
+                    (200, listing.to_string().into_bytes())
+                }
+                Err(_) => (404, Vec::new()),
+            },
+            ("GET", false) => match board.read(&path) {
+                Ok(content) => (200, content),
+                Err(_) => (404, Vec::new()),
+            },
+            ("PUT", true) => status(board.mkdir(&path), 201),
+            ("PUT", false) => status(board.write(&path, body), 201),
+            ("DELETE", _) => status(board.delete(&path), 204),
+            ("MOVE", false) => {
+                let Some(destination) = destination.strip_prefix("/fs/") else {
+                    return (400, Vec::new());
+                };
+                let destination = PathBuf::from(decode_path(destination));
+                if board.exists(&destination) {
+                    return (409, Vec::new());
+                }
+                status(board.rename(&path, &destination), 201)
+            }
+            _ => (405, Vec::new()),
+        }
+    }
+
+    fn decode_path(path: &str) -> String {
+        let bytes = path.as_bytes();
+        let mut decoded = Vec::new();
+        let mut i = 0;
+        while i < bytes.len() {
+            if bytes[i] == b'%' {
+                decoded.push(u8::from_str_radix(&path[i + 1..i + 3], 16).unwrap());
+                i += 3;
+            } else {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+        String::from_utf8(decoded).unwrap()
+    }
+
+    #[test]
+    fn test_encode_path() {
+        assert_eq!(encode_path(Path::new("lib/my sensor.py")), "lib/my%20sensor.py");
+        assert_eq!(decode_path(&encode_path(Path::new("lib/ü.py"))), "lib/ü.py");
+    }
+
+    #[test]
+    fn test_web_api_password() {
+        let project = TempDir::new().unwrap();
+        assert_eq!(web_api_password(project.path()).unwrap(), None);
+
+        fs::write(
+            project.path().join("settings.toml"),
+            "CIRCUITPY_WEB_API_PASSWORD = \"${CPD_TEST_WEB_PASSWORD}\"\n",
+        )
+        .unwrap();
+        fs::write(project.path().join("cpd.secrets.toml"), "CPD_TEST_WEB_PASSWORD = \"hunter2\"\n").unwrap();
+        assert_eq!(web_api_password(project.path()).unwrap().as_deref(), Some("hunter2"));
+    }
+
+    #[test]
+    fn test_web_board_storage() {
+        let server = StubServer::start(MemoryBoard::new().with_file("boot_out.txt", "Board ID:adafruit_feather_esp32s3\n"), "secret");
+
+        let error = WebBoard::connect(&server.url, Some("wrong")).unwrap_err();
+        assert!(matches!(error, CpdError::BoardConnectionFailed { .. }));
+        assert_eq!(error.io_error().unwrap().kind(), io::ErrorKind::PermissionDenied);
+
+        let board = WebBoard::connect(&server.url, Some("secret")).unwrap();
+        board.mkdir(Path::new("lib/sensors")).unwrap();
+        board.write(Path::new("lib/sensors/my sensor.py"), b"import board").unwrap();
+
+        assert_eq!(board.read(Path::new("lib/sensors/my sensor.py")).unwrap(), b"import board");
+        assert_eq!(board.stat(Path::new("lib/sensors/my sensor.py")).unwrap().unwrap().size, 12);
+        assert!(board.stat(Path::new("lib")).unwrap().unwrap().is_dir);
+        assert!(board.stat(Path::new("missing/code.py")).unwrap().is_none());
+        assert_eq!(board.free_space(), Some(51200));
+
+        board.delete(Path::new("boot_out.txt")).unwrap();
+        let error = board.read(Path::new("boot_out.txt")).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+
+        let memory = server.finish();
+        assert_eq!(memory.files(), vec![PathBuf::from("lib/sensors/my sensor.py")]);
+    }
+
+    #[test]
+    fn test_failed_write_keeps_old_file() {
+        let server = StubServer::start(MemoryBoard::new().with_file("code.py", "print('old')").with_capacity(30), "secret");
+
+        let board = WebBoard::connect(&server.url, Some("secret")).unwrap();
+        let error = board.write(Path::new("code.py"), b"print('a much longer program')").unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::StorageFull);
+        assert_eq!(board.read(Path::new("code.py")).unwrap(), b"print('old')");
+        assert!(board.stat(Path::new("code.py.cpdtmp")).unwrap().is_none());
+
+        board.write(Path::new("code.py"), b"print('new')").unwrap();
+        assert_eq!(board.read(Path::new("code.py")).unwrap(), b"print('new')");
+
+        let memory = server.finish();
+        assert_eq!(memory.files(), vec![PathBuf::from("code.py")]);
+    }
+
+    #[test]
+    fn test_deploy_over_http() {
+        let project = TempDir::new().unwrap();
+        fs::write(project.path().join("code.py"), "print('hello')").unwrap();
+        fs::write(project.path().join("notes.txt"), "not for the board").unwrap();
+        fs::write(project.path().join(".cpdignore"), "*.txt\n").unwrap();
+        fs::write(project.path().join("cpd.secrets.toml"), "CIRCUITPY_WEB_API_PASSWORD = \"secret\"\n").unwrap();
+
+        let server = StubServer::start(MemoryBoard::new().with_file("boot_out.txt", "Board ID:adafruit_feather_esp32s3\n"), "secret");
+        let board = BoardSelector::from_location(Path::new(&server.url), project.path()).unwrap();
+        let report = Deployer::builder(project.path())
+            .board(board)
+            .verify(1)
+            .build()
+            .run()
+            .unwrap();
+
+        assert!(report.is_success());
+        assert_eq!(report.result.files_copied, 1);
+        assert_eq!(report.board.board_id.as_deref(), Some("adafruit_feather_esp32s3"));
+
+        let memory = server.finish();
+        assert_eq!(memory.file("code.py").unwrap(), b"print('hello')");
+        assert!(memory.file("notes.txt").is_none());
+        assert!(memory.file(".cpd_manifest.json").is_some());
+    }
+}
@@ -1,10 +1,21 @@
+use std::cell::RefCell;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 use tempfile::TempDir;
 
 use circuitpython_deploy::board::BoardDetector;
+use circuitpython_deploy::config::LayoutConfig;
+use circuitpython_deploy::deploy::{BoardSelector, Deployer};
+use circuitpython_deploy::error::{exit_code, CpdError};
 use circuitpython_deploy::ignore::IgnoreFilter;
-use circuitpython_deploy::file_ops::FileOperations;
+use circuitpython_deploy::layout::PathMapper;
+use circuitpython_deploy::file_ops::{DeployedFile, FileOperations};
+use circuitpython_deploy::manifest::DeployManifest;
+use circuitpython_deploy::progress::{Batch, DeployObserver, Operation, SkipReason};
+use circuitpython_deploy::protect::ProtectedPaths;
+use circuitpython_deploy::storage::{BoardStorage, LocalBoard, MemoryBoard};
+use circuitpython_deploy::template::Secrets;
 
 /// Test that basic board detection works
 #[test]
@@ -71,7 +82,7 @@ fn test_file_operations() {
     fs::create_dir_all(source_dir.path().join("lib")).unwrap();
     fs::write(source_dir.path().join("lib/utils.py"), "# utils").unwrap();
     
-    let file_ops = FileOperations::new(false);
+    let file_ops = FileOperations::new();
     
     // Test copying with a filter that includes everything
     let filter = |_: &Path| true;
@@ -106,7 +117,7 @@ fn test_backup_functionality() {
     fs::create_dir_all(source_dir.path().join("old_lib")).unwrap();
     fs::write(source_dir.path().join("old_lib/old.py"), "# old lib").unwrap();
     
-    let file_ops = FileOperations::new(false);
+    let file_ops = FileOperations::new();
     
     // Create backup
     file_ops.create_backup(source_dir.path(), backup_dir.path()).unwrap();
@@ -129,7 +140,7 @@ fn test_dry_run_mode() {
     // Create source files
     fs::write(source_dir.path().join("test.py"), "print('test')").unwrap();
     
-    let file_ops = FileOperations::new(false);
+    let file_ops = FileOperations::new();
     let filter = |_: &Path| true;
     
     // Run in dry-run mode
@@ -182,3 +193,559 @@ fn test_special_characters() {
     assert!(filter.should_include(&project_path.join("file-with-dashes.py")));
     assert!(filter.should_include(&project_path.join("file_with_underscores.py")));
 }
+
+/// Test that clean only removes files recorded in the deployment manifest
+#[test]
+fn test_clean_destination_uses_manifest() {
+    let source_dir = TempDir::new().unwrap();
+    let board_dir = TempDir::new().unwrap();
+    
+    fs::write(source_dir.path().join("code.py"), "print('v1')").unwrap();
+    fs::write(source_dir.path().join("old.py"), "# removed later").unwrap();
+    
+    let file_ops = FileOperations::new();
+    let filter = |_: &Path| true;
+    
+    let result = file_ops.copy_directory_contents(
+        source_dir.path(),
+        board_dir.path(),
+        &filter,
+        false,
+    ).unwrap();
+    DeployManifest::merged(None, &result.deployed_files, None)
+        .write(&LocalBoard::new(board_dir.path()))
+        .unwrap();
+    
+    // A file created on the device, unknown to cpd
+    fs::write(board_dir.path().join("data.csv"), "1,2,3").unwrap();
+    fs::remove_file(source_dir.path().join("old.py")).unwrap();
+    
+    file_ops.clean_destination(source_dir.path(), board_dir.path(), &filter).unwrap();
+    
+    assert!(board_dir.path().join("code.py").exists());
+    assert!(!board_dir.path().join("old.py").exists());
+    assert!(board_dir.path().join("data.csv").exists());
+    
+    let manifest = DeployManifest::load(&LocalBoard::new(board_dir.path()), &|_| {}).unwrap().unwrap();
+    assert!(manifest.contains(Path::new("code.py")));
+    assert!(!manifest.contains(Path::new("old.py")));
+}
+
+/// Test that ignored directories are never descended into
+#[test]
+fn test_ignored_directories_are_pruned() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path();
+    
+    fs::write(project_path.join("code.py"), "print('hello')").unwrap();
+    fs::create_dir_all(project_path.join("node_modules/pkg/dist")).unwrap();
+    fs::write(project_path.join("node_modules/pkg/dist/index.js"), "// js").unwrap();
+    fs::create_dir_all(project_path.join("backups")).unwrap();
+    fs::write(project_path.join("backups/old.py"), "# old").unwrap();
+    fs::write(project_path.join(".cpdignore"), "backups/\n").unwrap();
+    
+    let ignore_filter = IgnoreFilter::new(project_path).unwrap();
+    let visited = RefCell::new(Vec::<PathBuf>::new());
+    let filter = |path: &Path| {
+        visited.borrow_mut().push(path.to_path_buf());
+        ignore_filter.should_include(path)
+    };
+    
+    let file_ops = FileOperations::new();
+    let files = file_ops.collect_files(project_path, &filter);
+    
+    assert_eq!(files, vec![project_path.join("code.py")]);
+    assert!(visited.borrow().contains(&project_path.join("node_modules")));
+    assert!(!visited.borrow().iter().any(|p| p.starts_with(project_path.join("node_modules/pkg"))));
+    assert!(!visited.borrow().contains(&project_path.join("backups/old.py")));
+}
+
+/// Test that a project directory given as a relative path filters correctly
+#[test]
+fn test_relative_project_dir() {
+    // Create the project under the current directory so it can be named relatively
+    let temp_dir = TempDir::new_in(".").unwrap();
+    let name = temp_dir.path().file_name().unwrap();
+    let project_path = Path::new(name).join("test_project");
+    fs::create_dir_all(&project_path).unwrap();
+    fs::write(project_path.join("code.py"), "print('hello')").unwrap();
+    fs::write(project_path.join(".cpdignore"), "test_*\n").unwrap();
+    
+    // Patterns must not match the project directory's own name
+    let filter = IgnoreFilter::new(&project_path).unwrap();
+    assert!(filter.should_include(&project_path.join("code.py")));
+    
+    let file_ops = FileOperations::new();
+    let files = file_ops.collect_files(&project_path, &|path| filter.should_include(path));
+    assert_eq!(files, vec![project_path.join("code.py")]);
+}
+
+/// Test that protected board files are never overwritten or deleted
+#[test]
+fn test_protected_board_files() {
+    let project_dir = TempDir::new().unwrap();
+    let board_dir = TempDir::new().unwrap();
+    let project_path = project_dir.path();
+    let board_path = board_dir.path();
+    
+    fs::write(project_path.join("code.py"), "print('hello')").unwrap();
+    fs::write(project_path.join("settings.toml"), "CIRCUITPY_WIFI_SSID = \"dev\"").unwrap();
+    fs::write(board_path.join("settings.toml"), "CIRCUITPY_WIFI_SSID = \"home\"").unwrap();
+    fs::create_dir_all(board_path.join("logs")).unwrap();
+    fs::write(board_path.join("logs/today.csv"), "1,2,3").unwrap();
+    
+    let protected = ProtectedPaths::new(&["logs/".to_string()], true).unwrap();
+    let file_ops = FileOperations::new().with_protected_paths(protected);
+    let files = file_ops.collect_files(project_path, &|_| true);
+    
+    let result = file_ops.copy_files(project_path, board_path, &files, true, None).unwrap();
+    assert_eq!(result.files_copied, 1);
+    assert_eq!(result.protected_files, vec![PathBuf::from("settings.toml")]);
+    
+    let result = file_ops.copy_files(project_path, board_path, &files, false, None).unwrap();
+    assert_eq!(result.files_copied, 1);
+    assert_eq!(fs::read_to_string(board_path.join("settings.toml")).unwrap(), "CIRCUITPY_WIFI_SSID = \"home\"");
+    
+    // A manifest that claims the log file doesn't make it deletable
+    let manifest = DeployManifest::merged(
+        None,
+        &[DeployedFile {
+            relative_path: PathBuf::from("logs/today.csv"),
+            size: 5,
+            sha256: String::new(),
+        }],
+        None,
+    );
+    manifest.write(&LocalBoard::new(board_path)).unwrap();
+    file_ops.clean_destination(project_path, board_path, &|_| true).unwrap();
+    assert!(board_path.join("logs/today.csv").exists());
+    
+    // Without protection the board copy is replaced
+    let result = FileOperations::new().copy_files(project_path, board_path, &files, false, None).unwrap();
+    assert!(result.protected_files.is_empty());
+    assert_eq!(fs::read_to_string(board_path.join("settings.toml")).unwrap(), "CIRCUITPY_WIFI_SSID = \"dev\"");
+}
+
+/// Test that --overwrite-protected replaces protected files but still validates the board
+#[test]
+fn test_overwrite_protected() {
+    let project_dir = TempDir::new().unwrap();
+    let board_dir = TempDir::new().unwrap();
+    let project_path = project_dir.path();
+    let board_path = board_dir.path();
+    
+    fs::create_dir_all(project_path.join("sd")).unwrap();
+    fs::write(project_path.join("sd/data.csv"), "new").unwrap();
+    fs::create_dir_all(board_path.join("sd")).unwrap();
+    fs::write(board_path.join("sd/data.csv"), "logged").unwrap();
+    
+    let deploy = |overwrite_protected| {
+        Deployer::builder(project_path)
+            .board(BoardSelector::Path(board_path.to_path_buf()))
+            .overwrite_protected(overwrite_protected)
+            .build()
+            .run()
+    };
+    
+    // Not a CircuitPython board yet
+    assert!(matches!(deploy(true), Err(CpdError::InvalidBoardPath { .. })));
+    
+    fs::write(board_path.join("boot_out.txt"), "Adafruit CircuitPython 9.0.0\n").unwrap();
+    let report = deploy(false).unwrap();
+    assert_eq!(report.result.protected_files, vec![PathBuf::from("sd/data.csv")]);
+    assert_eq!(fs::read_to_string(board_path.join("sd/data.csv")).unwrap(), "logged");
+    
+    let report = deploy(true).unwrap();
+    assert!(report.result.protected_files.is_empty());
+    assert_eq!(fs::read_to_string(board_path.join("sd/data.csv")).unwrap(), "new");
+}
+
+/// Test that templates are rendered with secrets before being written to the board
+#[test]
+fn test_templates_rendered_on_deploy() {
+    let project_dir = TempDir::new().unwrap();
+    let board_dir = TempDir::new().unwrap();
+    let project_path = project_dir.path();
+    let board_path = board_dir.path();
+    
+    fs::write(project_path.join("settings.toml"), "CIRCUITPY_WIFI_PASSWORD = \"${CPD_IT_WIFI_PASSWORD}\"\n").unwrap();
+    fs::write(project_path.join("secrets.json.tmpl"), "{\"token\": \"${CPD_IT_TOKEN}\"}").unwrap();
+    fs::write(project_path.join("cpd.secrets.toml"), "CPD_IT_WIFI_PASSWORD = \"hunter2\"\nCPD_IT_TOKEN = \"abc\"\n").unwrap();
+    
+    let ignore_filter = IgnoreFilter::new(project_path).unwrap();
+    let file_ops = FileOperations::new()
+        .with_verification(0)
+        .with_templates(Secrets::load(project_path).unwrap());
+    let files = file_ops.collect_files(project_path, &|path| ignore_filter.should_include(path));
+    
+    let result = file_ops.copy_files(project_path, board_path, &files, false, None).unwrap();
+    
+    assert_eq!(result.files_copied, 2);
+    assert_eq!(fs::read_to_string(board_path.join("settings.toml")).unwrap(), "CIRCUITPY_WIFI_PASSWORD = \"hunter2\"\n");
+    assert_eq!(fs::read_to_string(board_path.join("secrets.json")).unwrap(), "{\"token\": \"abc\"}");
+    assert!(!board_path.join("secrets.json.tmpl").exists());
+    assert!(!board_path.join("cpd.secrets.toml").exists());
+    assert!(result.deployed_files.iter().any(|f| f.relative_path == Path::new("secrets.json")));
+    
+    // A missing secret fails that file only
+    fs::remove_file(project_path.join("cpd.secrets.toml")).unwrap();
+    let file_ops = FileOperations::new().with_templates(Secrets::load(project_path).unwrap());
+    let result = file_ops.copy_files(project_path, board_path, &files, true, None).unwrap();
+    assert_eq!(result.files_failed, 2);
+    
+    // So does a template that can't be read as text
+    fs::write(project_path.join("secrets.json.tmpl"), b"\xff\xfe").unwrap();
+    fs::write(project_path.join("settings.toml"), "CIRCUITPY_WIFI_SSID = \"home\"\n").unwrap();
+    let result = file_ops.copy_files(project_path, board_path, &files, false, None).unwrap();
+    assert_eq!(result.files_copied, 1);
+    assert_eq!(result.files_failed, 1);
+    assert_eq!(result.failed_files[0].0, project_path.join("secrets.json.tmpl"));
+    assert_eq!(fs::read_to_string(board_path.join("settings.toml")).unwrap(), "CIRCUITPY_WIFI_SSID = \"home\"\n");
+}
+
+/// Test that a rendered settings.toml replaces the board's copy despite the default protection
+#[test]
+fn test_template_replaces_protected_settings() {
+    let project_dir = TempDir::new().unwrap();
+    let board_dir = TempDir::new().unwrap();
+    let project_path = project_dir.path();
+    let board_path = board_dir.path();
+    
+    fs::write(project_path.join("code.py"), "print('hello')").unwrap();
+    fs::write(project_path.join("settings.toml"), "CIRCUITPY_WIFI_SSID = \"${CPD_IT_PROTECTED_SSID}\"\n").unwrap();
+    fs::write(project_path.join("cpd.secrets.toml"), "CPD_IT_PROTECTED_SSID = \"office\"\n").unwrap();
+    fs::write(board_path.join("boot_out.txt"), "Adafruit CircuitPython 9.0.0\n").unwrap();
+    fs::write(board_path.join("settings.toml"), "CIRCUITPY_WIFI_SSID = \"home\"\n").unwrap();
+    
+    let report = Deployer::builder(project_path)
+        .board(BoardSelector::Path(board_path.to_path_buf()))
+        .build()
+        .run()
+        .unwrap();
+    
+    assert!(report.is_success());
+    assert!(report.result.protected_files.is_empty());
+    assert_eq!(fs::read_to_string(board_path.join("settings.toml")).unwrap(), "CIRCUITPY_WIFI_SSID = \"office\"\n");
+    assert_eq!(fs::read_to_string(board_path.join("boot_out.txt")).unwrap(), "Adafruit CircuitPython 9.0.0\n");
+}
+
+/// Test that the layout maps project directories onto the board
+#[test]
+fn test_layout_mapping() {
+    let project_dir = TempDir::new().unwrap();
+    let board_dir = TempDir::new().unwrap();
+    let project_path = project_dir.path();
+    let board_path = board_dir.path();
+    
+    for name in ["src/code.py", "src/lib/helper.py", "assets/fonts/big.bdf", "tools/flash.sh"] {
+        fs::create_dir_all(project_path.join(name).parent().unwrap()).unwrap();
+        fs::write(project_path.join(name), name).unwrap();
+    }
+    // Ignore rules still see project paths
+    fs::write(project_path.join(".cpdignore"), "src/lib/\n").unwrap();
+    
+    let layout = PathMapper::new(&LayoutConfig {
+        source_root: Some(PathBuf::from("src")),
+        map: [(PathBuf::from("assets/fonts"), PathBuf::from("/fonts"))].into(),
+    })
+    .unwrap();
+    let ignore_filter = IgnoreFilter::new(project_path).unwrap();
+    let file_ops = FileOperations::new().with_layout(layout.clone());
+    
+    let mut files = file_ops.collect_files(project_path, &|path| ignore_filter.should_include(path));
+    files.retain(|file| layout.covers(file.strip_prefix(project_path).unwrap()));
+    let result = file_ops.copy_files(project_path, board_path, &files, false, None).unwrap();
+    
+    assert_eq!(result.files_copied, 2);
+    assert!(board_path.join("code.py").exists());
+    assert!(board_path.join("fonts/big.bdf").exists());
+    assert!(!board_path.join("lib/helper.py").exists());
+    assert!(!board_path.join("tools").exists());
+    
+    // Cleaning understands the mapping and keeps mapped files
+    DeployManifest::merged(None, &result.deployed_files, None).write(&LocalBoard::new(board_path)).unwrap();
+    file_ops.clean_destination(project_path, board_path, &|path| ignore_filter.should_include(path)).unwrap();
+    assert!(board_path.join("code.py").exists());
+    assert!(board_path.join("fonts/big.bdf").exists());
+    
+    fs::remove_file(project_path.join("assets/fonts/big.bdf")).unwrap();
+    file_ops.clean_destination(project_path, board_path, &|path| ignore_filter.should_include(path)).unwrap();
+    assert!(!board_path.join("fonts/big.bdf").exists());
+}
+
+#[derive(Default)]
+struct RecordingObserver {
+    events: Mutex<Vec<String>>,
+}
+
+impl DeployObserver for RecordingObserver {
+    fn planned(&self, batch: &Batch) {
+        self.events.lock().unwrap().push(format!("planned {:?} {}", batch.operation, batch.total));
+    }
+    
+    fn copied(&self, path: &Path, _target: &Path, bytes: u64, _retries: u32) {
+        self.events.lock().unwrap().push(format!("copied {} {}", path.display(), bytes));
+    }
+    
+    fn removed(&self, target: &Path) {
+        self.events.lock().unwrap().push(format!("removed {}", target.display()));
+    }
+    
+    fn skipped(&self, path: &Path, _target: &Path, reason: SkipReason) {
+        self.events.lock().unwrap().push(format!("skipped {} {:?}", path.display(), reason));
+    }
+    
+    fn finished(&self, operation: Operation) {
+        self.events.lock().unwrap().push(format!("finished {:?}", operation));
+    }
+}
+
+#[test]
+fn test_observer_events() {
+    let project_dir = TempDir::new().unwrap();
+    let board_dir = TempDir::new().unwrap();
+    let backup_dir = TempDir::new().unwrap();
+    let project_path = project_dir.path();
+    let board_path = board_dir.path();
+    
+    fs::write(project_path.join("code.py"), "print('hello')").unwrap();
+    fs::write(project_path.join("settings.toml"), "CIRCUITPY_WIFI_SSID = \"dev\"").unwrap();
+    fs::write(board_path.join("settings.toml"), "CIRCUITPY_WIFI_SSID = \"home\"").unwrap();
+    fs::write(board_path.join("old.py"), "").unwrap();
+    
+    let observer = Arc::new(RecordingObserver::default());
+    let file_ops = FileOperations::new()
+        .with_protected_paths(ProtectedPaths::default())
+        .with_observer(observer.clone());
+    let mut files = file_ops.collect_files(project_path, &|_| true);
+    files.sort();
+    
+    file_ops.create_backup(board_path, backup_dir.path()).unwrap();
+    let result = file_ops.copy_files(project_path, board_path, &files, false, None).unwrap();
+    DeployManifest::merged(
+        None,
+        &[DeployedFile {
+            relative_path: PathBuf::from("old.py"),
+            size: 0,
+            sha256: String::new(),
+        }],
+        None,
+    )
+    .write(&LocalBoard::new(board_path))
+    .unwrap();
+    file_ops.clean_destination(project_path, board_path, &|_| true).unwrap();
+    
+    assert_eq!(result.files_copied, 1);
+    let mut events = observer.events.lock().unwrap().clone();
+    // Backed up files arrive in directory order
+    events[1..3].sort();
+    assert_eq!(
+        events,
+        vec![
+            "planned Backup 2",
+            "copied old.py 0",
+            "copied settings.toml 28",
+            "finished Backup",
+            "planned Deploy 2",
+            "copied code.py 14",
+            "skipped settings.toml Protected",
+            "finished Deploy",
+            "planned Clean 1",
+            "removed old.py",
+            "finished Clean",
+        ]
+    );
+}
+
+#[test]
+fn test_deployer() {
+    let project_dir = TempDir::new().unwrap();
+    let board_dir = TempDir::new().unwrap();
+    let project_path = project_dir.path();
+    let board_path = board_dir.path();
+    
+    fs::write(project_path.join("code.py"), "print('hello')").unwrap();
+    fs::write(project_path.join("notes.txt"), "not for the board").unwrap();
+    fs::write(board_path.join("boot_out.txt"), "Adafruit CircuitPython 9.0.0\nBoard ID:raspberry_pi_pico\n").unwrap();
+    fs::write(board_path.join("code.py"), "").unwrap();
+    
+    let planned = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&planned);
+    let report = Deployer::builder(project_path)
+        .board(BoardSelector::Path(board_path.to_path_buf()))
+        .filter(|path| path.extension().is_none_or(|ext| ext != "txt"))
+        .confirm(move |plan| {
+            recorded.lock().unwrap().extend(plan.files.iter().map(|file| file.target.clone()));
+            true
+        })
+        .build()
+        .run()
+        .unwrap();
+    
+    assert_eq!(*planned.lock().unwrap(), vec![PathBuf::from("code.py")]);
+    assert!(report.is_success());
+    assert!(report.manifest_written);
+    assert_eq!(report.board.board_id.as_deref(), Some("raspberry_pi_pico"));
+    assert_eq!(fs::read_to_string(board_path.join("code.py")).unwrap(), "print('hello')");
+    assert!(!board_path.join("notes.txt").exists());
+    
+    // Declining the plan leaves the board alone
+    fs::write(project_path.join("code.py"), "print('changed')").unwrap();
+    let cancelled = Deployer::builder(project_path)
+        .board(BoardSelector::Path(board_path.to_path_buf()))
+        .confirm(|_| false)
+        .build()
+        .run();
+    assert!(matches!(cancelled, Err(CpdError::Cancelled)));
+    assert_eq!(fs::read_to_string(board_path.join("code.py")).unwrap(), "print('hello')");
+    
+    // A version file outside the board or in an unknown format is rejected before anything is written
+    for version_file in ["../escape.py", "version.txt"] {
+        let rejected = Deployer::builder(project_path)
+            .board(BoardSelector::Path(board_path.to_path_buf()))
+            .version_file(version_file)
+            .build()
+            .run();
+        assert!(matches!(rejected, Err(CpdError::Configuration { .. })));
+    }
+    assert_eq!(fs::read_to_string(board_path.join("code.py")).unwrap(), "print('hello')");
+    assert!(!board_dir.path().parent().unwrap().join("escape.py").exists());
+}
+
+/// Deploy, back up and clean a board that only exists in memory
+#[test]
+fn test_memory_board_deploy() {
+    let project_dir = TempDir::new().unwrap();
+    let backup_dir = TempDir::new().unwrap();
+    let project_path = project_dir.path();
+    
+    fs::write(project_path.join("code.py"), "print('hello')").unwrap();
+    fs::create_dir_all(project_path.join("lib")).unwrap();
+    fs::write(project_path.join("lib/helper.py"), "def help(): pass").unwrap();
+    
+    let board = Arc::new(
+        MemoryBoard::new()
+            .with_file("boot_out.txt", "Adafruit CircuitPython 9.0.0\nBoard ID:raspberry_pi_pico\n")
+            .with_file("data/log.csv", "1,2,3"),
+    );
+    let report = Deployer::builder(project_path)
+        .board(BoardSelector::Storage(board.clone()))
+        .verify(1)
+        .version_file("version.json")
+        .build()
+        .run()
+        .unwrap();
+    
+    assert!(report.is_success());
+    assert_eq!(report.board.board_id.as_deref(), Some("raspberry_pi_pico"));
+    assert_eq!(board.file("code.py").unwrap(), b"print('hello')");
+    assert_eq!(board.file("lib/helper.py").unwrap(), b"def help(): pass");
+    assert!(board.file("version.json").is_some());
+    assert_eq!(DeployManifest::load(board.as_ref(), &|_| {}).unwrap().unwrap().files.len(), 3);
+    
+    let file_ops = FileOperations::new();
+    file_ops.backup_board(board.as_ref(), backup_dir.path()).unwrap();
+    assert_eq!(fs::read_to_string(backup_dir.path().join("data/log.csv")).unwrap(), "1,2,3");
+    assert!(backup_dir.path().join("lib/helper.py").exists());
+    
+    // Only files cpd deployed are cleaned up
+    fs::remove_file(project_path.join("lib/helper.py")).unwrap();
+    file_ops.clean_board(board.as_ref(), project_path, &|_| true).unwrap();
+    assert!(!board.exists(Path::new("lib/helper.py")));
+    assert!(board.exists(Path::new("code.py")));
+    assert!(board.exists(Path::new("data/log.csv")));
+    
+    // A full board stops the deployment
+    let full = Deployer::builder(project_path)
+        .board(BoardSelector::Storage(Arc::new(MemoryBoard::new().with_capacity(4))))
+        .build()
+        .run();
+    assert!(matches!(full, Err(CpdError::InsufficientSpace { .. })));
+}
+
+/// Test that --board values name aliases only when they aren't paths
+#[test]
+fn test_board_alias_selector() {
+    let temp_dir = TempDir::new().unwrap();
+    let project_path = temp_dir.path();
+    
+    let selector = BoardSelector::from_location(Path::new("kitchen-sensor"), project_path).unwrap();
+    assert!(matches!(selector, BoardSelector::Alias(ref name) if name == "kitchen-sensor"));
+    
+    // An existing directory of the same name is still a path
+    let board_path = project_path.join("kitchen-sensor");
+    fs::create_dir(&board_path).unwrap();
+    let selector = BoardSelector::from_location(&board_path, project_path).unwrap();
+    assert!(matches!(selector, BoardSelector::Path(_)));
+    assert!(!BoardSelector::is_alias(Path::new("/media/user/CIRCUITPY1")));
+}
+
+/// A profile including everything still never deploys cpd's own files
+#[test]
+fn test_profile_include_keeps_internal_files_off_board() {
+    let project_dir = TempDir::new().unwrap();
+    let project_path = project_dir.path();
+    
+    fs::write(project_path.join("code.py"), "print('hello')").unwrap();
+    fs::write(project_path.join("notes.tmp"), "scratch").unwrap();
+    fs::write(project_path.join("cpd.secrets.toml"), "WIFI_PASSWORD = \"hunter2\"\n").unwrap();
+    fs::write(project_path.join("cpd.toml"), "[profiles.all]\ninclude = [\"*\"]\n").unwrap();
+    
+    let board = Arc::new(MemoryBoard::new().with_file("boot_out.txt", "Board ID:raspberry_pi_pico\n"));
+    let report = Deployer::builder(project_path)
+        .board(BoardSelector::Storage(board.clone()))
+        .profile("all")
+        .build()
+        .run()
+        .unwrap();
+    
+    assert!(report.is_success());
+    assert!(board.exists(Path::new("code.py")));
+    assert!(board.exists(Path::new("notes.tmp")));
+    assert!(!board.exists(Path::new("cpd.secrets.toml")));
+    assert!(!board.exists(Path::new("cpd.toml")));
+}
+
+/// A board that refuses writes stops the deployment with the read-only exit code
+#[test]
+fn test_read_only_board_deploy() {
+    let project_dir = TempDir::new().unwrap();
+    let project_path = project_dir.path();
+    fs::write(project_path.join("code.py"), "print('hello')").unwrap();
+    fs::write(project_path.join("helper.py"), "def help(): pass").unwrap();
+    
+    let board = Arc::new(
+        MemoryBoard::new()
+            .with_file("boot_out.txt", "Board ID:raspberry_pi_pico\n")
+            .with_read_only(),
+    );
+    let error = Deployer::builder(project_path)
+        .board(BoardSelector::Storage(board.clone()))
+        .build()
+        .run()
+        .unwrap_err();
+    
+    assert!(matches!(error, CpdError::PermissionDenied { .. }));
+    assert!(!error.is_recoverable());
+    assert_eq!(error.exit_code(), exit_code::READ_ONLY_BOARD);
+    assert_eq!(board.files(), vec![PathBuf::from("boot_out.txt")]);
+}
+
+/// A deployment can be handed to a worker thread
+#[test]
+fn test_deploy_on_another_thread() {
+    let project_dir = TempDir::new().unwrap();
+    fs::write(project_dir.path().join("code.py"), "print('hello')").unwrap();
+    
+    let board = Arc::new(MemoryBoard::new().with_file("boot_out.txt", "Board ID:raspberry_pi_pico\n"));
+    let deployer = Deployer::builder(project_dir.path())
+        .board(BoardSelector::Storage(board.clone()))
+        .observer(RecordingObserver::default())
+        .confirm(|plan| !plan.files.is_empty())
+        .build();
+    
+    let report = std::thread::spawn(move || deployer.run()).join().unwrap().unwrap();
+    
+    assert!(report.is_success());
+    assert_eq!(board.file("code.py").unwrap(), b"print('hello')");
+}